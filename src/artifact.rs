@@ -0,0 +1,160 @@
+use crate::config::{Config, TypeCollision};
+use crate::error::Error;
+use crate::filter::ArtifactSkipReason;
+use std::fmt;
+
+// design-time artifact types of a package, each one is listed and downloaded the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ArtifactKind {
+    Iflow,
+    ValueMapping,
+    ScriptCollection,
+    MessageMapping,
+}
+
+impl ArtifactKind {
+    pub const ALL: [ArtifactKind; 4] = [
+        ArtifactKind::Iflow,
+        ArtifactKind::ValueMapping,
+        ArtifactKind::ScriptCollection,
+        ArtifactKind::MessageMapping,
+    ];
+
+    // OData entity set of the artifact type
+    pub fn entity_set(self) -> &'static str {
+        match self {
+            ArtifactKind::Iflow => "IntegrationDesigntimeArtifacts",
+            ArtifactKind::ValueMapping => "ValueMappingDesigntimeArtifacts",
+            ArtifactKind::ScriptCollection => "ScriptCollectionDesigntimeArtifacts",
+            ArtifactKind::MessageMapping => "MessageMappingDesigntimeArtifacts",
+        }
+    }
+
+    // key of the artifact type in per-type config objects like zip_extraction
+    pub fn config_name(self) -> &'static str {
+        match self {
+            ArtifactKind::Iflow => "iflow",
+            ArtifactKind::ValueMapping => "value_mapping",
+            ArtifactKind::ScriptCollection => "script_collection",
+            ArtifactKind::MessageMapping => "message_mapping",
+        }
+    }
+
+    // Type of the artifact in IntegrationRuntimeArtifacts
+    pub fn runtime_type(self) -> &'static str {
+        match self {
+            ArtifactKind::Iflow => "INTEGRATION_FLOW",
+            ArtifactKind::ValueMapping => "VALUE_MAPPING",
+            ArtifactKind::ScriptCollection => "SCRIPT_COLLECTION",
+            ArtifactKind::MessageMapping => "MESSAGE_MAPPING",
+        }
+    }
+
+    // path segment of the artifact page in the design-time WebUI
+    pub fn ui_page(self) -> &'static str {
+        match self {
+            ArtifactKind::Iflow => "integrationflows",
+            ArtifactKind::ValueMapping => "valuemappings",
+            ArtifactKind::ScriptCollection => "scriptcollections",
+            ArtifactKind::MessageMapping => "messagemappings",
+        }
+    }
+
+    pub fn enabled(self, config: &Config) -> bool {
+        match self {
+            ArtifactKind::Iflow => true,
+            ArtifactKind::ValueMapping => config.packages.include_value_mappings,
+            ArtifactKind::ScriptCollection => config.packages.include_script_collections,
+            ArtifactKind::MessageMapping => config.packages.include_message_mappings,
+        }
+    }
+
+    pub fn list_url(self, config: &Config, package_id: &str) -> String {
+        format!(
+            "{api}IntegrationPackages('{package_id}')/{entity_set}",
+            api = crate::check_api_url(config),
+            package_id = package_id,
+            entity_set = self.entity_set()
+        )
+    }
+
+    pub fn payload_url(self, config: &Config, artifact_id: &str) -> String {
+        self.version_payload_url(config, artifact_id, "Active")
+    }
+
+    // a saved version like "1.0.3", or "Active"
+    pub fn version_payload_url(self, config: &Config, artifact_id: &str, version: &str) -> String {
+        format!(
+            "{api}{entity_set}(Id='{artifact_id}',Version='{version}')/$value",
+            api = crate::check_api_url(config),
+            artifact_id = artifact_id,
+            version = version,
+            entity_set = self.entity_set()
+        )
+    }
+
+    // the saved versions of an artifact
+    pub fn versions_url(self, config: &Config, artifact_id: &str) -> String {
+        format!(
+            "{api}{entity_set}(Id='{artifact_id}',Version='Active')/Versions",
+            api = crate::check_api_url(config),
+            artifact_id = artifact_id,
+            entity_set = self.entity_set()
+        )
+    }
+}
+
+impl fmt::Display for ArtifactKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.entity_set())
+    }
+}
+
+// directory name of every artifact of a package, in the order given. Artifacts of different
+// types can share an id and would write into the same directory: the first one keeps the id,
+// later ones get the type as suffix (`MyId.script_collection`), are skipped or fail the package.
+// The encoded folder names are compared ignoring case, Windows and macOS file systems do.
+pub(crate) fn artifact_dirs(
+    package_id: &str,
+    artifacts: &[(ArtifactKind, &str)],
+    policy: TypeCollision,
+) -> Result<Vec<Result<String, ArtifactSkipReason>>, Error> {
+    let mut dirs = Vec::new();
+    for (index, (kind, artifact_id)) in artifacts.iter().enumerate() {
+        let dir = crate::paths::dir_name(artifact_id);
+        let owner = artifacts[..index].iter().find(|(other, other_id)| {
+            other != kind && crate::paths::dir_name(other_id).eq_ignore_ascii_case(&dir)
+        });
+        let (owner_kind, _) = match owner {
+            Some(owner) => owner,
+            None => {
+                dirs.push(Ok(dir));
+                continue;
+            }
+        };
+        log::warn!(
+            "Package {:?}: {} {:?} has the same id as {} {:?}, both would write into the same folder",
+            package_id,
+            kind.config_name(),
+            artifact_id,
+            owner_kind.config_name(),
+            artifact_id
+        );
+        dirs.push(match policy {
+            TypeCollision::Suffix => Ok(format!("{}.{}", dir, kind.config_name())),
+            TypeCollision::Skip => Err(ArtifactSkipReason::TypeCollision {
+                shared_with: owner_kind.to_string(),
+            }),
+            TypeCollision::Error => {
+                return Err(Error::Limit(format!(
+                    "Package {:?}: {} and {} share the artifact id {:?} (packages.type_collision: error)",
+                    package_id,
+                    owner_kind.config_name(),
+                    kind.config_name(),
+                    artifact_id
+                )))
+            }
+        });
+    }
+    Ok(dirs)
+}
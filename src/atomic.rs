@@ -0,0 +1,96 @@
+use crate::error::Error;
+use serde::de::DeserializeOwned;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// the files the tool writes itself: manifest, checkpoint, report, plans and the state files in
+// local_dir. They are written to a temp file in the same directory, synced and renamed over the
+// target, so a crash or a full disk leaves the old or the new content, never half a file.
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+pub(crate) fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+// the rename only survives a power loss once the directory entry is synced as well
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+pub(crate) fn write(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let temp_path = with_suffix(path, ".tmp");
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&temp_path, path)?;
+        sync_dir(path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// like write, the replaced file is kept as <name>.bak while it still reads as a T. A damaged
+// file never replaces the last good backup.
+pub(crate) fn write_with_backup<T: DeserializeOwned>(
+    path: &Path,
+    content: &[u8],
+) -> std::io::Result<()> {
+    if let Ok(current) = std::fs::read(path) {
+        if serde_json::from_slice::<T>(&current).is_ok() {
+            write(&backup_path(path), &current)?;
+        }
+    }
+    write(path, content)
+}
+
+// a file of write_with_backup, None when it doesn't exist. A file that can't be read is replaced
+// by its backup with a warning, e.g. after a disk ran full on a tool that wrote it in place.
+pub(crate) fn read_with_backup<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, Error> {
+    let error = match std::fs::read(path) {
+        Ok(content) => match serde_json::from_slice::<T>(&content) {
+            Ok(value) => return Ok(Some(value)),
+            Err(err) => err,
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let backup = backup_path(path);
+    let restored = std::fs::read(&backup)
+        .ok()
+        .and_then(|content| serde_json::from_slice::<T>(&content).ok());
+    match restored {
+        Some(value) => {
+            log::warn!(
+                "Can not read {} ({}), using the last good copy {}",
+                path.display(),
+                error,
+                backup.display()
+            );
+            Ok(Some(value))
+        }
+        None => Err(Error::StateFile {
+            path: path.to_path_buf(),
+            source: error,
+        }),
+    }
+}
@@ -0,0 +1,279 @@
+use crate::config::{Credential, CredentialInside};
+use crate::error::Error;
+use crate::identity::{resolve_identity, Identity};
+use crate::{
+    authenticate, authorization_header, check_api_url, get_password, http, AuthResult, Config,
+};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// a token is renewed this long before expires_in runs out
+const RENEW_MARGIN: Duration = Duration::from_secs(60);
+
+struct Token {
+    value: String,
+    expires_at: Option<Instant>,
+}
+
+impl Token {
+    fn new(auth_result: &AuthResult) -> Token {
+        Token {
+            value: auth_result.authorization.clone(),
+            expires_at: auth_result
+                .expires_in
+                .map(|expires_in| Instant::now() + Duration::from_secs(expires_in)),
+        }
+    }
+}
+
+struct Inner {
+    config: Config,
+    client: reqwest::Client,
+    password: String,
+    timings: Option<http::Timings>,
+    token: tokio::sync::Mutex<Token>,
+}
+
+// the authorization header value of a run, clones share the same token.
+// An OAuth token is renewed before it expires and once after a 401,
+// basic auth and API keys are used unchanged.
+#[derive(Clone)]
+pub(crate) struct Authorization {
+    inner: Arc<Inner>,
+}
+
+impl Authorization {
+    pub(crate) fn new(
+        config: &Config,
+        client: &reqwest::Client,
+        password: &str,
+        auth_result: &AuthResult,
+        timings: Option<&http::Timings>,
+    ) -> Authorization {
+        Authorization {
+            inner: Arc::new(Inner {
+                config: config.clone(),
+                client: client.clone(),
+                password: password.to_string(),
+                timings: timings.cloned(),
+                token: tokio::sync::Mutex::new(Token::new(auth_result)),
+            }),
+        }
+    }
+
+    fn is_oauth(&self) -> bool {
+        matches!(
+            self.inner.config.tenant.credential.active(),
+            CredentialInside::OauthClientCredentials(_)
+                | CredentialInside::OauthClientCertificate(_)
+                | CredentialInside::ServiceKey(_)
+        )
+    }
+
+    async fn renew(&self, token: &mut Token, reason: &str) -> Result<(), Error> {
+        let inner = &self.inner;
+        let auth_result = authenticate(
+            &inner.config,
+            &inner.client,
+            &inner.password,
+            inner.timings.as_ref(),
+        )
+        .await?;
+        match auth_result.expires_in {
+            Some(expires_in) => log::debug!(
+                "OAuth token renewed ({}), expires in {} seconds",
+                reason,
+                expires_in
+            ),
+            None => log::debug!("OAuth token renewed ({})", reason),
+        }
+        *token = Token::new(&auth_result);
+        Ok(())
+    }
+
+    // current header value, renewed first if the token is about to expire
+    pub(crate) async fn value(&self) -> Result<String, Error> {
+        let mut token = self.inner.token.lock().await;
+        let expiring = token
+            .expires_at
+            .is_some_and(|at| at.saturating_duration_since(Instant::now()) < RENEW_MARGIN);
+        if expiring && self.is_oauth() {
+            self.renew(&mut token, "token expires soon").await?;
+        }
+        Ok(token.value.clone())
+    }
+
+    // concurrent requests get the same 401, only the first one asks for a new token
+    async fn renew_rejected(&self, rejected: &str) -> Result<String, Error> {
+        let mut token = self.inner.token.lock().await;
+        if token.value == rejected {
+            self.renew(&mut token, "request rejected with 401").await?;
+        }
+        Ok(token.value.clone())
+    }
+
+    // http::fetch with the authorization header, a 401 is retried once with a new OAuth token
+    pub(crate) async fn fetch(
+        &self,
+        request: reqwest::RequestBuilder,
+        what: &str,
+        endpoint: http::Endpoint,
+        timings: Option<&http::Timings>,
+    ) -> Result<http::Fetched, Error> {
+        let config = &self.inner.config;
+        let header = authorization_header(config);
+        let retry = request.try_clone();
+        let value = self.value().await?;
+        let resp = http::fetch(
+            config,
+            request.header(header, &value),
+            what,
+            endpoint,
+            timings,
+        )
+        .await?;
+        match retry {
+            Some(retry) if resp.status == reqwest::StatusCode::UNAUTHORIZED && self.is_oauth() => {
+                let value = self.renew_rejected(&value).await?;
+                Ok(http::fetch(
+                    config,
+                    retry.header(header, &value),
+                    what,
+                    endpoint,
+                    timings,
+                )
+                .await?)
+            }
+            _ => Ok(resp),
+        }
+    }
+}
+
+// the credential a run uses, tenant.credential of `config` holds only that one
+pub(crate) struct SelectedCredential {
+    pub config: Config,
+    pub password: String,
+    pub auth_result: AuthResult,
+    pub credential_type: &'static str,
+    // position in a chain, counted from 1, None for a single credential
+    pub credential_index: Option<usize>,
+    // the API first check already passed with this credential, a chain checks every attempt
+    pub api_checked: bool,
+}
+
+impl SelectedCredential {
+    // who performs the run, with the credential of a chain that was accepted
+    pub(crate) fn identity(&self) -> Identity {
+        Identity {
+            credential_type: self.credential_type.to_string(),
+            credential_index: self.credential_index,
+            ..resolve_identity(&self.config, &self.auth_result.authorization)
+        }
+    }
+}
+
+// one attempt of a credential chain: the secret must be available without a prompt, unless
+// it's the last one, and the API first check must pass, a 401 for basic auth only shows there
+async fn try_credential(
+    config: &Config,
+    client: &reqwest::Client,
+    no_input: bool,
+    confirm_secret: bool,
+    timings: Option<&http::Timings>,
+) -> Result<SelectedCredential, Error> {
+    let password = get_password(config, no_input, confirm_secret)?;
+    let auth_result = authenticate(config, client, &password, timings).await?;
+    let api_check_url = check_api_url(config);
+    let request = client
+        .get(&api_check_url)
+        .header(authorization_header(config), &auth_result.authorization);
+    let resp = http::fetch(
+        config,
+        request,
+        &api_check_url,
+        http::Endpoint::ApiCheck,
+        timings,
+    )
+    .await?;
+    if !resp.status.is_success() {
+        return Err(Error::api_check(&api_check_url, resp.status, &resp.text()));
+    }
+    Ok(SelectedCredential {
+        config: config.clone(),
+        password,
+        auth_result,
+        credential_type: config.tenant.credential.active().type_name(),
+        credential_index: None,
+        api_checked: true,
+    })
+}
+
+// the tenant refused the credential. A connect, TLS or timeout error says nothing about it and
+// keeps its own exit code.
+fn is_rejection(err: &Error) -> bool {
+    matches!(err, Error::Auth(_))
+}
+
+// tries the credentials of tenant.credential in order and uses the first one that is accepted.
+// A single credential is only authenticated, the caller checks the API unless api_checked.
+pub(crate) async fn select_credential(
+    config: &Config,
+    client: &reqwest::Client,
+    no_input: bool,
+    confirm_secret: bool,
+    timings: Option<&http::Timings>,
+) -> Result<SelectedCredential, Error> {
+    let candidates = config.tenant.credential.candidates();
+    if candidates.len() == 1 {
+        let password = get_password(config, no_input, confirm_secret)?;
+        let auth_result = authenticate(config, client, &password, timings).await?;
+        return Ok(SelectedCredential {
+            config: config.clone(),
+            password,
+            auth_result,
+            credential_type: config.tenant.credential.active().type_name(),
+            credential_index: None,
+            api_checked: false,
+        });
+    }
+
+    let mut rejected = false;
+    let mut last_err = None;
+    for (index, candidate) in candidates.iter().enumerate() {
+        let mut attempt = config.clone();
+        attempt.tenant.credential = Credential::Single(candidate.clone());
+        let last = index + 1 == candidates.len();
+        match try_credential(&attempt, client, no_input || !last, confirm_secret, timings).await {
+            Ok(mut selected) => {
+                selected.credential_type = candidate.type_name();
+                selected.credential_index = Some(index + 1);
+                log::info!(
+                    "Using credential {} of {} ({})",
+                    index + 1,
+                    candidates.len(),
+                    candidate.type_name()
+                );
+                return Ok(selected);
+            }
+            Err(err) => {
+                log::warn!(
+                    "Credential {} of {} ({}) not accepted: {}",
+                    index + 1,
+                    candidates.len(),
+                    candidate.type_name(),
+                    err
+                );
+                rejected |= is_rejection(&err);
+                last_err = Some(err);
+            }
+        }
+    }
+    // the tenant couldn't be reached with any of them, that's not a rejected chain
+    if let (false, Some(err)) = (rejected, last_err) {
+        return Err(err);
+    }
+    Err(Error::Auth(format!(
+        "None of the {} credentials in tenant.credential was accepted",
+        candidates.len()
+    )))
+}
@@ -0,0 +1,123 @@
+use crate::error::Error;
+use crate::filter::{self, ArtifactSkip, ArtifactSkipReason};
+use crate::{ArtifactTask, PackageOutput};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+fn dir_size(dir: &Path) -> Result<u64, std::io::Error> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+// the listing has no sizes, the local copy of the previous sync is the estimate:
+// the zip, or the extracted files, which overestimates the download
+fn local_size(package_dir: &Path, artifact_dir: &str) -> Option<u64> {
+    let dir = package_dir.join(artifact_dir);
+    if dir.is_dir() {
+        return dir_size(&dir).ok();
+    }
+    fs::metadata(package_dir.join(format!("{}.zip", artifact_dir)))
+        .ok()
+        .map(|m| m.len())
+}
+
+// keeps the most recently modified artifacts that fit into the budget, the rest is deferred
+// with the skip reason budget_exceeded. Artifacts without ModifiedAt come last. Once one
+// artifact doesn't fit, all older ones are deferred too, so the next run continues with them.
+// Returns the packages with deferred artifacts.
+pub(crate) fn defer_over_budget<F>(
+    budget: u64,
+    assumed_size: u64,
+    outputs: &mut [PackageOutput<F>],
+) -> HashSet<String> {
+    let mut candidates = Vec::new();
+    for (package_index, (_, tasks, _)) in outputs.iter().enumerate() {
+        for (task_index, task) in tasks.iter().enumerate() {
+            let estimate = local_size(&task.container, &task.artifact_dir).unwrap_or(assumed_size);
+            let modified_at = task
+                .modified_at
+                .as_deref()
+                .and_then(filter::parse_modified_at);
+            candidates.push(((package_index, task_index), modified_at, estimate));
+        }
+    }
+    // newest first, the sort is stable and keeps the listing order for equal dates
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.1));
+
+    let mut used = 0;
+    let mut deferred: HashMap<(usize, usize), u64> = HashMap::new();
+    for (position, _, estimate) in candidates {
+        if deferred.is_empty() && used + estimate <= budget {
+            used += estimate;
+        } else {
+            deferred.insert(position, estimate);
+        }
+    }
+
+    let mut deferred_packages = HashSet::new();
+    let mut deferred_bytes = 0;
+    for (package_index, (package_id, tasks, skipped)) in outputs.iter_mut().enumerate() {
+        let mut kept = Vec::new();
+        for (task_index, task) in std::mem::take(tasks).into_iter().enumerate() {
+            match deferred.get(&(package_index, task_index)) {
+                Some(estimate) => {
+                    deferred_bytes += estimate;
+                    deferred_packages.insert(package_id.clone());
+                    skipped.push(ArtifactSkip {
+                        package_id: package_id.clone(),
+                        artifact_id: task.artifact_id.clone(),
+                        artifact_type: task.kind.to_string(),
+                        reason: ArtifactSkipReason::BudgetExceeded {
+                            estimated_bytes: *estimate,
+                        },
+                    });
+                }
+                None => kept.push(task),
+            }
+        }
+        *tasks = kept;
+    }
+    if !deferred.is_empty() {
+        log::warn!(
+            "Size budget {} bytes: {} artifact(s) estimated at {} bytes are synced, {} artifact(s) estimated at {} bytes are deferred to the next run",
+            budget,
+            outputs.iter().map(|(_, tasks, _)| tasks.len()).sum::<usize>(),
+            used,
+            deferred.len(),
+            deferred_bytes
+        );
+    }
+    deferred_packages
+}
+
+// a package with deferred artifacts keeps their local copies, only the artifacts downloaded
+// now are removed before the download
+pub(crate) fn clear_artifacts<F>(tasks: &[ArtifactTask<F>]) -> Result<(), Error> {
+    for task in tasks {
+        clear_artifact(&task.container, &task.artifact_dir)?;
+    }
+    Ok(())
+}
+
+// the folder and the files of one artifact, anything else in the package folder stays
+pub(crate) fn clear_artifact(package_dir: &Path, artifact_dir: &str) -> Result<(), Error> {
+    for entry in crate::prune::artifact_entries(artifact_dir) {
+        let path = package_dir.join(entry);
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else if path.is_file() {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use crate::auth::{select_credential, Authorization};
+use crate::config::CredentialInside;
+use crate::error::Error;
+use crate::identity::jwt_claims;
+use crate::{authorization_header, check_api_url, get_all_packages, http, Config, Identity};
+use serde::Serialize;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// never contains the token or the secret
+#[derive(Serialize, Debug)]
+struct CheckReport {
+    host: String,
+    identity: Identity,
+    auth_ms: u128,
+    expires_in: Option<u64>,
+    expires_at: Option<u64>,
+    scopes: Vec<String>,
+    api_check: Option<u16>,
+    package_count: Option<usize>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_secs(0))
+        .as_secs()
+}
+
+fn print_text(report: &CheckReport) {
+    println!("Authenticated as: {}", report.identity);
+    println!("Authentication time in ms: {}", report.auth_ms);
+    if let Some(expires_in) = report.expires_in {
+        println!("Token expires in seconds: {}", expires_in);
+    }
+    if !report.scopes.is_empty() {
+        println!("Scopes: {}", report.scopes.join(" "));
+    }
+    if let Some(api_check) = report.api_check {
+        println!("API First Check Response Code: {}", api_check);
+    }
+    if let Some(package_count) = report.package_count {
+        println!("Packages on tenant: {}", package_count);
+    }
+}
+
+pub async fn run_check(
+    config: &Config,
+    no_input: bool,
+    confirm_secret: bool,
+    auth_only: bool,
+    json_output: bool,
+) -> Result<(), Error> {
+    let client = http::build_client(config)?;
+
+    let start = Instant::now();
+    let selected = select_credential(config, &client, no_input, confirm_secret, None).await?;
+    let (config, password, auth_result) =
+        (&selected.config, &selected.password, &selected.auth_result);
+    let authorization = auth_result.authorization.clone();
+
+    //basic auth and API keys are only verified by a request, a token request already proves OAuth
+    let mut api_check = None;
+    let is_oauth = matches!(
+        config.tenant.credential.active(),
+        CredentialInside::OauthClientCredentials(_)
+            | CredentialInside::OauthClientCertificate(_)
+            | CredentialInside::ServiceKey(_)
+    );
+    if !auth_only || !is_oauth {
+        let request = client
+            .get(check_api_url(config))
+            .header(authorization_header(config), &authorization);
+        let resp = http::send(config, request).await?;
+        let resp_code = resp.status();
+        if !resp_code.is_success() {
+            log::error!("API First Check Failed!");
+            log::error!("API Response Code: {:#?}", resp_code);
+            let url = check_api_url(config);
+            let body = resp.text().await.unwrap_or_default();
+            return Err(Error::api_check(&url, resp_code, &body));
+        }
+        api_check = Some(resp_code.as_u16());
+    }
+    let auth_ms = start.elapsed().as_millis();
+
+    let claims = jwt_claims(&authorization);
+    let expires_at = claims
+        .as_ref()
+        .and_then(|c| c.get("exp"))
+        .and_then(|v| v.as_u64())
+        .or_else(|| auth_result.expires_in.map(|e| unix_now() + e));
+    let mut scopes: Vec<String> = match &auth_result.scope {
+        Some(scope) => scope.split_whitespace().map(|s| s.to_string()).collect(),
+        None => Vec::new(),
+    };
+    if scopes.is_empty() {
+        if let Some(claim_scopes) = claims
+            .as_ref()
+            .and_then(|c| c.get("scope"))
+            .and_then(|v| v.as_array())
+        {
+            scopes = claim_scopes
+                .iter()
+                .filter_map(|s| s.as_str())
+                .map(|s| s.to_string())
+                .collect();
+        }
+    }
+
+    let package_count = if auth_only {
+        None
+    } else {
+        let session = Authorization::new(config, &client, password, auth_result, None);
+        Some(
+            get_all_packages(config, &client, &session, None)
+                .await?
+                .d
+                .results
+                .len(),
+        )
+    };
+
+    let report = CheckReport {
+        host: config.tenant.management_host.clone(),
+        identity: selected.identity(),
+        auth_ms,
+        expires_in: auth_result.expires_in,
+        expires_at,
+        scopes,
+        api_check,
+        package_count,
+    };
+
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_text(&report);
+    }
+    Ok(())
+}
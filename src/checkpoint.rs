@@ -0,0 +1,115 @@
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+const CHECKPOINT_VERSION: u32 = 1;
+
+// progress of a sync split across runs, written after every completed package
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Checkpoint {
+    pub version: u32,
+    // fingerprint of the full package selection the checkpoint was started with
+    pub selection: String,
+    pub last_completed: Option<String>,
+    pub completed: Vec<String>,
+    pub pending: Vec<String>,
+}
+
+pub(crate) struct CheckpointFile {
+    path: PathBuf,
+    state: Checkpoint,
+}
+
+fn selection_fingerprint(package_list: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for package_id in package_list {
+        hasher.update(package_id.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl CheckpointFile {
+    // resumes from an existing checkpoint of the same selection, otherwise starts over
+    pub(crate) fn open(path: &Path, package_list: &[String]) -> Result<CheckpointFile, Error> {
+        let selection = selection_fingerprint(package_list);
+        if path.exists() {
+            let text = std::fs::read_to_string(path)?;
+            match serde_json::from_str::<Checkpoint>(&text) {
+                Ok(state) if state.version == CHECKPOINT_VERSION && state.selection == selection => {
+                    log::info!(
+                        "Resuming from checkpoint {}: {} package(s) done, {} pending",
+                        path.display(),
+                        state.completed.len(),
+                        state.pending.len()
+                    );
+                    return Ok(CheckpointFile {
+                        path: path.to_path_buf(),
+                        state,
+                    });
+                }
+                Ok(_) => log::warn!(
+                    "Checkpoint {} was created for a different package selection, filter_rules or the tenant packages changed. Starting over with all {} package(s).",
+                    path.display(),
+                    package_list.len()
+                ),
+                Err(err) => log::warn!(
+                    "Checkpoint {} can't be read ({}). Starting over with all {} package(s).",
+                    path.display(),
+                    err,
+                    package_list.len()
+                ),
+            }
+        }
+
+        let checkpoint = CheckpointFile {
+            path: path.to_path_buf(),
+            state: Checkpoint {
+                version: CHECKPOINT_VERSION,
+                selection,
+                last_completed: None,
+                completed: Vec::new(),
+                pending: package_list.to_vec(),
+            },
+        };
+        checkpoint.save()?;
+        Ok(checkpoint)
+    }
+
+    pub(crate) fn pending(&self) -> &[String] {
+        &self.state.pending
+    }
+
+    pub(crate) fn complete(&mut self, package_id: &str) -> Result<(), Error> {
+        self.state.pending.retain(|p| p != package_id);
+        self.state.completed.push(package_id.to_string());
+        self.state.last_completed = Some(package_id.to_string());
+        self.save()
+    }
+
+    // nothing left to resume once every package is done
+    pub(crate) fn finish(self) -> Result<(), Error> {
+        if self.state.pending.is_empty() && self.path.exists() {
+            std::fs::remove_file(&self.path)?;
+            log::info!(
+                "Checkpoint {} cleared, all packages are done.",
+                self.path.display()
+            );
+        }
+        Ok(())
+    }
+
+    // an interrupted write never leaves half a file, see atomic::write
+    fn save(&self) -> Result<(), Error> {
+        crate::atomic::write(
+            &self.path,
+            serde_json::to_string_pretty(&self.state)?.as_bytes(),
+        )?;
+        Ok(())
+    }
+}
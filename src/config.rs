@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use crate::artifact::ArtifactKind;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use serde::{Deserialize, Deserializer, Serialize};
 
 fn default_package_rule_operation() -> OperationEnum {
     OperationEnum::Include
@@ -7,15 +10,32 @@ fn default_extract_zip() -> ZipExtraction {
     ZipExtraction::Enabled
 }
 
+fn default_extract_zip_per_type() -> ZipExtractionPerType {
+    ZipExtractionPerType {
+        iflow: default_extract_zip(),
+        value_mapping: default_extract_zip(),
+        script_collection: default_extract_zip(),
+        message_mapping: default_extract_zip(),
+    }
+}
+
+fn default_size_budget_assumed_size() -> u64 {
+    1_000_000
+}
+
 fn default_prop_comment_removal() -> PropCommentRemoval {
     PropCommentRemoval::Disabled
 }
 
+fn default_true() -> bool {
+    true
+}
+
 fn default_packages_local_dir() -> String {
     "".to_string()
 }
 
-fn default_download_worker_count() -> usize {
+fn default_worker_count() -> usize {
     5
 }
 
@@ -27,17 +47,139 @@ pub enum OperationEnum {
     Exclude,
 }
 
+// artifacts are synced only if their ModifiedAt is inside the window, bounds are optional
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ModifiedWindow {
+    #[serde(default, deserialize_with = "crate::units::deserialize_opt_date_time")]
+    pub modified_after: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "crate::units::deserialize_opt_date_time")]
+    pub modified_before: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ModifiedMissing {
+    #[serde(rename = "include")]
+    Include,
+    #[serde(rename = "exclude")]
+    Exclude,
+}
+
+fn default_modified_missing() -> ModifiedMissing {
+    ModifiedMissing::Include
+}
+
+// the package field a regex rule is matched against
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegexField {
+    #[serde(rename = "id")]
+    Id,
+    #[serde(rename = "name")]
+    Name,
+}
+
+fn default_regex_field() -> RegexField {
+    RegexField::Id
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArtifactSingle {
+    pub id: String,
+    #[serde(default = "default_package_rule_operation")]
+    pub operation: OperationEnum,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArtifactRegex {
+    #[serde(default = "default_package_rule_operation")]
+    pub operation: OperationEnum,
+    pub pattern: String,
+}
+
+// same semantics as package rules, applied to the artifact ids of a package
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum ArtifactRuleEnum {
+    #[serde(rename = "regex")]
+    Regex(ArtifactRegex),
+    #[serde(rename = "single")]
+    Single(ArtifactSingle),
+}
+
+// an artifact of a single package rule synced at a saved version instead of 'Active'
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PinnedArtifact {
+    pub id: String,
+    pub version: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PackageSingle {
     pub id: String,
     #[serde(default = "default_package_rule_operation")]
     pub operation: OperationEnum,
+    // replaces packages.artifact_filter_rules for this package
+    pub artifact_filter_rules: Option<Vec<ArtifactRuleEnum>>,
+    #[serde(default)]
+    pub artifacts: Vec<PinnedArtifact>,
+    // replaces packages.zip_extraction for this package
+    #[serde(
+        default,
+        deserialize_with = "deserialize_opt_zip_extraction",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub zip_extraction: Option<ZipExtractionPerType>,
+    #[serde(flatten)]
+    pub modified: ModifiedWindow,
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PackageRegex {
     #[serde(default = "default_package_rule_operation")]
     pub operation: OperationEnum,
     pub pattern: String,
+    #[serde(default = "default_regex_field")]
+    pub field: RegexField,
+    #[serde(default)]
+    pub lint_ignore: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_opt_zip_extraction",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub zip_extraction: Option<ZipExtractionPerType>,
+    #[serde(flatten)]
+    pub modified: ModifiedWindow,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackageDescriptionContains {
+    #[serde(default = "default_package_rule_operation")]
+    pub operation: OperationEnum,
+    pub text: String,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    #[serde(flatten)]
+    pub modified: ModifiedWindow,
+}
+
+// matches the user id in CreatedBy/ModifiedBy of the package, exactly or as regex
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackageUser {
+    #[serde(default = "default_package_rule_operation")]
+    pub operation: OperationEnum,
+    pub user: String,
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(flatten)]
+    pub modified: ModifiedWindow,
+}
+
+// every package of the tenant, later exclude rules take packages out again
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackageAll {
+    #[serde(default = "default_package_rule_operation")]
+    pub operation: OperationEnum,
+    #[serde(flatten)]
+    pub modified: ModifiedWindow,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -47,6 +189,14 @@ pub enum PackageRuleEnum {
     Regex(PackageRegex),
     #[serde(rename = "single")]
     Single(PackageSingle),
+    #[serde(rename = "description_contains")]
+    DescriptionContains(PackageDescriptionContains),
+    #[serde(rename = "modified_by")]
+    ModifiedBy(PackageUser),
+    #[serde(rename = "created_by")]
+    CreatedBy(PackageUser),
+    #[serde(rename = "all")]
+    All(PackageAll),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -55,6 +205,108 @@ pub enum ZipExtraction {
     Disabled,
     #[serde(rename = "enabled")]
     Enabled,
+    // the zip and the extracted folder next to it
+    #[serde(rename = "both")]
+    Both,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ZipExtractionPerType {
+    #[serde(default = "default_extract_zip")]
+    pub iflow: ZipExtraction,
+    #[serde(default = "default_extract_zip")]
+    pub value_mapping: ZipExtraction,
+    #[serde(default = "default_extract_zip")]
+    pub script_collection: ZipExtraction,
+    #[serde(default = "default_extract_zip")]
+    pub message_mapping: ZipExtraction,
+}
+
+impl ZipExtractionPerType {
+    pub fn for_kind(&self, kind: ArtifactKind) -> &ZipExtraction {
+        match kind {
+            ArtifactKind::Iflow => &self.iflow,
+            ArtifactKind::ValueMapping => &self.value_mapping,
+            ArtifactKind::ScriptCollection => &self.script_collection,
+            ArtifactKind::MessageMapping => &self.message_mapping,
+        }
+    }
+}
+
+// zip_extraction accepts "enabled"/"disabled"/"both" for all artifact types, or a map per artifact type
+fn deserialize_zip_extraction<'de, D>(deserializer: D) -> Result<ZipExtractionPerType, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ZipExtractionForm {
+        All(ZipExtraction),
+        PerType(ZipExtractionPerType),
+    }
+
+    Ok(match ZipExtractionForm::deserialize(deserializer)? {
+        ZipExtractionForm::All(z) => ZipExtractionPerType {
+            iflow: z.clone(),
+            value_mapping: z.clone(),
+            script_collection: z.clone(),
+            message_mapping: z,
+        },
+        ZipExtractionForm::PerType(p) => p,
+    })
+}
+
+// zip_extraction of a filter rule, in the same forms as packages.zip_extraction
+fn deserialize_opt_zip_extraction<'de, D>(
+    deserializer: D,
+) -> Result<Option<ZipExtractionPerType>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_zip_extraction(deserializer).map(Some)
+}
+
+// what happens when artifacts of different types in a package have the same id
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeCollision {
+    #[serde(rename = "suffix")]
+    Suffix,
+    #[serde(rename = "skip")]
+    Skip,
+    #[serde(rename = "error")]
+    Error,
+}
+
+fn default_type_collision() -> TypeCollision {
+    TypeCollision::Suffix
+}
+
+// which content of an artifact is synced: the design-time version or the deployed one
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    #[serde(rename = "designtime")]
+    Designtime,
+    #[serde(rename = "deployed")]
+    Deployed,
+}
+
+fn default_source() -> Source {
+    Source::Designtime
+}
+
+// an artifact that was only ever saved as draft has no Active version to download
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DraftHandling {
+    #[serde(rename = "download")]
+    Download,
+    #[serde(rename = "skip")]
+    Skip,
+    #[serde(rename = "error")]
+    Error,
+}
+
+fn default_draft_handling() -> DraftHandling {
+    DraftHandling::Error
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -65,16 +317,101 @@ pub enum PropCommentRemoval {
     Enabled,
 }
 
+// individual content validation checks, all enabled by default
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContentChecks {
+    #[serde(default = "default_true")]
+    pub iflw_present: bool,
+    #[serde(default = "default_true")]
+    pub xml_well_formed: bool,
+    #[serde(default = "default_true")]
+    pub script_nul_bytes: bool,
+}
+
+impl Default for ContentChecks {
+    fn default() -> Self {
+        ContentChecks {
+            iflw_present: true,
+            xml_well_formed: true,
+            script_nul_bytes: true,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Packages {
-    #[serde(default = "default_extract_zip")]
-    pub zip_extraction: ZipExtraction,
+    #[serde(
+        default = "default_extract_zip_per_type",
+        deserialize_with = "deserialize_zip_extraction"
+    )]
+    pub zip_extraction: ZipExtractionPerType,
     #[serde(default = "default_prop_comment_removal")]
     pub prop_comment_removal: PropCommentRemoval,
-    #[serde(default = "default_download_worker_count")]
-    pub download_worker_count: usize,
+    // `download_worker_count` before 0.4.0
+    #[serde(default = "default_worker_count", alias = "download_worker_count")]
+    pub worker_count: usize,
     #[serde(default = "default_packages_local_dir")]
     pub local_dir: String,
+    #[serde(default, deserialize_with = "crate::layout::deserialize_opt_layout")]
+    pub layout: Option<String>,
+    #[serde(default)]
+    pub accept_any_content: bool,
+    #[serde(default)]
+    pub lint_require_anchors: bool,
+    pub max_artifacts: Option<usize>,
+    #[serde(default, deserialize_with = "crate::units::deserialize_opt_size")]
+    pub size_budget: Option<u64>,
+    #[serde(
+        default = "default_size_budget_assumed_size",
+        deserialize_with = "crate::units::deserialize_size"
+    )]
+    pub size_budget_assumed_size: u64,
+    #[serde(default)]
+    pub artifact_filter_rules: Vec<ArtifactRuleEnum>,
+    #[serde(default = "default_true")]
+    pub include_value_mappings: bool,
+    #[serde(default = "default_true")]
+    pub include_script_collections: bool,
+    #[serde(default = "default_true")]
+    pub include_message_mappings: bool,
+    #[serde(default = "default_true")]
+    pub include_configurations: bool,
+    #[serde(default = "default_true")]
+    pub include_package_metadata: bool,
+    #[serde(default)]
+    pub include_readonly_packages: bool,
+    #[serde(default)]
+    pub check_stale_active: bool,
+    #[serde(default = "default_type_collision")]
+    pub type_collision: TypeCollision,
+    #[serde(default = "default_source")]
+    pub source: Source,
+    #[serde(default = "default_draft_handling")]
+    pub draft_handling: DraftHandling,
+    #[serde(default)]
+    pub skip_empty_dirs: bool,
+    #[serde(default, deserialize_with = "crate::units::deserialize_opt_mode")]
+    pub file_mode: Option<u32>,
+    #[serde(default, deserialize_with = "crate::units::deserialize_opt_mode")]
+    pub dir_mode: Option<u32>,
+    #[serde(default)]
+    pub clear_quarantine: bool,
+    #[serde(default)]
+    pub refresh_listing_at_end: bool,
+    #[serde(default)]
+    pub prune: bool,
+    #[serde(default)]
+    pub manifest: bool,
+    #[serde(flatten)]
+    pub modified: ModifiedWindow,
+    #[serde(default = "default_modified_missing")]
+    pub modified_missing: ModifiedMissing,
+    #[serde(default)]
+    pub validate_content: bool,
+    #[serde(default)]
+    pub validate_content_strict: bool,
+    #[serde(default)]
+    pub validate_content_checks: ContentChecks,
     pub filter_rules: Vec<PackageRuleEnum>,
 }
 
@@ -82,6 +419,7 @@ pub struct Packages {
 pub struct CredentialSUser {
     pub username: String,
     pub password_environment_variable: Option<String>,
+    pub password_file: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -89,21 +427,233 @@ pub struct CredentialOauthClientCredentials {
     pub client_id: String,
     pub token_endpoint_url: String,
     pub client_secret_environment_variable: Option<String>,
+    pub client_secret_file: Option<String>,
+}
+
+// client credentials with an X.509 certificate instead of a secret: the certificate and key as PEM
+// files, or a PKCS#12 file. The token endpoint is the `certurl` of the service key.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CredentialOauthClientCertificate {
+    pub client_id: String,
+    pub token_endpoint_url: String,
+    pub certificate_path: Option<String>,
+    pub key_path: Option<String>,
+    pub pkcs12_path: Option<String>,
+    pub pkcs12_passphrase_environment_variable: Option<String>,
+}
+
+// a Process Integration Runtime service key JSON, from a file or an environment variable. It has
+// the client id, secret or certificate, token URL and the API URL for management_host.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CredentialServiceKey {
+    pub path: Option<String>,
+    pub environment_variable: Option<String>,
+}
+
+fn default_api_key_header_name() -> String {
+    "APIKey".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CredentialApiKey {
+    #[serde(default = "default_api_key_header_name")]
+    pub header_name: String,
+    pub api_key_environment_variable: Option<String>,
+    pub api_key_file: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum CredentialInside {
     #[serde(rename = "oauth_client_credentials")]
     OauthClientCredentials(CredentialOauthClientCredentials),
+    #[serde(rename = "oauth_client_certificate")]
+    OauthClientCertificate(CredentialOauthClientCertificate),
     #[serde(rename = "s_user")]
     SUser(CredentialSUser),
+    #[serde(rename = "api_key")]
+    ApiKey(CredentialApiKey),
+    #[serde(rename = "service_key")]
+    ServiceKey(CredentialServiceKey),
+}
+
+// a single credential, or several tried in order until one is accepted
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Credential {
+    Single(CredentialInside),
+    Chain(Vec<CredentialInside>),
+}
+
+impl Credential {
+    pub fn candidates(&self) -> &[CredentialInside] {
+        match self {
+            Credential::Single(c) => std::slice::from_ref(c),
+            Credential::Chain(chain) => chain,
+        }
+    }
+
+    // the credential in use, once a run picked one from a chain it is the only candidate
+    pub fn active(&self) -> &CredentialInside {
+        &self.candidates()[0]
+    }
+}
+
+impl CredentialInside {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            CredentialInside::OauthClientCredentials(_) => "oauth_client_credentials",
+            CredentialInside::OauthClientCertificate(_) => "oauth_client_certificate",
+            CredentialInside::SUser(_) => "s_user",
+            CredentialInside::ApiKey(_) => "api_key",
+            CredentialInside::ServiceKey(_) => "service_key",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum RootStore {
+    #[serde(rename = "system")]
+    System,
+    #[serde(rename = "bundled")]
+    Bundled,
+    #[serde(rename = "both")]
+    Both,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tls {
+    pub root_store: Option<RootStore>,
+    // PEM file with one or more CA certificates trusted in addition to the root store
+    pub ca_cert_path: Option<String>,
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Tenant {
+    // can be left out with a service_key credential, see service_key::resolve_service_keys
+    #[serde(default)]
     pub management_host: String,
-    pub credential: CredentialInside,
+    pub credential: Credential,
     // credential: CredentialInside,
+    #[serde(default)]
+    pub redact_identity: bool,
+    pub tls: Option<Tls>,
+    // WebUI host for `open`, defaults to management_host
+    pub ui_host: Option<String>,
+    // prefix of the API URLs instead of https://<management_host>/api/v1, e.g. for a gateway
+    #[serde(default, deserialize_with = "deserialize_opt_api_base_url")]
+    pub api_base_url: Option<String>,
+}
+
+// scheme and host are required, query and fragment would end up in the middle of every URL
+fn deserialize_opt_api_base_url<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    let url = reqwest::Url::parse(&value)
+        .map_err(|e| serde::de::Error::custom(format!("invalid api_base_url {}: {}", value, e)))?;
+    if !matches!(url.scheme(), "http" | "https")
+        || !url.has_host()
+        || url.query().is_some()
+        || url.fragment().is_some()
+    {
+        return Err(serde::de::Error::custom(format!(
+            "invalid api_base_url {}: use http(s)://host[:port][/path] without query or fragment",
+            value
+        )));
+    }
+    Ok(Some(value.trim_end_matches('/').to_string()))
+}
+
+fn default_retry_attempts() -> usize {
+    3
+}
+
+fn default_retry_base_delay() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_connect_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+// replaces HTTP_PROXY/HTTPS_PROXY, the password is only read from the environment like credentials
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Proxy {
+    pub url: String,
+    pub username: Option<String>,
+    pub password_environment_variable: Option<String>,
+}
+
+// retries of transient HTTP failures (5xx, 429, connection errors)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Http {
+    #[serde(default = "default_retry_attempts")]
+    pub retry_attempts: usize,
+    #[serde(
+        default = "default_retry_base_delay",
+        deserialize_with = "crate::units::deserialize_duration"
+    )]
+    pub retry_base_delay: Duration,
+    pub proxy: Option<Proxy>,
+    // whole request including the body, artifact payloads use download_timeout
+    #[serde(
+        default = "default_timeout",
+        deserialize_with = "crate::units::deserialize_duration"
+    )]
+    pub timeout: Duration,
+    #[serde(
+        default = "default_connect_timeout",
+        deserialize_with = "crate::units::deserialize_duration"
+    )]
+    pub connect_timeout: Duration,
+    // None: payload downloads have no limit
+    #[serde(default, deserialize_with = "crate::units::deserialize_opt_duration")]
+    pub download_timeout: Option<Duration>,
+    // None: requests are only limited by worker_count
+    pub max_requests_per_second: Option<f64>,
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Http {
+            retry_attempts: default_retry_attempts(),
+            retry_base_delay: default_retry_base_delay(),
+            proxy: None,
+            timeout: default_timeout(),
+            connect_timeout: default_connect_timeout(),
+            download_timeout: None,
+            max_requests_per_second: None,
+        }
+    }
+}
+
+fn default_git_message() -> String {
+    "cpi-sync {host} {timestamp}".to_string()
+}
+
+// commit of local_dir after a successful sync, off unless git.commit or --git-commit
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Git {
+    #[serde(default)]
+    pub commit: bool,
+    #[serde(default = "default_git_message")]
+    pub message: String,
+}
+
+impl Default for Git {
+    fn default() -> Self {
+        Git {
+            commit: false,
+            message: default_git_message(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -111,4 +661,24 @@ pub struct Config {
     pub cpisync: String,
     pub tenant: Tenant,
     pub packages: Packages,
+    #[serde(default)]
+    pub http: Http,
+    #[serde(default)]
+    pub git: Git,
+    // organization specific fields, validated with --schema but ignored by the tool
+    pub extensions: Option<serde_json::Value>,
+}
+
+// a config with the given packages object for the tests of the modules
+#[cfg(test)]
+pub(crate) fn test_config(packages: serde_json::Value) -> Config {
+    serde_json::from_value(serde_json::json!({
+        "cpisync": crate::CONFIG_VERSION,
+        "tenant": {
+            "management_host": "tenant.example.com",
+            "credential": { "s_user": { "username": "S0001" } }
+        },
+        "packages": packages
+    }))
+    .unwrap()
 }
@@ -0,0 +1,78 @@
+use crate::auth::Authorization;
+use crate::config::ZipExtraction;
+use crate::error::Error;
+use crate::{http, ArtifactKind, Config};
+use std::path::{Path, PathBuf};
+
+// externalized parameters of an integration flow as configured on the tenant, the designtime
+// zip only has their defaults. Written as configurations.json into the extracted artifact, or
+// as <artifact>.configurations.json next to the zip.
+pub(crate) const FILE_NAME: &str = "configurations.json";
+
+// container is the folder the artifact folder or zip is in, the package folder without a layout
+pub(crate) fn configurations_path(
+    config: &Config,
+    container: &Path,
+    artifact_dir: &str,
+) -> PathBuf {
+    let package_dir = container;
+    match config.packages.zip_extraction.for_kind(ArtifactKind::Iflow) {
+        ZipExtraction::Enabled | ZipExtraction::Both => {
+            package_dir.join(artifact_dir).join(FILE_NAME)
+        }
+        ZipExtraction::Disabled => package_dir.join(format!("{}.{}", artifact_dir, FILE_NAME)),
+    }
+}
+
+fn configurations_url(config: &Config, artifact_id: &str, version: &str) -> String {
+    format!(
+        "{api}{entity_set}(Id='{artifact_id}',Version='{version}')/Configurations",
+        api = crate::check_api_url(config),
+        entity_set = ArtifactKind::Iflow.entity_set(),
+        artifact_id = artifact_id,
+        version = version
+    )
+}
+
+// returns the number of parameters written, an empty result writes no file
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn download_configurations(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    timings: &http::Timings,
+    container: &Path,
+    artifact_id: &str,
+    version: &str,
+    artifact_dir: &str,
+) -> Result<usize, Error> {
+    let url = configurations_url(config, artifact_id, version);
+    let request = client.get(&url).header("Accept", "application/json");
+    let resp = authorization
+        .fetch(request, &url, http::Endpoint::Configurations, Some(timings))
+        .await?;
+    if !resp.status.is_success() {
+        log::error!("Artifact Configurations Failed!");
+        log::error!("API URL: {}", &url);
+        log::error!("API Response Code: {:#?}", &resp.status);
+        crate::logging::response_body(&resp.text());
+        return Err(Error::api_request(
+            "Artifact configurations",
+            &url,
+            resp.status,
+            &resp.text(),
+        ));
+    }
+
+    let body: serde_json::Value = serde_json::from_slice(&resp.body)?;
+    let parameters = match body["d"]["results"].as_array() {
+        Some(parameters) if !parameters.is_empty() => parameters,
+        _ => return Ok(0),
+    };
+    let path = configurations_path(config, container, artifact_dir);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(parameters)? + "\n")?;
+    Ok(parameters.len())
+}
@@ -0,0 +1,310 @@
+use crate::error::Error;
+use crate::stale::compare_versions;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::fmt;
+
+// the config format in the `cpisync` field. Renamed fields are mapped by DEPRECATIONS within a
+// format; the 0.1.0 package rules can't be mapped, they changed meaning in 0.2.0.
+pub const CONFIG_VERSION: &str = "0.2.0";
+
+// what changed in the config format, old names keep working until the entry is removed
+enum Change {
+    // a field got a new name, the value is kept
+    Field {
+        old: &'static str,
+        new: &'static str,
+    },
+    // a string value of a field got a new spelling, no value is deprecated yet
+    #[allow(dead_code)]
+    Value {
+        field: &'static str,
+        old: &'static str,
+        new: &'static str,
+    },
+}
+
+struct DeprecationRule {
+    // object path of the field, "*" stands for every element of an array
+    parent: &'static [&'static str],
+    change: Change,
+    since: &'static str,
+}
+
+// one entry per deprecation, removed again with the next breaking release
+const DEPRECATIONS: &[DeprecationRule] = &[
+    DeprecationRule {
+        parent: &["packages"],
+        change: Change::Field {
+            old: "download_worker_count",
+            new: "worker_count",
+        },
+        since: "0.4.0",
+    },
+    DeprecationRule {
+        parent: &["tenants", "*", "packages"],
+        change: Change::Field {
+            old: "download_worker_count",
+            new: "worker_count",
+        },
+        since: "0.4.0",
+    },
+];
+
+#[derive(Debug, Clone)]
+pub struct Deprecation {
+    // location of the deprecated field, e.g. "packages.download_worker_count"
+    pub location: String,
+    pub since: &'static str,
+    // JSON snippet to use instead
+    pub replacement: String,
+    // both the old and the new form are set, the old one is ignored
+    pub conflict: bool,
+}
+
+impl fmt::Display for Deprecation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.conflict {
+            write!(
+                f,
+                "{} is deprecated since {} and ignored because {} is set, remove it",
+                self.location, self.since, self.replacement
+            )
+        } else {
+            write!(
+                f,
+                "{} is deprecated since {}, replace it with {}",
+                self.location, self.since, self.replacement
+            )
+        }
+    }
+}
+
+fn snippet(key: &str, value: &Value) -> String {
+    format!("\"{}\": {}", key, value)
+}
+
+// renames the key in place so the key order of the file is kept
+fn rename_key(object: &mut Map<String, Value>, old: &str, new: &str) {
+    let entries = std::mem::take(object);
+    for (key, value) in entries {
+        if key == old {
+            object.insert(new.to_string(), value);
+        } else {
+            object.insert(key, value);
+        }
+    }
+}
+
+fn apply_rule(
+    object: &mut Map<String, Value>,
+    location: &str,
+    rule: &DeprecationRule,
+    found: &mut Vec<Deprecation>,
+) {
+    match rule.change {
+        Change::Field { old, new } => {
+            let value = match object.get(old) {
+                Some(value) => value.clone(),
+                None => return,
+            };
+            let conflict = object.contains_key(new);
+            if conflict {
+                object.remove(old);
+            } else {
+                rename_key(object, old, new);
+            }
+            found.push(Deprecation {
+                location: format!("{}{}", location, old),
+                since: rule.since,
+                replacement: snippet(new, object.get(new).unwrap_or(&value)),
+                conflict,
+            });
+        }
+        Change::Value { field, old, new } => {
+            if object.get(field).and_then(|v| v.as_str()) == Some(old) {
+                object.insert(field.to_string(), Value::String(new.to_string()));
+                found.push(Deprecation {
+                    location: format!("{}{}", location, field),
+                    since: rule.since,
+                    replacement: snippet(field, &Value::String(new.to_string())),
+                    conflict: false,
+                });
+            }
+        }
+    }
+}
+
+fn walk(
+    value: &mut Value,
+    path: &[&str],
+    location: String,
+    rule: &DeprecationRule,
+    found: &mut Vec<Deprecation>,
+) {
+    match (path.first(), value) {
+        (None, Value::Object(object)) => apply_rule(object, &location, rule, found),
+        (Some(&"*"), Value::Array(items)) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                let location = format!("{}[{}].", location.trim_end_matches('.'), index);
+                walk(item, &path[1..], location, rule, found);
+            }
+        }
+        (Some(key), Value::Object(object)) => {
+            if let Some(child) = object.get_mut(*key) {
+                let location = format!("{}{}.", location, key);
+                walk(child, &path[1..], location, rule, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+// runs before deprecations and schema validation, which would only say that the value differs
+pub fn check_config_version(config_json: &Value) -> Result<(), Error> {
+    let version = match config_json.get("cpisync") {
+        Some(Value::String(version)) => version.trim(),
+        Some(other) => {
+            return Err(Error::ConfigValidation(format!(
+                "Config field cpisync must be a version string like \"{}\", found {}",
+                CONFIG_VERSION, other
+            )))
+        }
+        None => {
+            return Err(Error::ConfigValidation(format!(
+                "Config has no cpisync version field, add \"cpisync\": \"{}\"",
+                CONFIG_VERSION
+            )))
+        }
+    };
+    match compare_versions(version, CONFIG_VERSION) {
+        Ordering::Equal => Ok(()),
+        Ordering::Less => Err(Error::ConfigValidation(format!(
+            "Config version {} is not supported, cpisync {} reads config version {}. \
+             Package rules changed in 0.2.0 (type regex or single with include/exclude \
+             operation, local_dir for all packages), update filter_rules and set \"cpisync\": \"{}\"",
+            version,
+            env!("CARGO_PKG_VERSION"),
+            CONFIG_VERSION,
+            CONFIG_VERSION
+        ))),
+        Ordering::Greater => Err(Error::ConfigValidation(format!(
+            "Config version {} is newer than cpisync {} supports (config version {}), \
+             update cpisync",
+            version,
+            env!("CARGO_PKG_VERSION"),
+            CONFIG_VERSION
+        ))),
+    }
+}
+
+// maps deprecated fields and values of a raw config to the current form,
+// runs before schema validation so the rest of the tool only sees current names
+pub fn apply_deprecations(config_json: &mut Value) -> Vec<Deprecation> {
+    let mut found = Vec::new();
+    for rule in DEPRECATIONS {
+        walk(config_json, rule.parent, String::new(), rule, &mut found);
+    }
+    found
+}
+
+// textual edit of a key or value that occurs exactly once, keeps the formatting of the file
+fn replace_once(text: &str, from: &str, to: &str) -> Option<String> {
+    if text.matches(from).count() == 1 {
+        Some(text.replacen(from, to, 1))
+    } else {
+        None
+    }
+}
+
+fn rewrite_text(config_path: &str, config_text: &str) -> Option<String> {
+    let yaml = crate::discovery::is_yaml_path(config_path);
+    // JSON keys are quoted, YAML keys usually aren't
+    let key = |name: &str| {
+        if yaml {
+            format!("{}:", name)
+        } else {
+            format!("\"{}\"", name)
+        }
+    };
+    let value = |name: &str| {
+        if yaml {
+            name.to_string()
+        } else {
+            format!("\"{}\"", name)
+        }
+    };
+
+    let mut text = config_text.to_string();
+    for rule in DEPRECATIONS {
+        let mut probe = crate::discovery::parse_config_text(config_path, &text).ok()?;
+        let mut found = Vec::new();
+        walk(&mut probe, rule.parent, String::new(), rule, &mut found);
+        match (found.len(), &rule.change) {
+            (0, _) => continue,
+            (1, Change::Field { old, new }) if !found[0].conflict => {
+                text = replace_once(&text, &key(old), &key(new))?;
+            }
+            (1, Change::Value { old, new, .. }) => {
+                text = replace_once(&text, &value(old), &value(new))?;
+            }
+            _ => return None,
+        }
+    }
+    Some(text)
+}
+
+// config file content with all deprecations applied, plus what was changed
+pub fn migrate_config_text(
+    config_path: &str,
+    config_text: &str,
+) -> Result<(String, Vec<Deprecation>), Error> {
+    let mut config_json = crate::discovery::parse_config_text(config_path, config_text)?;
+    check_config_version(&config_json)?;
+    let found = apply_deprecations(&mut config_json);
+    if found.is_empty() {
+        return Ok((config_text.to_string(), found));
+    }
+
+    // the textual edit is only used if it gives exactly the same config, otherwise reformat
+    let textual = rewrite_text(config_path, config_text).filter(|text| {
+        crate::discovery::parse_config_text(config_path, text)
+            .ok()
+            .as_ref()
+            == Some(&config_json)
+    });
+    let migrated = match textual {
+        Some(text) => text,
+        None if crate::discovery::is_yaml_path(config_path) => serde_yaml::to_string(&config_json)?,
+        None => {
+            let mut text = serde_json::to_string_pretty(&config_json)?;
+            text.push('\n');
+            text
+        }
+    };
+    Ok((migrated, found))
+}
+
+pub fn run_migrate_config(config_path: &str, write: bool) -> Result<(), Error> {
+    let config_text = std::fs::read_to_string(config_path)?;
+    let (migrated, found) = migrate_config_text(config_path, &config_text)?;
+    if found.is_empty() {
+        log::info!("No deprecated config fields found in {}", config_path);
+        return Ok(());
+    }
+    for deprecation in &found {
+        log::info!("Config deprecation: {}", deprecation);
+    }
+    if write {
+        std::fs::write(config_path, migrated)?;
+        log::info!("Updated {}", config_path);
+    } else {
+        println!("Migrated config:");
+        println!("{}", migrated.trim_end());
+        log::info!(
+            "Run `cpisync migrate-config --write` to update {}",
+            config_path
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,297 @@
+use crate::error::Error;
+use crate::runtime::{self, DeployStatus};
+use crate::stale::list_artifacts;
+use crate::{artifact, get_authorization, http, managed, manifest, paths, ArtifactKind, Config};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+// a difference between the selected content of the tenant and local_dir
+enum Change {
+    Added {
+        package_id: String,
+        kind: ArtifactKind,
+        artifact_id: String,
+        version: Option<String>,
+        // with --with-deploy-status
+        deploy_status: Option<DeployStatus>,
+    },
+    Changed {
+        package_id: String,
+        kind: ArtifactKind,
+        artifact_id: String,
+        local: String,
+        tenant: String,
+        deploy_status: Option<DeployStatus>,
+    },
+    // a folder or zip of a selected package the tenant no longer lists
+    Deleted {
+        package_id: String,
+        name: String,
+    },
+    // a package folder of an earlier sync that isn't selected or gone from the tenant
+    PackageRemoved {
+        package_id: String,
+    },
+}
+
+// artifact folders and zips of a package folder by name, true for a zip
+fn local_artifacts(package_dir: &Path) -> Result<BTreeMap<String, bool>, Error> {
+    let mut artifacts = BTreeMap::new();
+    if !package_dir.is_dir() {
+        return Ok(artifacts);
+    }
+    for entry in std::fs::read_dir(package_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() {
+            artifacts.insert(name, false);
+        } else if let Some(stem) = name.strip_suffix(".zip") {
+            //zip_extraction "both" writes a folder next to the zip, the folder is compared
+            artifacts.entry(stem.to_string()).or_insert(true);
+        }
+    }
+    Ok(artifacts)
+}
+
+fn bundle_version(manifest: &str) -> Option<String> {
+    manifest
+        .lines()
+        .find_map(|line| line.strip_prefix("Bundle-Version:"))
+        .map(|version| version.trim().to_string())
+}
+
+// Bundle-Version of META-INF/MANIFEST.MF in the folder or zip, None if it can't be read
+fn local_bundle_version(path: &Path, zip: bool) -> Option<String> {
+    let mut content = String::new();
+    match zip {
+        true => {
+            let file = std::fs::File::open(path).ok()?;
+            let mut archive = zip::ZipArchive::new(file).ok()?;
+            let mut entry = archive.by_name("META-INF/MANIFEST.MF").ok()?;
+            entry.read_to_string(&mut content).ok()?;
+        }
+        false => {
+            content = std::fs::read_to_string(path.join("META-INF").join("MANIFEST.MF")).ok()?;
+        }
+    }
+    bundle_version(&content)
+}
+
+// package folders of earlier syncs, like the mirror hash sees them
+pub(crate) fn local_packages(data_dir: &Path) -> Result<Vec<String>, Error> {
+    let mut names = Vec::new();
+    if !data_dir.is_dir() {
+        return Ok(names);
+    }
+    let managed = managed::ManagedPaths::read(data_dir)?;
+    for entry in std::fs::read_dir(data_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() && !name.starts_with('.') && managed.includes(&name) {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn status_column(deploy_status: &Option<DeployStatus>) -> String {
+    match deploy_status {
+        Some(status) => format!("\t{}", status),
+        None => String::new(),
+    }
+}
+
+fn print_change(change: &Change) {
+    match change {
+        Change::Added {
+            package_id,
+            kind,
+            artifact_id,
+            version,
+            deploy_status,
+        } => println!(
+            "added\t{}\t{}\t{}\t{}{}",
+            package_id,
+            kind,
+            artifact_id,
+            version.as_deref().unwrap_or("-"),
+            status_column(deploy_status)
+        ),
+        Change::Changed {
+            package_id,
+            kind,
+            artifact_id,
+            local,
+            tenant,
+            deploy_status,
+        } => println!(
+            "changed\t{}\t{}\t{}\t{} -> {}{}",
+            package_id,
+            kind,
+            artifact_id,
+            local,
+            tenant,
+            status_column(deploy_status)
+        ),
+        Change::Deleted { package_id, name } => {
+            println!("deleted\t{}\t-\t{}\t-", package_id, name)
+        }
+        Change::PackageRemoved { package_id } => println!("removed_package\t{}", package_id),
+    }
+}
+
+// `diff` lists the tenant like `list` and compares it with local_dir, nothing is written.
+// Local versions come from manifest.json or the Bundle-Version of the artifact.
+pub async fn run_diff(
+    config: &Config,
+    config_path: &str,
+    no_input: bool,
+    confirm_secret: bool,
+    with_deploy_status: bool,
+) -> Result<(), Error> {
+    let client = http::build_client(config)?;
+    let (config, authorization) =
+        get_authorization(config, &client, no_input, confirm_secret).await?;
+    let config = &config;
+    let (selected_packages, artifacts) =
+        list_artifacts(config, &client, &authorization, false).await?;
+    let data_dir = crate::local_data_dir(config, config_path);
+    let recorded = manifest::recorded_versions(&data_dir)?;
+    let runtime_artifacts = match with_deploy_status {
+        true => Some(
+            runtime::list_runtime_artifacts(
+                config,
+                &client,
+                &authorization,
+                &http::Timings::default(),
+            )
+            .await?,
+        ),
+        false => None,
+    };
+    let deploy_status = |kind, artifact_id: &str, version: &Option<String>| {
+        runtime_artifacts.as_ref().map(|runtime_artifacts| {
+            runtime::deploy_status(runtime_artifacts, kind, artifact_id, version.as_deref())
+        })
+    };
+    let runtime_only = match &runtime_artifacts {
+        Some(runtime_artifacts) => runtime::runtime_only(runtime_artifacts, &artifacts),
+        None => Vec::new(),
+    };
+
+    let mut tenant: BTreeMap<String, Vec<(ArtifactKind, String, Option<String>)>> =
+        selected_packages
+            .into_iter()
+            .map(|package_id| (package_id, Vec::new()))
+            .collect();
+    for (package_id, kind, artifact_id, version) in artifacts {
+        tenant
+            .entry(package_id)
+            .or_default()
+            .push((kind, artifact_id, version));
+    }
+
+    let mut changes = Vec::new();
+    let mut unknown_versions = 0;
+    for (package_id, artifacts) in tenant.iter() {
+        let package_dir_name = paths::dir_name(package_id);
+        let package_dir = paths::package_dir(&data_dir, package_id);
+        let mut local = local_artifacts(&package_dir)?;
+        let ids: Vec<(ArtifactKind, &str)> = artifacts
+            .iter()
+            .map(|(kind, artifact_id, _)| (*kind, artifact_id.as_str()))
+            .collect();
+        let dirs = artifact::artifact_dirs(package_id, &ids, config.packages.type_collision)?;
+        let mut synced_dirs = Vec::new();
+        for ((kind, artifact_id, version), dir) in artifacts.iter().zip(dirs) {
+            //skipped by type_collision, a sync doesn't write it either
+            let dir = match dir {
+                Ok(dir) => dir,
+                Err(_) => continue,
+            };
+            synced_dirs.push(format!("{}_", dir));
+            let zip = match local.remove(&dir) {
+                Some(zip) => zip,
+                None => {
+                    changes.push(Change::Added {
+                        package_id: package_id.clone(),
+                        kind: *kind,
+                        artifact_id: artifact_id.clone(),
+                        version: version.clone(),
+                        deploy_status: deploy_status(*kind, artifact_id, version),
+                    });
+                    continue;
+                }
+            };
+            let file_name = match zip {
+                true => format!("{}.zip", dir),
+                false => dir.clone(),
+            };
+            let local_version = recorded
+                .get(&format!("{}/{}", package_dir_name, file_name))
+                .cloned()
+                .or_else(|| local_bundle_version(&package_dir.join(&file_name), zip));
+            match (local_version, version) {
+                (Some(local), Some(tenant)) if &local != tenant => changes.push(Change::Changed {
+                    package_id: package_id.clone(),
+                    kind: *kind,
+                    artifact_id: artifact_id.clone(),
+                    local,
+                    tenant: tenant.clone(),
+                    deploy_status: deploy_status(*kind, artifact_id, version),
+                }),
+                (None, _) => unknown_versions += 1,
+                _ => {}
+            }
+        }
+        //folders of pinned versions belong to their artifact
+        local.retain(|name, _| !synced_dirs.iter().any(|dir| name.starts_with(dir.as_str())));
+        for name in local.into_keys() {
+            changes.push(Change::Deleted {
+                package_id: package_id.clone(),
+                name: paths::id_from_dir_name(&name),
+            });
+        }
+    }
+    let selected: HashSet<String> = tenant.keys().map(|id| paths::dir_name(id)).collect();
+    for name in local_packages(&data_dir)? {
+        if !selected.contains(&name) {
+            changes.push(Change::PackageRemoved {
+                package_id: paths::id_from_dir_name(&name),
+            });
+        }
+    }
+
+    for change in changes.iter() {
+        print_change(change);
+    }
+    //not a difference to local_dir, a sync doesn't write them
+    for deployed in runtime_only.iter() {
+        println!(
+            "runtime_only\t-\t{}\t{}\t{}",
+            deployed.artifact_type.as_deref().unwrap_or("-"),
+            deployed.id,
+            deployed.version.as_deref().unwrap_or("-")
+        );
+    }
+    if unknown_versions > 0 {
+        log::info!(
+            "{} local artifact(s) have no readable version, only their presence was compared",
+            unknown_versions
+        );
+    }
+    println!(
+        "Diff: {} package(s) compared, {} difference(s)",
+        tenant.len(),
+        changes.len()
+    );
+    if !changes.is_empty() {
+        return Err(Error::Differences {
+            count: changes.len(),
+            local_dir: data_dir.display().to_string(),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use crate::error::Error;
+use serde_json::Value;
+use std::env;
+use std::path::{Path, PathBuf};
+
+const LOCAL_CONFIG_JSON: &str = "./cpi-sync.json";
+const LOCAL_CONFIG_YAML: &str = "./cpi-sync.yaml";
+
+// written by `init`, replace the host and credential before the first sync
+const CONFIG_TEMPLATE: &str = r#"{
+  "cpisync": "0.2.0",
+  "tenant": {
+    "management_host": "change-tmn.hci.eu1change.hana.ondemand.com",
+    "credential": {
+      "s_user": {
+        "username": "S000change",
+        "password_environment_variable": "CPI_PASSWORD"
+      }
+    }
+  },
+  "packages": {
+    "filter_rules": [{ "type": "all" }]
+  }
+}
+"#;
+
+// per-user config: %APPDATA%\cpi-sync\config.json on Windows,
+// $XDG_CONFIG_HOME/cpi-sync/config.json (default ~/.config) elsewhere
+pub fn global_config_path() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join("cpi-sync").join("config.json"))
+}
+
+// the discovery chain used when --config is not given, in order
+pub fn config_search_paths() -> Vec<PathBuf> {
+    let mut paths = vec![
+        PathBuf::from(LOCAL_CONFIG_JSON),
+        PathBuf::from(LOCAL_CONFIG_YAML),
+    ];
+    paths.extend(global_config_path());
+    paths
+}
+
+// an explicit path is used as is and never falls back
+pub fn discover_config(explicit: Option<&str>) -> Result<String, Error> {
+    if let Some(path) = explicit {
+        return Ok(path.to_string());
+    }
+    let paths = config_search_paths();
+    match paths.iter().find(|p| p.is_file()) {
+        Some(path) => Ok(path.to_string_lossy().into_owned()),
+        None => {
+            let searched: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+            Err(Error::ConfigValidation(format!(
+                "No config file found, searched: {}. Use --config or `cpisync init`",
+                searched.join(", ")
+            )))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Yaml,
+}
+
+// the extension decides the parser, files without one are JSON like before
+pub fn config_format(path: &str) -> Result<ConfigFormat, Error> {
+    let extension = Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        None | Some("json") => Ok(ConfigFormat::Json),
+        Some("yaml") | Some("yml") => Ok(ConfigFormat::Yaml),
+        Some(other) => Err(Error::ConfigValidation(format!(
+            "Unknown config file extension '.{}' of {}, use .json, .yaml or .yml",
+            other, path
+        ))),
+    }
+}
+
+pub fn is_yaml_path(path: &str) -> bool {
+    matches!(config_format(path), Ok(ConfigFormat::Yaml))
+}
+
+// JSON configs may contain comments and trailing commas,
+// YAML configs are read into the same JSON value, schema validation and deprecations apply unchanged
+pub fn parse_config_text(path: &str, text: &str) -> Result<Value, Error> {
+    match config_format(path)? {
+        ConfigFormat::Json => serde_json::from_str(&crate::jsonc::to_json(text))
+            .map_err(|e| Error::ConfigValidation(format!("Invalid JSON in {}: {}", path, e))),
+        ConfigFormat::Yaml => serde_yaml::from_str(text)
+            .map_err(|e| Error::ConfigValidation(format!("Invalid YAML in {}: {}", path, e))),
+    }
+}
+
+// the config of the init questions, or the template with --no-input
+pub fn run_init(path: &Path, content: Option<&Value>, force: bool) -> Result<(), Error> {
+    if path.exists() && !force {
+        return Err(Error::ConfigValidation(format!(
+            "Config file already exists: {}, use --force to overwrite it",
+            path.display()
+        )));
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match content {
+        Some(content) => {
+            std::fs::write(path, serde_json::to_string_pretty(content)? + "\n")?;
+            log::info!("Created {}", path.display());
+        }
+        None => {
+            std::fs::write(path, CONFIG_TEMPLATE)?;
+            log::info!("Created {}", path.display());
+            log::info!("Set management_host and the credential before the first sync.");
+        }
+    }
+    Ok(())
+}
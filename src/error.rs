@@ -0,0 +1,156 @@
+use crate::exit::ExitCode;
+use crate::Cancelled;
+
+// longest part of a response body kept in Error::ApiRequest
+const BODY_SNIPPET_CHARS: usize = 200;
+
+// part of a payload that isn't a zip in the error, enough to recognize a gateway's error page
+pub(crate) const NOT_A_ZIP_BODY_CHARS: usize = 500;
+
+// the start of a response body on one line, secrets it echoes are redacted before it's cut
+pub(crate) fn body_prefix(body: &str, chars: usize) -> String {
+    let collapsed = body.split_whitespace().collect::<Vec<_>>().join(" ");
+    crate::logging::redact(&collapsed)
+        .chars()
+        .take(chars)
+        .collect()
+}
+
+// the failures an embedder can tell apart, the library functions return them and
+// ExitCode::of tells the exit code from the variant
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    // the config file doesn't match the schema, an option or a combination of command line
+    // options is invalid, or a credential or certificate named by the config can't be used
+    #[error("{0}")]
+    ConfigValidation(String),
+    // no secret, or the credential was rejected
+    #[error("{0}")]
+    Auth(String),
+    // the API answered with an error status. The body isn't part of the message, it can echo
+    // secrets and is only logged with --show-response-body.
+    #[error("{what} failed: HTTP {status} from {url}")]
+    ApiRequest {
+        what: String,
+        url: String,
+        status: u16,
+        body_snippet: String,
+    },
+    // a success status with a body that can't be read
+    #[error("{what} failed: unexpected response from {url}: {reason}")]
+    ApiResponse {
+        what: String,
+        url: String,
+        reason: String,
+    },
+    // a downloaded artifact that can't be extracted
+    #[error("Artifact {artifact}: {reason}")]
+    Zip { artifact: String, reason: String },
+    // a state file in local_dir that can't be read and has no readable backup
+    #[error("Can not read {}: {source}", .path.display())]
+    StateFile {
+        path: std::path::PathBuf,
+        source: serde_json::Error,
+    },
+    // a `single` filter rule names a package the tenant doesn't have
+    #[error("Package ID not found: {0}")]
+    PackageNotFound(String),
+    // an artifact download failed and --ignore-error-download wasn't given, the failure is
+    // the source
+    #[error("{0}")]
+    Download(#[source] Box<Error>),
+    // `diff` found differences between the tenant and local_dir
+    #[error("{count} difference(s) between the tenant and {local_dir}")]
+    Differences { count: usize, local_dir: String },
+    // the CancellationToken stopped the sync, with the stats up to then
+    #[error(transparent)]
+    Cancelled(Box<Cancelled>),
+    // the interactive package selection was left without selecting
+    #[error("Package selection cancelled")]
+    SelectionCancelled,
+    // the run stopped at packages.max_artifacts or at a packages option set to error, like
+    // type_collision or draft_handling
+    #[error("{0}")]
+    Limit(String),
+    // nothing to work on: no package selected, or an id that matches none or several
+    #[error("{0}")]
+    Selection(String),
+    // a folder or file the command reads isn't there, like manifest.json before a sync
+    #[error("{0}")]
+    NotFound(String),
+    // a plan file that doesn't belong to the operation, the config or the current files
+    #[error("{0}")]
+    Plan(String),
+    // git can't be run or a git command failed
+    #[error("{0}")]
+    Git(String),
+    // downloaded content or the mirror failed a check: content rules, verify
+    #[error("{0}")]
+    Validation(String),
+    // steps of a command failed, each failure was logged when it happened
+    #[error("{0}")]
+    Failed(String),
+    // tenants of a multi-tenant run failed, exit_code is the highest of their codes
+    #[error("{} of {} tenants failed: {}", .failed.len(), .total, .failed.join(", "))]
+    Tenants {
+        failed: Vec<String>,
+        total: usize,
+        exit_code: ExitCode,
+    },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    // a request that got no response: connect, TLS, timeout or a body that broke off
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Xml(#[from] quick_xml::Error),
+    // a zip the tool writes or reads itself, an artifact that doesn't extract is Zip
+    #[error(transparent)]
+    Archive(#[from] zip::result::ZipError),
+    // the terminal of the interactive package selection
+    #[error(transparent)]
+    Terminal(#[from] crossterm::ErrorKind),
+}
+
+impl From<Cancelled> for Error {
+    fn from(cancelled: Cancelled) -> Error {
+        Error::Cancelled(Box::new(cancelled))
+    }
+}
+
+impl Error {
+    pub(crate) fn api_request(
+        what: &str,
+        url: &str,
+        status: reqwest::StatusCode,
+        body: &str,
+    ) -> Error {
+        Error::ApiRequest {
+            what: what.to_string(),
+            url: url.to_string(),
+            status: status.as_u16(),
+            body_snippet: body_prefix(body, BODY_SNIPPET_CHARS),
+        }
+    }
+
+    // the API first check answered with an error status, 401 and 403 reject the credential
+    pub(crate) fn api_check(url: &str, status: reqwest::StatusCode, body: &str) -> Error {
+        let err = Error::api_request("API first check", url, status, body);
+        match status.as_u16() {
+            401 | 403 => Error::Auth(err.to_string()),
+            _ => err,
+        }
+    }
+
+    pub(crate) fn api_response(what: &str, url: &str, reason: impl ToString) -> Error {
+        Error::ApiResponse {
+            what: what.to_string(),
+            url: url.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
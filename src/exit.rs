@@ -0,0 +1,42 @@
+use crate::error::Error;
+
+// exit codes of the command line tool. Scripts depend on them: a code keeps its meaning
+// across releases and a new kind of failure gets a new number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    // any failure without a code of its own
+    Failure = 1,
+    // config file, schema, lint or command line option error
+    Config = 2,
+    // credential, token request or API first check rejected
+    Authentication = 3,
+    // a `single` filter rule names a package the tenant doesn't have
+    PackageNotFound = 4,
+    // an artifact download failed and --ignore-error-download wasn't given
+    Download = 5,
+    // `diff` found differences between the tenant and local_dir
+    Differences = 6,
+    // cancelled with Ctrl+C, same code as the second Ctrl+C
+    Cancelled = 130,
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    // the code an error of the library ends the tool with
+    pub fn of(err: &Error) -> ExitCode {
+        match err {
+            Error::ConfigValidation(_) => ExitCode::Config,
+            Error::Auth(_) => ExitCode::Authentication,
+            Error::PackageNotFound(_) => ExitCode::PackageNotFound,
+            Error::Download(_) => ExitCode::Download,
+            Error::Differences { .. } => ExitCode::Differences,
+            Error::Cancelled(_) | Error::SelectionCancelled => ExitCode::Cancelled,
+            Error::Tenants { exit_code, .. } => *exit_code,
+            _ => ExitCode::Failure,
+        }
+    }
+}
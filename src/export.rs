@@ -0,0 +1,119 @@
+use crate::error::Error;
+use crate::transport::{build_transport, TransportPackage};
+use crate::{
+    check_api_url, filter, get_all_packages, get_authorization, http, is_zip_content, Config,
+};
+use std::path::Path;
+
+// the description is written next to the archive: transport.zip -> transport.json
+fn description_path(out: &Path) -> std::path::PathBuf {
+    out.with_extension("json")
+}
+
+pub async fn run_export_transport(
+    config: &Config,
+    no_input: bool,
+    confirm_secret: bool,
+    out: &Path,
+    mta_id: &str,
+    mta_version: &str,
+    allow_partial: bool,
+) -> Result<(), Error> {
+    let client = http::build_client(config)?;
+    let (config, authorization) =
+        get_authorization(config, &client, no_input, confirm_secret).await?;
+    let config = &config;
+
+    // packages are selected with the same filter rules as a sync, artifact rules don't apply
+    let api_package_list = get_all_packages(config, &client, &authorization, None).await?;
+    let package_decisions = filter::evaluate_filter_rules(config, &api_package_list.d.results)?;
+    let mut selected: Vec<_> = api_package_list
+        .d
+        .results
+        .iter()
+        .filter(|p| {
+            package_decisions
+                .iter()
+                .any(|d| d.selected && d.package_id == p.id)
+        })
+        .collect();
+    selected.sort_by(|a, b| a.id.cmp(&b.id));
+    if selected.is_empty() {
+        return Err(Error::Selection(
+            "No package selected, nothing to export".to_string(),
+        ));
+    }
+
+    let mut packages = Vec::new();
+    let mut failed = Vec::new();
+    for package in selected {
+        let url = format!(
+            "{api}IntegrationPackages('{id}')/$value",
+            api = check_api_url(config),
+            id = package.id
+        );
+        let request = client.get(&url);
+        let resp = authorization
+            .fetch(request, &url, http::Endpoint::PackageExport, None)
+            .await?;
+        // packages with content from the discover section can't be exported
+        if !resp.status.is_success() || !is_zip_content(&resp.body) {
+            log::error!("- Package: {:#?} export failed", package.id);
+            log::error!("API URL: {}", &url);
+            log::error!("API Response Code: {:#?}", &resp.status);
+            if !resp.status.is_success() {
+                crate::logging::response_body(&resp.text());
+            }
+            failed.push(package.id.clone());
+            continue;
+        }
+        log::info!(
+            "- Package: {:#?} exported ({} bytes)",
+            package.id,
+            resp.body.len()
+        );
+        packages.push(TransportPackage {
+            id: package.id.clone(),
+            name: package.name.clone(),
+            version: package.version.clone(),
+            content: resp.body.to_vec(),
+        });
+    }
+
+    if !failed.is_empty() {
+        log::warn!(
+            "{} of {} package(s) failed to export: {}",
+            failed.len(),
+            failed.len() + packages.len(),
+            failed.join(", ")
+        );
+        if !allow_partial {
+            return Err(Error::Failed(
+                "Export failed, no transport written. Use --allow-partial to write the exported packages anyway.".to_string(),
+            ));
+        }
+        if packages.is_empty() {
+            return Err(Error::Failed(
+                "No package exported, no transport written".to_string(),
+            ));
+        }
+    }
+
+    let (archive, description) = build_transport(mta_id, mta_version, packages, failed)?;
+    std::fs::write(out, &archive)?;
+    let json_path = description_path(out);
+    std::fs::write(
+        &json_path,
+        serde_json::to_string_pretty(&description)? + "\n",
+    )?;
+    println!(
+        "Transport {} {} written to {} ({} package(s), {} bytes), description in {}",
+        description.mta_id,
+        description.mta_version,
+        out.display(),
+        description.packages.len(),
+        archive.len(),
+        json_path.display()
+    );
+    Ok(())
+}
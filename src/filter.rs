@@ -0,0 +1,678 @@
+use crate::config::{
+    ArtifactRuleEnum, Config, ModifiedMissing, ModifiedWindow, OperationEnum, PackageRuleEnum,
+    PackageSingle, PackageUser, PinnedArtifact, RegexField, ZipExtractionPerType,
+};
+use crate::error::Error;
+use crate::APIResponseResult;
+use chrono::{DateTime, TimeZone, Utc};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+// why a package from the tenant listing was not synced
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum PackageSkipReason {
+    ExcludedByRule { rule_index: usize },
+    NotMatchedByAnyInclude,
+    // a configure-only package, see packages.include_readonly_packages
+    ReadOnly { mode: String },
+}
+
+impl PackageSkipReason {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PackageSkipReason::ExcludedByRule { .. } => "excluded_by_rule",
+            PackageSkipReason::NotMatchedByAnyInclude => "not_matched_by_any_include",
+            PackageSkipReason::ReadOnly { .. } => "read_only",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PackageDecision {
+    pub package_id: String,
+    pub selected: bool,
+    // index of the last rule that matched the package, it decides the outcome
+    pub rule_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none", flatten)]
+    pub skipped: Option<PackageSkipReason>,
+}
+
+// why an artifact of a synced package was not downloaded
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ArtifactSkipReason {
+    DateFiltered { modified_at: Option<String> },
+    // rule_index is None when no include rule matched the artifact
+    Filtered { rule_index: Option<usize> },
+    // another artifact type of the package has the same id, see packages.type_collision
+    TypeCollision { shared_with: String },
+    // deferred by --size-budget, estimated from the local copy or the assumed size
+    BudgetExceeded { estimated_bytes: u64 },
+    // packages.source "deployed": the artifact isn't deployed
+    NotDeployed,
+    // packages.source "deployed": the runtime has no content to download for the artifact
+    NoRuntimePayload { status: u16 },
+    // the artifact has no Active version, only a draft, and packages.draft_handling is "skip"
+    Draft,
+}
+
+impl ArtifactSkipReason {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ArtifactSkipReason::DateFiltered { .. } => "date_filtered",
+            ArtifactSkipReason::Filtered { .. } => "filtered",
+            ArtifactSkipReason::TypeCollision { .. } => "type_collision",
+            ArtifactSkipReason::BudgetExceeded { .. } => "budget_exceeded",
+            ArtifactSkipReason::NotDeployed => "not_deployed",
+            ArtifactSkipReason::NoRuntimePayload { .. } => "no_runtime_payload",
+            ArtifactSkipReason::Draft => "draft",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ArtifactSkip {
+    pub package_id: String,
+    pub artifact_id: String,
+    // OData entity set like in RunStats
+    pub artifact_type: String,
+    #[serde(flatten)]
+    pub reason: ArtifactSkipReason,
+}
+
+// returns the matched part of the text, so near-misses are easy to debug
+pub(crate) fn find_text<'a>(
+    text: &'a str,
+    needle: &'a str,
+    case_insensitive: bool,
+) -> Option<&'a str> {
+    if !case_insensitive {
+        return text.find(needle).map(|i| &text[i..i + needle.len()]);
+    }
+    let lower_text = text.to_lowercase();
+    let lower_needle = needle.to_lowercase();
+    let i = lower_text.find(&lower_needle)?;
+    if lower_text.len() == text.len() {
+        text.get(i..i + lower_needle.len())
+    } else {
+        //lowercasing changed byte offsets, fall back to the needle itself
+        Some(needle)
+    }
+}
+
+// the patterns of the filter rules come from the config, one that doesn't compile is a config error
+fn config_regex(pattern: &str) -> Result<Regex, Error> {
+    Regex::new(pattern).map_err(|e| Error::ConfigValidation(e.to_string()))
+}
+
+// packages without the user field never match, they are counted so the rule can be debugged
+fn user_matches(
+    rule: &PackageUser,
+    field_name: &str,
+    packages: &[APIResponseResult],
+    user_of: fn(&APIResponseResult) -> Option<&String>,
+) -> Result<Vec<String>, Error> {
+    let re = if rule.regex {
+        Some(config_regex(&rule.user)?)
+    } else {
+        None
+    };
+    let mut matched = Vec::new();
+    let mut missing = 0;
+    for p in packages {
+        let user = match user_of(p) {
+            Some(user) if !user.is_empty() => user,
+            _ => {
+                missing += 1;
+                continue;
+            }
+        };
+        let is_match = match &re {
+            Some(re) => re.is_match(user),
+            None => user == &rule.user,
+        };
+        if is_match {
+            matched.push(p.id.clone());
+        }
+    }
+    if missing > 0 {
+        log::info!(
+            "Note: {} packages have no {}, the {} rule for '{}' can't match them",
+            missing, field_name, field_name, rule.user
+        );
+    }
+    Ok(matched)
+}
+
+// ids of the packages matched by a single rule, in tenant listing order
+fn rule_matches(
+    package_rule: &PackageRuleEnum,
+    packages: &[APIResponseResult],
+    package_name_map: &HashMap<String, String>,
+) -> Result<Vec<String>, Error> {
+    let mut matched = Vec::new();
+    match package_rule {
+        PackageRuleEnum::Regex(rule) => {
+            let re = config_regex(&rule.pattern)?;
+            for p in packages {
+                match rule.field {
+                    RegexField::Id if re.is_match(&p.id) => matched.push(p.id.clone()),
+                    //packages sharing a name are all matched
+                    RegexField::Name if re.is_match(&p.name) => {
+                        log::info!(
+                            "Package {} ({}) matched name pattern '{}'",
+                            &p.name, &p.id, &rule.pattern
+                        );
+                        matched.push(p.id.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        PackageRuleEnum::Single(rule) => {
+            //if single package rule not found in original package list check names and inform.
+            if !packages.iter().any(|p| p.id == rule.id) {
+                log::error!("Package ID not found: {}", &rule.id);
+
+                if let Some(id_for_name) = package_name_map.get(&rule.id) {
+                    log::info!(
+                        "Did you enter the Package name instead of this Package ID?: '{}'",
+                        id_for_name
+                    );
+                }
+
+                return Err(Error::PackageNotFound(rule.id.clone()));
+            }
+            matched.push(rule.id.clone());
+        }
+        PackageRuleEnum::DescriptionContains(rule) => {
+            for p in packages {
+                let found = [&p.short_text, &p.description]
+                    .iter()
+                    .filter_map(|text| text.as_deref())
+                    .find_map(|text| find_text(text, &rule.text, rule.case_insensitive));
+
+                if let Some(found) = found {
+                    log::info!(
+                        "Package {} matched description text '{}': '{}'",
+                        &p.id, &rule.text, found
+                    );
+                    matched.push(p.id.clone());
+                }
+            }
+        }
+        PackageRuleEnum::ModifiedBy(rule) => {
+            matched = user_matches(rule, "ModifiedBy", packages, |p| p.modified_by.as_ref())?;
+        }
+        PackageRuleEnum::CreatedBy(rule) => {
+            matched = user_matches(rule, "CreatedBy", packages, |p| p.created_by.as_ref())?;
+        }
+        PackageRuleEnum::All(_) => {
+            matched = packages.iter().map(|p| p.id.clone()).collect();
+        }
+    }
+    Ok(matched)
+}
+
+fn rule_window(package_rule: &PackageRuleEnum) -> &ModifiedWindow {
+    match package_rule {
+        PackageRuleEnum::Regex(rule) => &rule.modified,
+        PackageRuleEnum::Single(rule) => &rule.modified,
+        PackageRuleEnum::DescriptionContains(rule) => &rule.modified,
+        PackageRuleEnum::ModifiedBy(rule) | PackageRuleEnum::CreatedBy(rule) => &rule.modified,
+        PackageRuleEnum::All(rule) => &rule.modified,
+    }
+}
+
+pub(crate) fn rule_has_window(package_rule: &PackageRuleEnum) -> bool {
+    let window = rule_window(package_rule);
+    window.modified_after.is_some() || window.modified_before.is_some()
+}
+
+fn rule_operation(package_rule: &PackageRuleEnum) -> &OperationEnum {
+    match package_rule {
+        PackageRuleEnum::Regex(rule) => &rule.operation,
+        PackageRuleEnum::Single(rule) => &rule.operation,
+        PackageRuleEnum::DescriptionContains(rule) => &rule.operation,
+        PackageRuleEnum::ModifiedBy(rule) | PackageRuleEnum::CreatedBy(rule) => &rule.operation,
+        PackageRuleEnum::All(rule) => &rule.operation,
+    }
+}
+
+// --package: a single rule per id replaces packages.filter_rules, ids that aren't on the tenant
+// fail like any single rule. artifact_filter_rules and the rest of the config still apply.
+pub fn select_packages(config: &mut Config, package_ids: &[String]) {
+    let mut rules: Vec<PackageRuleEnum> = Vec::new();
+    for id in package_ids {
+        if rules
+            .iter()
+            .any(|rule| matches!(rule, PackageRuleEnum::Single(single) if &single.id == id))
+        {
+            continue;
+        }
+        rules.push(PackageRuleEnum::Single(PackageSingle {
+            id: id.clone(),
+            operation: OperationEnum::Include,
+            artifact_filter_rules: None,
+            artifacts: Vec::new(),
+            zip_extraction: None,
+            modified: ModifiedWindow::default(),
+        }));
+    }
+    config.packages.filter_rules = rules;
+}
+
+// SAP standard packages copied from the Discover catalog are configure-only, the API doesn't
+// expose the content of their artifacts
+fn read_only_mode(package: &APIResponseResult) -> Option<&str> {
+    let mode = package.mode.as_deref()?;
+    match mode.to_ascii_uppercase().as_str() {
+        "READ_ONLY" | "CONFIGURE_ONLY" => Some(mode),
+        _ => None,
+    }
+}
+
+// rules are applied in order, the last rule matching a package decides whether it is synced
+pub(crate) fn evaluate_filter_rules(
+    config: &Config,
+    packages: &[APIResponseResult],
+) -> Result<Vec<PackageDecision>, Error> {
+    let mut package_name_map: HashMap<String, String> = HashMap::new();
+    for package in packages {
+        match package_name_map.entry(package.name.to_string()) {
+            std::collections::hash_map::Entry::Occupied(mut e) => {
+                e.insert(e.get().clone() + "," + &package.id);
+            }
+            std::collections::hash_map::Entry::Vacant(e) => {
+                e.insert(package.id.to_string());
+            }
+        };
+    }
+
+    if config.packages.filter_rules.is_empty() {
+        log::warn!(
+            "packages.filter_rules is empty, no package is selected. Use {{\"type\": \"all\"}} to sync every package"
+        );
+    }
+    let mut last_match: HashMap<String, (usize, bool)> = HashMap::new();
+    for (rule_index, package_rule) in config.packages.filter_rules.iter().enumerate() {
+        let include = matches!(rule_operation(package_rule), OperationEnum::Include);
+        for package_id in rule_matches(package_rule, packages, &package_name_map)? {
+            last_match.insert(package_id, (rule_index, include));
+        }
+    }
+
+    let decisions: Vec<PackageDecision> = packages
+        .iter()
+        .map(|p| {
+            let read_only =
+                read_only_mode(p).filter(|_| !config.packages.include_readonly_packages);
+            let (rule_index, selected, skipped) = match (last_match.get(&p.id), read_only) {
+                (Some((rule_index, true)), Some(mode)) => {
+                    log::info!(
+                        "Package {} ({}) is {}, its content can't be downloaded, skipped. \
+                         Set packages.include_readonly_packages to sync it anyway",
+                        &p.name,
+                        &p.id,
+                        mode
+                    );
+                    (
+                        Some(*rule_index),
+                        false,
+                        Some(PackageSkipReason::ReadOnly {
+                            mode: mode.to_string(),
+                        }),
+                    )
+                }
+                (Some((rule_index, true)), None) => (Some(*rule_index), true, None),
+                (Some((rule_index, false)), _) => (
+                    Some(*rule_index),
+                    false,
+                    Some(PackageSkipReason::ExcludedByRule {
+                        rule_index: *rule_index,
+                    }),
+                ),
+                (None, _) => (None, false, Some(PackageSkipReason::NotMatchedByAnyInclude)),
+            };
+            PackageDecision {
+                package_id: p.id.clone(),
+                selected,
+                rule_index,
+                skipped,
+            }
+        })
+        .collect();
+
+    //an `all` rule surprises less when the output says what it did
+    let selected = decisions.iter().filter(|d| d.selected).count();
+    let has_all_rule = config
+        .packages
+        .filter_rules
+        .iter()
+        .any(|rule| matches!(rule, PackageRuleEnum::All(_)));
+    if has_all_rule {
+        match selected == packages.len() {
+            true => log::info!("All {} packages of the tenant are selected", packages.len()),
+            false => log::info!(
+                "{} of {} packages of the tenant are selected",
+                selected,
+                packages.len()
+            ),
+        }
+    }
+
+    Ok(decisions)
+}
+
+enum ArtifactMatcher {
+    Regex(Regex),
+    Id(String),
+}
+
+// what to download from a selected package, artifact rules are compiled once per package
+pub(crate) struct ArtifactSelection {
+    pub window: ModifiedWindow,
+    rules: Vec<(ArtifactMatcher, bool)>,
+    pins: Vec<PinnedArtifact>,
+    // zip_extraction of the rule that selected the package, it wins over packages.zip_extraction
+    pub zip_extraction: Option<ZipExtractionPerType>,
+}
+
+impl ArtifactSelection {
+    // the versions a selected artifact is synced at, None is 'Active'. Without an entry in the
+    // rule's `artifacts` only the active version is synced.
+    pub(crate) fn versions(&self, artifact_id: &str) -> Vec<Option<String>> {
+        let mut versions = Vec::new();
+        for pin in self.pins.iter().filter(|p| p.id == artifact_id) {
+            if !versions.contains(&pin.version) {
+                versions.push(pin.version.clone());
+            }
+        }
+        if versions.is_empty() {
+            versions.push(None);
+        }
+        versions
+    }
+
+    // None if the artifact is selected, otherwise why it is skipped
+    pub(crate) fn skip_reason(
+        &self,
+        config: &Config,
+        artifact_id: &str,
+        modified_at: Option<&str>,
+    ) -> Option<ArtifactSkipReason> {
+        if !self.rules.is_empty() {
+            let last_match = self
+                .rules
+                .iter()
+                .enumerate()
+                .rev()
+                .find(|(_, (matcher, _))| match matcher {
+                    ArtifactMatcher::Regex(re) => re.is_match(artifact_id),
+                    ArtifactMatcher::Id(id) => id == artifact_id,
+                });
+            match last_match {
+                Some((_, (_, true))) => {}
+                Some((rule_index, (_, false))) => {
+                    return Some(ArtifactSkipReason::Filtered {
+                        rule_index: Some(rule_index),
+                    })
+                }
+                None => return Some(ArtifactSkipReason::Filtered { rule_index: None }),
+            }
+        }
+        //date window is checked before any payload download
+        if !artifact_in_window(
+            &self.window,
+            &config.packages.modified_missing,
+            modified_at,
+        ) {
+            return Some(ArtifactSkipReason::DateFiltered {
+                modified_at: modified_at.map(|m| m.to_string()),
+            });
+        }
+        None
+    }
+}
+
+pub(crate) fn artifact_selection(
+    config: &Config,
+    decision: &PackageDecision,
+) -> Result<ArtifactSelection, Error> {
+    let package_rule = decision
+        .rule_index
+        .and_then(|i| config.packages.filter_rules.get(i));
+    let rules = match package_rule {
+        Some(PackageRuleEnum::Single(rule)) => rule
+            .artifact_filter_rules
+            .as_ref()
+            .unwrap_or(&config.packages.artifact_filter_rules),
+        _ => &config.packages.artifact_filter_rules,
+    };
+    let pins = match package_rule {
+        Some(PackageRuleEnum::Single(rule)) => rule.artifacts.clone(),
+        _ => Vec::new(),
+    };
+    let zip_extraction = match package_rule {
+        Some(PackageRuleEnum::Single(rule)) => rule.zip_extraction.clone(),
+        Some(PackageRuleEnum::Regex(rule)) => rule.zip_extraction.clone(),
+        _ => None,
+    };
+
+    let mut compiled = Vec::new();
+    for rule in rules {
+        compiled.push(match rule {
+            ArtifactRuleEnum::Regex(r) => (
+                ArtifactMatcher::Regex(config_regex(&r.pattern)?),
+                matches!(r.operation, OperationEnum::Include),
+            ),
+            ArtifactRuleEnum::Single(r) => (
+                ArtifactMatcher::Id(r.id.clone()),
+                matches!(r.operation, OperationEnum::Include),
+            ),
+        });
+    }
+
+    Ok(ArtifactSelection {
+        window: effective_window(config, decision),
+        rules: compiled,
+        pins,
+        zip_extraction,
+    })
+}
+
+// the global window and the window of the rule that selected the package both apply
+fn effective_window(config: &Config, decision: &PackageDecision) -> ModifiedWindow {
+    let global = &config.packages.modified;
+    let rule = decision
+        .rule_index
+        .and_then(|i| config.packages.filter_rules.get(i))
+        .map(rule_window);
+
+    let mut window = global.clone();
+    if let Some(rule) = rule {
+        window.modified_after = match (window.modified_after, rule.modified_after) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        window.modified_before = match (window.modified_before, rule.modified_before) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+    window
+}
+
+// OData V2 JSON sends "/Date(1612345678901)/", plain epoch milliseconds and ISO-8601 are accepted too
+pub(crate) fn parse_modified_at(value: &str) -> Option<DateTime<Utc>> {
+    let millis = value
+        .trim()
+        .trim_start_matches("/Date(")
+        .trim_end_matches(")/");
+    if let Ok(millis) = millis.parse::<i64>() {
+        return Utc.timestamp_millis_opt(millis).single();
+    }
+    crate::units::parse_date_time(value).ok()
+}
+
+fn artifact_in_window(
+    window: &ModifiedWindow,
+    modified_missing: &ModifiedMissing,
+    modified_at: Option<&str>,
+) -> bool {
+    if window.modified_after.is_none() && window.modified_before.is_none() {
+        return true;
+    }
+    let modified_at = match modified_at.and_then(parse_modified_at) {
+        Some(modified_at) => modified_at,
+        None => return matches!(modified_missing, ModifiedMissing::Include),
+    };
+    if let Some(after) = window.modified_after {
+        if modified_at < after {
+            return false;
+        }
+    }
+    if let Some(before) = window.modified_before {
+        if modified_at >= before {
+            return false;
+        }
+    }
+    true
+}
+
+pub(crate) fn print_skip_summary(decisions: &[PackageDecision]) {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for reason in decisions.iter().filter_map(|d| d.skipped.as_ref()) {
+        *counts.entry(reason.code()).or_default() += 1;
+    }
+    if counts.is_empty() {
+        return;
+    }
+    log::info!("Skipped Packages:");
+    for (code, count) in counts {
+        log::info!("  {}: {}", code, count);
+    }
+}
+
+pub(crate) fn print_artifact_skip_summary(skipped: &[ArtifactSkip]) {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for skip in skipped {
+        *counts.entry(skip.reason.code()).or_default() += 1;
+    }
+    if counts.is_empty() {
+        return;
+    }
+    log::info!("Skipped Artifacts:");
+    for (code, count) in counts {
+        log::info!("  {}: {}", code, count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::parse_date_time;
+
+    fn window(after: Option<&str>, before: Option<&str>) -> ModifiedWindow {
+        ModifiedWindow {
+            modified_after: after.map(|d| parse_date_time(d).unwrap()),
+            modified_before: before.map(|d| parse_date_time(d).unwrap()),
+        }
+    }
+
+    #[test]
+    fn date_bound_is_utc_midnight() {
+        let after = window(Some("2024-03-01"), None);
+        let include = ModifiedMissing::Include;
+        assert!(!artifact_in_window(
+            &after,
+            &include,
+            Some("2024-02-29T23:59:59Z")
+        ));
+        assert!(artifact_in_window(
+            &after,
+            &include,
+            Some("2024-03-01T00:00:00Z")
+        ));
+        //midnight in Berlin is still the day before in UTC
+        assert!(!artifact_in_window(
+            &after,
+            &include,
+            Some("2024-03-01T00:30:00+01:00")
+        ));
+    }
+
+    #[test]
+    fn explicit_offset_moves_the_bound() {
+        let after = window(Some("2024-03-01T00:00:00+02:00"), None);
+        let include = ModifiedMissing::Include;
+        assert_eq!(
+            after.modified_after.unwrap(),
+            Utc.with_ymd_and_hms(2024, 2, 29, 22, 0, 0).unwrap()
+        );
+        assert!(artifact_in_window(
+            &after,
+            &include,
+            Some("2024-02-29T23:00:00Z")
+        ));
+        assert!(!artifact_in_window(
+            &after,
+            &include,
+            Some("2024-02-29T21:59:59Z")
+        ));
+    }
+
+    #[test]
+    fn odata_dates_are_epoch_milliseconds() {
+        let midnight = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        assert_eq!(parse_modified_at("/Date(1709251200000)/"), Some(midnight));
+        assert_eq!(parse_modified_at("1709251200000"), Some(midnight));
+        assert_eq!(parse_modified_at("2024-03-01T00:00:00Z"), Some(midnight));
+
+        //the upper bound is exclusive, the lower one inclusive
+        let march = window(Some("2024-03-01"), Some("2024-04-01"));
+        let include = ModifiedMissing::Include;
+        assert!(artifact_in_window(
+            &march,
+            &include,
+            Some("/Date(1709251200000)/")
+        ));
+        assert!(!artifact_in_window(
+            &march,
+            &include,
+            Some("/Date(1709251199999)/")
+        ));
+        assert!(!artifact_in_window(
+            &march,
+            &include,
+            Some("/Date(1711929600000)/")
+        ));
+        assert!(artifact_in_window(
+            &march,
+            &include,
+            Some("/Date(1711929599999)/")
+        ));
+    }
+
+    #[test]
+    fn missing_modified_at_follows_modified_missing() {
+        let march = window(Some("2024-03-01"), Some("2024-04-01"));
+        for modified_at in &[None, Some(""), Some("yesterday")] {
+            assert!(artifact_in_window(
+                &march,
+                &ModifiedMissing::Include,
+                *modified_at
+            ));
+            assert!(!artifact_in_window(
+                &march,
+                &ModifiedMissing::Exclude,
+                *modified_at
+            ));
+        }
+        //without a window there is nothing to compare
+        let open = window(None, None);
+        assert!(artifact_in_window(&open, &ModifiedMissing::Exclude, None));
+    }
+}
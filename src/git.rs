@@ -0,0 +1,109 @@
+use crate::error::Error;
+use crate::{Config, Identity};
+use chrono::Utc;
+use std::path::Path;
+use std::process::{Command, Output};
+
+// runs the git binary in local_dir, a non-zero exit is an error with git's message
+fn git(data_dir: &Path, args: &[&str]) -> Result<Output, Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(data_dir)
+        .args(args)
+        .output()
+        .map_err(|e| Error::Git(format!("Can not run git: {}", e)))?;
+    Ok(output)
+}
+
+fn git_checked(data_dir: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = git(data_dir, args)?;
+    if !output.status.success() {
+        return Err(Error::Git(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// {host}, {identity}, {timestamp} (RFC 3339, UTC) and {downloaded} are replaced
+fn message(config: &Config, downloaded: usize, identity: &Identity) -> String {
+    config
+        .git
+        .message
+        .replace("{host}", &config.tenant.management_host)
+        .replace("{identity}", &identity.to_string())
+        .replace(
+            "{timestamp}",
+            &Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        )
+        .replace("{downloaded}", &downloaded.to_string())
+}
+
+// git.commit: stages everything below local_dir and commits it, other paths of the repository
+// and what is already staged there stay untouched. Returns the new commit, None without changes.
+pub(crate) fn commit(
+    config: &Config,
+    data_dir: &Path,
+    downloaded: usize,
+    identity: &Identity,
+) -> Result<Option<String>, Error> {
+    git_checked(data_dir, &["rev-parse", "--show-toplevel"]).map_err(|e| {
+        Error::Git(format!(
+            "git.commit: {} is not in a git work tree ({})",
+            data_dir.display(),
+            e
+        ))
+    })?;
+    git_checked(data_dir, &["add", "--all", "--", "."])?;
+    //exit code 1: there are staged changes below local_dir
+    let diff = git(data_dir, &["diff", "--cached", "--quiet", "--", "."])?;
+    match diff.status.code() {
+        Some(0) => {
+            log::info!("Git: no changes in {}, nothing committed", data_dir.display());
+            return Ok(None);
+        }
+        Some(1) => {}
+        _ => {
+            return Err(Error::Git(format!(
+                "git diff failed: {}",
+                String::from_utf8_lossy(&diff.stderr).trim()
+            )))
+        }
+    }
+    let message = message(config, downloaded, identity);
+    git_checked(data_dir, &["commit", "--quiet", "-m", &message, "--", "."])?;
+    let commit = git_checked(data_dir, &["rev-parse", "HEAD"])?;
+    log::info!("Git: committed {} \"{}\"", commit, message);
+    Ok(Some(commit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(message: &str, redact: bool) -> Config {
+        let mut config = crate::config::test_config(serde_json::json!({ "filter_rules": [] }));
+        config.git.message = message.to_string();
+        config.tenant.redact_identity = redact;
+        config
+    }
+
+    #[test]
+    fn message_names_the_identity() {
+        let config = config("Sync {host} as {identity}, {downloaded} artifact(s)", false);
+        let identity = crate::identity::resolve_identity(&config, "Basic czAwMDE6c2VjcmV0");
+        assert_eq!(
+            message(&config, 3, &identity),
+            "Sync tenant.example.com as S0001 (s_user), 3 artifact(s)"
+        );
+    }
+
+    #[test]
+    fn redacted_identity_stays_out_of_the_message() {
+        let config = config("{identity}", true);
+        let identity = crate::identity::resolve_identity(&config, "Basic czAwMDE6c2VjcmV0");
+        assert_eq!(message(&config, 0, &identity), "<redacted> (s_user)");
+    }
+}
@@ -0,0 +1,167 @@
+use crate::error::Error;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+// content hashes of a mirror, computed from the files on disk so two mirrors can be compared
+// without copying them. Every hash is SHA-256 over sorted "name<TAB>hash" lines:
+// a package over its files (relative paths with '/'), the root over its packages and the
+// folders of packages.layout.
+#[derive(Debug, Clone)]
+pub struct PackageHash {
+    pub package_id: String,
+    pub hash: String,
+    pub file_count: usize,
+}
+
+// a top-level folder of packages.layout, e.g. "iflows", hashed like a package
+#[derive(Debug, Clone)]
+pub struct LayoutDirHash {
+    pub dir: String,
+    pub hash: String,
+    pub file_count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct MirrorHash {
+    pub root: String,
+    pub packages: Vec<PackageHash>,
+    pub layout_dirs: Vec<LayoutDirHash>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hash_lines(entries: &[(String, String)]) -> String {
+    let mut hasher = Sha256::new();
+    for (name, hash) in entries {
+        hasher.update(name.as_bytes());
+        hasher.update(b"\t");
+        hasher.update(hash.as_bytes());
+        hasher.update(b"\n");
+    }
+    hex(&hasher.finalize())
+}
+
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<(String, String)>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let relative = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &relative, files)?;
+        } else {
+            let content = fs::read(entry.path())?;
+            files.push((relative, hex(&Sha256::digest(&content))));
+        }
+    }
+    Ok(())
+}
+
+// empty directories don't contribute, only file paths and contents do
+pub fn hash_package(data_dir: &Path, package_id: &str) -> Result<PackageHash, Error> {
+    let package_dir = crate::paths::package_dir(data_dir, package_id);
+    if !package_dir.is_dir() {
+        return Err(Error::NotFound(format!(
+            "Package directory not found: {}",
+            package_dir.display()
+        )));
+    }
+    let mut files = Vec::new();
+    collect_files(&package_dir, "", &mut files)?;
+    files.sort();
+    Ok(PackageHash {
+        package_id: package_id.to_string(),
+        hash: hash_lines(&files),
+        file_count: files.len(),
+    })
+}
+
+// the hash of any directory, computed like the one of a package
+pub(crate) fn hash_dir(dir: &Path) -> Result<String, Error> {
+    let mut files = Vec::new();
+    collect_files(dir, "", &mut files)?;
+    files.sort();
+    Ok(hash_lines(&files))
+}
+
+// every top-level directory the sync created is a package or a folder of packages.layout, files,
+// dot-directories (e.g. .git) and the user's own directories next to them are ignored
+pub fn hash_mirror(data_dir: &Path) -> Result<MirrorHash, Error> {
+    let managed = crate::managed::ManagedPaths::read(data_dir)?;
+    let layout_dir_names = managed.layout_dirs();
+    let mut package_ids = Vec::new();
+    for entry in fs::read_dir(data_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() && !name.starts_with('.') && managed.includes(&name) {
+            package_ids.push(name);
+        }
+    }
+    package_ids.sort();
+
+    let mut packages = Vec::new();
+    for package_id in package_ids.iter() {
+        packages.push(hash_package(data_dir, package_id)?);
+    }
+    let mut layout_dirs = Vec::new();
+    for dir in layout_dir_names {
+        let path = data_dir.join(&dir);
+        if !path.is_dir() {
+            continue;
+        }
+        let mut files = Vec::new();
+        collect_files(&path, "", &mut files)?;
+        files.sort();
+        layout_dirs.push(LayoutDirHash {
+            dir,
+            hash: hash_lines(&files),
+            file_count: files.len(),
+        });
+    }
+    // a folder of a layout ends with '/', it never has the name of a package directory
+    let entries: Vec<(String, String)> = packages
+        .iter()
+        .map(|p| (p.package_id.clone(), p.hash.clone()))
+        .chain(
+            layout_dirs
+                .iter()
+                .map(|d| (format!("{}/", d.dir), d.hash.clone())),
+        )
+        .collect();
+    Ok(MirrorHash {
+        root: hash_lines(&entries),
+        packages,
+        layout_dirs,
+    })
+}
+
+pub fn run_hash(
+    config: &crate::Config,
+    config_path: &str,
+    package_id: Option<&str>,
+) -> Result<(), Error> {
+    let data_dir = crate::local_data_dir(config, config_path);
+    match package_id {
+        Some(package_id) => {
+            let package = hash_package(&data_dir, package_id)?;
+            println!("{}  {}", package.hash, package.package_id);
+        }
+        None => {
+            let mirror = hash_mirror(&data_dir)?;
+            for package in mirror.packages.iter() {
+                println!("{}  {}", package.hash, package.package_id);
+            }
+            for dir in mirror.layout_dirs.iter() {
+                println!("{}  {}/", dir.hash, dir.dir);
+            }
+            println!("Mirror root hash: {}", mirror.root);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use crate::config::{Config, CredentialInside};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+const REDACTED: &str = "<redacted>";
+
+// non-sensitive description of who performed the run, the token itself is never kept. Also in
+// the run report, the manifest and the {identity} of git.message, tenant.redact_identity applies
+// to all of them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Identity {
+    pub credential_type: String,
+    // position of the accepted credential in a tenant.credential chain, counted from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_index: Option<usize>,
+    pub user: String,
+    pub subaccount_id: Option<String>,
+    pub zone_id: Option<String>,
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.credential_index {
+            Some(index) => write!(
+                f,
+                "{} ({}, credential {})",
+                self.user, self.credential_type, index
+            )?,
+            None => write!(f, "{} ({})", self.user, self.credential_type)?,
+        }
+        if let Some(subaccount_id) = &self.subaccount_id {
+            write!(f, ", subaccount: {}", subaccount_id)?;
+        }
+        if let Some(zone_id) = &self.zone_id {
+            write!(f, ", zone: {}", zone_id)?;
+        }
+        Ok(())
+    }
+}
+
+// decode the JWT payload without verification, opaque (NEO) tokens just return None
+pub(crate) fn jwt_claims(authorization: &str) -> Option<Value> {
+    let token = authorization.strip_prefix("Bearer ")?;
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn claim(claims: &Value, pointer: &str) -> Option<String> {
+    claims
+        .pointer(pointer)
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+}
+
+pub(crate) fn resolve_identity(config: &Config, authorization: &str) -> Identity {
+    let redact = config.tenant.redact_identity;
+    let credential = config.tenant.credential.active();
+    let user = match credential {
+        CredentialInside::SUser(c) => c.username.to_string(),
+        CredentialInside::OauthClientCredentials(c) => c.client_id.to_string(),
+        CredentialInside::OauthClientCertificate(c) => c.client_id.to_string(),
+        CredentialInside::ServiceKey(c) => crate::service_key::read(c)
+            .map(|key| key.client_id)
+            .unwrap_or_else(|_| "service key".to_string()),
+        CredentialInside::ApiKey(c) => format!("{} header", c.header_name),
+    };
+
+    let claims = jwt_claims(authorization);
+    let subaccount_id = claims
+        .as_ref()
+        .and_then(|c| claim(c, "/ext_attr/subaccountid"));
+    let zone_id = claims.as_ref().and_then(|c| claim(c, "/zid"));
+
+    let redacted = |v: String| if redact { REDACTED.to_string() } else { v };
+    Identity {
+        credential_type: credential.type_name().to_string(),
+        credential_index: None,
+        user: redacted(user),
+        subaccount_id: subaccount_id.map(redacted),
+        zone_id: zone_id.map(redacted),
+    }
+}
@@ -0,0 +1,145 @@
+use crate::deprecation::CONFIG_VERSION;
+use crate::error::Error;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+
+fn read_answer(prompt: &str) -> Result<String, Error> {
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    if std::io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "No answer, stdin was closed",
+        )
+        .into());
+    }
+    Ok(line.trim().to_string())
+}
+
+// one answer from stdin, the default when the line is empty
+fn ask(question: &str, default: Option<&str>) -> Result<String, Error> {
+    loop {
+        let answer = match default {
+            Some(default) => read_answer(&format!("{} [{}]: ", question, default))?,
+            None => read_answer(&format!("{}: ", question))?,
+        };
+        match (answer.is_empty(), default) {
+            (false, _) => return Ok(answer),
+            (true, Some(default)) => return Ok(default.to_string()),
+            (true, None) => println!("An answer is required."),
+        }
+    }
+}
+
+fn ask_yes_no(question: &str, default: bool) -> Result<bool, Error> {
+    let hint = if default { "Y/n" } else { "y/N" };
+    loop {
+        let answer = read_answer(&format!("{} [{}]: ", question, hint))?;
+        match answer.to_ascii_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Answer y or n."),
+        }
+    }
+}
+
+// "https://my-tmn.hci.eu1.hana.ondemand.com/" is pasted from the browser often
+fn host_of(answer: &str) -> String {
+    let host = answer
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    host.split('/').next().unwrap_or(host).to_string()
+}
+
+// the questions of `init` without --no-input. Secrets are never asked for, the config only
+// names the environment variable they are read from.
+pub fn ask_init_config() -> Result<Value, Error> {
+    println!("Create a cpi-sync config, press Enter to take the value in brackets.");
+    let credential_type = loop {
+        let answer = ask(
+            "Credential type: s_user, oauth_client_credentials or service_key",
+            Some("oauth_client_credentials"),
+        )?;
+        match answer.as_str() {
+            "s_user" | "oauth_client_credentials" | "service_key" => break answer,
+            _ => println!("Unknown credential type {:?}.", answer),
+        }
+    };
+
+    let (host, credential) = match credential_type.as_str() {
+        "s_user" => {
+            let host = host_of(&ask(
+                "Management host, e.g. my-tmn.hci.eu10.hana.ondemand.com",
+                None,
+            )?);
+            let username = ask("Username, e.g. S0001234567 or your e-mail", None)?;
+            let variable = ask(
+                "Environment variable with the password",
+                Some("CPI_PASSWORD"),
+            )?;
+            (
+                Some(host),
+                json!({ "s_user": { "username": username, "password_environment_variable": variable } }),
+            )
+        }
+        "oauth_client_credentials" => {
+            let host = host_of(&ask(
+                "Management host, e.g. my-tmn.hci.eu10.hana.ondemand.com",
+                None,
+            )?);
+            let client_id = ask("Client id (clientid of the service key)", None)?;
+            let token_url = ask("Token endpoint URL (tokenurl of the service key)", None)?;
+            let variable = ask(
+                "Environment variable with the client secret",
+                Some("CPI_CLIENT_SECRET"),
+            )?;
+            (
+                Some(host),
+                json!({ "oauth_client_credentials": {
+                    "client_id": client_id,
+                    "client_secret_environment_variable": variable,
+                    "token_endpoint_url": token_url
+                } }),
+            )
+        }
+        _ => {
+            let variable = ask(
+                "Environment variable with the service key JSON",
+                Some("CPI_SERVICE_KEY"),
+            )?;
+            //the url of the service key is the tenant
+            (
+                None,
+                json!({ "service_key": { "environment_variable": variable } }),
+            )
+        }
+    };
+
+    let filter_rules = match ask_yes_no("Sync all packages of the tenant?", true)? {
+        true => json!([{ "type": "all" }]),
+        false => {
+            let pattern = ask("Regex for the package ids to sync", Some("^Z_"))?;
+            json!([{ "type": "regex", "pattern": pattern }])
+        }
+    };
+    let local_dir = ask(
+        "Directory for the packages, relative to the config file",
+        Some("./"),
+    )?;
+
+    let tenant = match host {
+        Some(host) => json!({ "management_host": host, "credential": credential }),
+        None => json!({ "credential": credential }),
+    };
+    Ok(json!({
+        "cpisync": CONFIG_VERSION,
+        "tenant": tenant,
+        "packages": { "local_dir": local_dir, "filter_rules": filter_rules }
+    }))
+}
+
+pub fn ask_connection_check() -> Result<bool, Error> {
+    ask_yes_no("Test the connection now?", true)
+}
@@ -0,0 +1,92 @@
+// JSON with `//` and `/* */` comments and trailing commas, reduced to plain JSON.
+// Removed characters become spaces and newlines are kept, so parse errors
+// still point at the line and column of the original file.
+
+fn blank(c: char) -> char {
+    if c == '\n' || c == '\r' {
+        c
+    } else {
+        ' '
+    }
+}
+
+fn strip_comments(text: &str) -> Vec<char> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = Vec::with_capacity(chars.len());
+    let mut i = 0;
+    let mut in_string = false;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if in_string {
+            out.push(c);
+            if c == '\\' {
+                if let Some(escaped) = next {
+                    out.push(escaped);
+                    i += 1;
+                }
+            } else if c == '"' {
+                in_string = false;
+            }
+            i += 1;
+        } else if c == '"' {
+            in_string = true;
+            out.push(c);
+            i += 1;
+        } else if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                out.push(blank(chars[i]));
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            out.push(' ');
+            out.push(' ');
+            i += 2;
+            // an unterminated comment is blanked to the end, serde_json then reports the EOF
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                out.push(blank(chars[i]));
+                i += 1;
+            }
+            if i < chars.len() {
+                out.push(' ');
+                out.push(' ');
+                i += 2;
+            }
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+    out
+}
+
+fn strip_trailing_commas(chars: &mut [char]) {
+    let mut in_string = false;
+    let mut escaped = false;
+    for i in 0..chars.len() {
+        let c = chars[i];
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if matches!(next, Some('}') | Some(']')) {
+                chars[i] = ' ';
+            }
+        }
+    }
+}
+
+// strict JSON comes back unchanged
+pub(crate) fn to_json(text: &str) -> String {
+    let mut chars = strip_comments(text);
+    strip_trailing_commas(&mut chars);
+    chars.into_iter().collect()
+}
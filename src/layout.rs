@@ -0,0 +1,151 @@
+use crate::artifact::ArtifactKind;
+use serde::de::{self, Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
+
+const PLACEHOLDERS: [&str; 4] = ["package_id", "package_name", "artifact_id", "artifact_type"];
+
+enum Part<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn parse_segment(segment: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = segment;
+    while !rest.is_empty() {
+        match (rest.find('{'), rest.find('}')) {
+            (Some(0), Some(end)) => {
+                let name = &rest[1..end];
+                if !PLACEHOLDERS.contains(&name) {
+                    return Err(format!(
+                        "unknown placeholder {{{}}}, use {}",
+                        name,
+                        PLACEHOLDERS
+                            .iter()
+                            .map(|p| format!("{{{}}}", p))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+                if let Some(Part::Placeholder(previous)) = parts.last() {
+                    return Err(format!(
+                        "{{{}}}{{{}}} can't be told apart, put a separator between them",
+                        previous, name
+                    ));
+                }
+                parts.push(Part::Placeholder(name));
+                rest = &rest[end + 1..];
+            }
+            (Some(start), Some(end)) if start < end => {
+                parts.push(Part::Text(&rest[..start]));
+                rest = &rest[start..];
+            }
+            (None, None) => {
+                parts.push(Part::Text(rest));
+                rest = "";
+            }
+            _ => return Err(format!("unbalanced braces in '{}'", segment)),
+        }
+    }
+    Ok(parts)
+}
+
+// a layout is relative to local_dir, every folder level is named and the artifact is the last one
+pub fn parse_layout(template: &str) -> Result<String, String> {
+    let error = |reason: String| format!("invalid layout '{}': {}", template, reason);
+    if template.contains('\\') || template.contains(':') || template.starts_with('/') {
+        return Err(error(
+            "use '/' between folders and a path relative to local_dir".to_string(),
+        ));
+    }
+    let segments: Vec<&str> = template.split('/').collect();
+    for segment in segments.iter() {
+        if segment.is_empty() || segment.starts_with('.') {
+            return Err(error(format!(
+                "folder '{}' is empty or starts with '.'",
+                segment
+            )));
+        }
+        parse_segment(segment).map_err(error)?;
+    }
+    let last = segments.last().copied().unwrap_or("");
+    if !last.contains("{artifact_id}") {
+        return Err(error(
+            "the last folder must contain {artifact_id}, otherwise artifacts overwrite each other"
+                .to_string(),
+        ));
+    }
+    Ok(template.to_string())
+}
+
+pub fn deserialize_opt_layout<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_layout(&value).map(Some).map_err(de::Error::custom)
+}
+
+// the values of a layout, encoded like folder names. artifact_dir already has the type suffix
+// and the version of a pinned artifact.
+pub(crate) struct LayoutValues<'a> {
+    pub package_id: &'a str,
+    pub package_name: &'a str,
+    pub artifact_dir: &'a str,
+    pub kind: ArtifactKind,
+}
+
+// the folder the artifact folder or zip goes into and its name, e.g. `iflows` and `MyFlow` for
+// `iflows/{artifact_id}`. Without a layout it is the package folder and artifact_dir.
+pub(crate) fn artifact_location(
+    layout: Option<&str>,
+    data_dir: &Path,
+    values: &LayoutValues,
+) -> (PathBuf, String) {
+    let layout = match layout {
+        Some(layout) => layout,
+        None => {
+            return (
+                crate::paths::package_dir(data_dir, values.package_id),
+                values.artifact_dir.to_string(),
+            )
+        }
+    };
+    let mut rendered: Vec<String> = Vec::new();
+    for segment in layout.split('/') {
+        //validated when the config was read
+        let parts = parse_segment(segment).unwrap_or_default();
+        let mut name = String::new();
+        for part in parts {
+            match part {
+                Part::Text(text) => name.push_str(text),
+                Part::Placeholder("package_id") => {
+                    name.push_str(&crate::paths::dir_name(values.package_id))
+                }
+                Part::Placeholder("package_name") => {
+                    name.push_str(&crate::paths::dir_name(values.package_name))
+                }
+                Part::Placeholder("artifact_type") => name.push_str(values.kind.config_name()),
+                Part::Placeholder(_) => name.push_str(values.artifact_dir),
+            }
+        }
+        rendered.push(name);
+    }
+    let name = rendered.pop().unwrap_or_default();
+    let container = rendered
+        .iter()
+        .fold(data_dir.to_path_buf(), |dir, part| dir.join(part));
+    (container, name)
+}
+
+// the artifact folder or zip name below local_dir with '/', like the manifest records it
+pub(crate) fn relative_path(data_dir: &Path, container: &Path, name: &str) -> String {
+    let mut parts: Vec<String> = container
+        .strip_prefix(data_dir)
+        .unwrap_or(container)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    parts.push(name.to_string());
+    parts.join("/")
+}
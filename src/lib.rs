@@ -1,35 +1,147 @@
+mod artifact;
+mod atomic;
+mod auth;
+mod budget;
+mod check;
+mod checkpoint;
 mod config;
+mod configurations;
+mod deprecation;
+mod diff;
+mod discovery;
+mod error;
+mod exit;
+mod export;
+mod filter;
+mod git;
+mod hash;
+mod http;
+mod identity;
+mod init;
+mod jsonc;
+mod layout;
+mod lint;
+mod logging;
+mod managed;
+mod manifest;
+mod metadata;
+mod options;
+mod package_metadata;
+mod paths;
+mod ping;
+mod permissions;
+mod plan;
+mod progress;
+mod prune;
+mod push;
+mod report;
+mod runtime;
+mod select;
+mod service_key;
+mod stale;
+mod stats;
+mod support;
+mod tenants;
+mod transport;
+mod units;
+mod validate;
+mod webui;
+use auth::Authorization;
+use checkpoint::CheckpointFile;
+use report::ReportBuilder;
 use config::*;
 use futures::{
     stream::{FuturesUnordered, StreamExt},
-    Future,
+    Future, FutureExt,
 };
 use path_slash::PathBufExt;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     env,
     io::{Read, Write},
-    iter::FromIterator,
     path::{Component, Path, PathBuf},
 };
 use std::{fs, io::Cursor, ops::Deref};
 
+pub use artifact::ArtifactKind;
+pub use check::run_check;
+pub use checkpoint::Checkpoint;
 pub use config::Config;
+pub use diff::run_diff;
+pub use deprecation::{
+    apply_deprecations, check_config_version, migrate_config_text, run_migrate_config, Deprecation,
+    CONFIG_VERSION,
+};
+pub use error::Error;
+pub use exit::ExitCode;
+pub use export::run_export_transport;
+pub use discovery::{
+    config_format, config_search_paths, discover_config, global_config_path, parse_config_text,
+    run_init, ConfigFormat,
+};
+pub use filter::{
+    select_packages, ArtifactSkip, ArtifactSkipReason, PackageDecision, PackageSkipReason,
+};
+pub use hash::{hash_mirror, hash_package, run_hash, LayoutDirHash, MirrorHash, PackageHash};
+pub use identity::Identity;
+pub use init::{ask_connection_check, ask_init_config};
+pub use lint::{lint_config, ConfigLint};
+pub use logging::{init_logging, with_log_prefix};
+pub use manifest::run_verify;
+pub use metadata::run_metadata;
+pub use options::{validate_options, EffectiveSettings, OptionIssue, OptionSeverity};
+pub use ping::run_ping;
+pub use progress::enable_progress;
+pub use select::{enable_interactive_select, print_selected_filter_rules};
+pub use prune::run_prune;
+pub use push::{run_push, run_push_apply};
+pub use service_key::resolve_service_keys;
+pub use report::{
+    ArtifactAction, ArtifactReport, PackageAction, PackageReport, RunReport, RunStatus,
+    REPORT_VERSION,
+};
+pub use http::{share_host_limit, Endpoint, EndpointTimings, Timings};
+pub use stale::{run_list, StaleActive, StaleActiveCheck};
+pub use stats::{Cancelled, RunStats};
+pub use support::run_support_bundle;
+pub use tenants::{select_tenant, split_tenants, TenantValue};
+pub use tokio_util::sync::CancellationToken;
+pub use units::{
+    deserialize_duration, deserialize_opt_duration, deserialize_opt_size, deserialize_size,
+    parse_date_time, parse_duration, parse_size,
+};
+pub use validate::{check_artifact, ArtifactFile, ContentViolation};
+pub use webui::run_open;
 
 // use rand::seq::SliceRandom;
 // use rand::thread_rng;
 
 // response types
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct APIResponseResult {
     #[serde(rename = "Id")]
     id: String,
     #[serde(rename = "Name")]
     name: String,
-    #[serde(rename = "Mode")]
+    // null and missing fields are left out of the package metadata file
+    #[serde(rename = "Version", skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(rename = "Mode", skip_serializing_if = "Option::is_none")]
     mode: Option<String>,
+    #[serde(rename = "Description", skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(rename = "ShortText", skip_serializing_if = "Option::is_none")]
+    short_text: Option<String>,
+    #[serde(rename = "ModifiedAt", skip_serializing_if = "Option::is_none")]
+    modified_at: Option<String>,
+    #[serde(rename = "CreatedBy", skip_serializing_if = "Option::is_none")]
+    created_by: Option<String>,
+    #[serde(rename = "ModifiedBy", skip_serializing_if = "Option::is_none")]
+    modified_by: Option<String>,
+    // the other fields of the response, written to the package metadata file
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_json::Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -46,73 +158,187 @@ struct APIResponseRoot {
 #[derive(Serialize, Deserialize, Debug)]
 struct TokenAPIResponseRoot {
     access_token: String,
+    expires_in: Option<u64>,
+    scope: Option<String>,
 }
 
-async fn write_artifact(
+// outcome of authentication, token details are only known for OAuth
+pub(crate) struct AuthResult {
+    pub authorization: String,
+    pub expires_in: Option<u64>,
+    pub scope: Option<String>,
+}
+
+// an artifact download that hasn't started yet, futures only run when awaited
+struct ArtifactTask<F> {
+    kind: ArtifactKind,
+    artifact_id: String,
+    artifact_dir: String,
+    // the folder artifact_dir is written to, the package folder without packages.layout
+    container: PathBuf,
+    // packages.zip_extraction or the one of the package's rule
+    zip_extraction: ZipExtraction,
+    version: Option<String>,
+    // the listed version, also for a pinned or deployed one
+    active_version: Option<String>,
+    modified_at: Option<String>,
+    download: F,
+}
+
+// a selected artifact with the folder it resolved to and where its content comes from
+struct ArtifactSource {
+    kind: ArtifactKind,
+    artifact: APIResponseResult,
+    artifact_dir: String,
+    container: PathBuf,
+    // packages.source "deployed"
+    deployed: Option<runtime::RuntimeArtifact>,
+    // a version of the `artifacts` of a single package rule, None is 'Active'
+    pinned_version: Option<String>,
+}
+
+// a listed package: its download tasks and the artifacts skipped
+type PackageOutput<F> = (String, Vec<ArtifactTask<F>>, Vec<ArtifactSkip>);
+
+// a finished download task: package id, artifact id, version, type and outcome
+type DownloadResult = (
+    String,
+    String,
+    Option<String>,
+    ArtifactKind,
+    Result<ArtifactDownload, Error>,
+);
+
+// a download that didn't stop the sync
+struct ArtifactDownload {
+    violations: Vec<ContentViolation>,
+    bytes: u64,
+    duration: std::time::Duration,
+    // the download failed and --ignore-error-download kept the sync going
+    failure: Option<String>,
+    // there was nothing to download, e.g. a deployed artifact without payload
+    skipped: Option<ArtifactSkipReason>,
+    // checksums for packages.manifest
+    digest: Option<manifest::PayloadDigest>,
+    // the draft was downloaded, packages.draft_handling "download"
+    draft: bool,
+}
+
+// the path of a zip entry below the artifact folder, None when it would leave it: only plain
+// components are accepted, encoded like ids, and none of them may be an existing symlink
+fn contained_path(root: &Path, relative: &Path) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for component in relative.components() {
+        match component {
+            std::path::Component::Normal(part) => {
+                path.push(paths::dir_name(&part.to_string_lossy()))
+            }
+            std::path::Component::CurDir => continue,
+            _ => return None,
+        }
+        let is_symlink = fs::symlink_metadata(&path)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false);
+        if is_symlink {
+            return None;
+        }
+    }
+    Some(path)
+}
+
+// the payload as <artifact_dir>.zip
+fn write_zip(
+    container: &Path,
+    artifact_dir: &str,
+    payload: &[u8],
+) -> Result<(), Error> {
+    let write_dir = container.join(artifact_dir.to_string() + ".zip");
+
+    if let Some(parent_dir) = write_dir.parent() {
+        fs::create_dir_all(parent_dir)?;
+    }
+
+    let mut write_dir = fs::File::create(&write_dir)?;
+    std::io::copy(&mut Cursor::new(payload), &mut write_dir)?;
+    Ok(())
+}
+
+// the payload extracted into <artifact_dir>
+fn extract_zip(
     package_id: &str,
-    artifact_id: &str,
+    artifact_dir: &str,
     config: &Config,
-    data_dir: &std::path::PathBuf,
-    mut respbytes_cursor: Cursor<&[u8]>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    match config.packages.zip_extraction {
-        ZipExtraction::Disabled => {
-            let write_dir = data_dir
-                .join(&package_id)
-                .join(artifact_id.to_string() + ".zip");
-
-            let parent_dir = write_dir.parent().unwrap();
-            fs::create_dir_all(parent_dir).unwrap();
-
-            let mut write_dir = fs::File::create(&write_dir).unwrap();
-            std::io::copy(&mut respbytes_cursor, &mut write_dir).unwrap();
-        }
-        ZipExtraction::Enabled => {
-            let mut archive = zip::ZipArchive::new(respbytes_cursor).unwrap();
-
-            for i in 0..archive.len() {
-                let mut file = archive.by_index(i).unwrap();
-
-                let outpath_str = file.enclosed_name().unwrap().to_str().unwrap();
-                let outpath: PathBuf = PathBuf::from_slash(outpath_str);
-
-                // println!(
-                //     "data_dir: {:?} , package_id:{:?} , artifact_id: {:?}, outpath: {:?}",
-                //     &data_dir, &package_id, &artifact.id, &outpath
-                // );
-                let write_dir = data_dir.join(&package_id).join(artifact_id).join(outpath);
-                // println!("write_dir: {:?} ", &write_dir);
-
-                let parent_dir = write_dir.parent().unwrap();
-                fs::create_dir_all(parent_dir).unwrap();
-                let mut write_dir = fs::File::create(&write_dir).unwrap();
-
-                match config.packages.prop_comment_removal {
-                    PropCommentRemoval::Disabled => {
-                        std::io::copy(&mut file, &mut write_dir).unwrap();
-                    }
-                    PropCommentRemoval::Enabled => {
-                        if outpath_str.ends_with("parameters.prop") {
-                            let mut prop_content = String::new();
-                            file.read_to_string(&mut prop_content)?;
-
-                            let prop_lines: Vec<&str> = prop_content
-                                .lines()
-                                .filter(|l| !l.starts_with("#"))
-                                .collect();
-
-                            for line in prop_lines {
-                                write_dir
-                                    .write_all(line.as_bytes())
-                                    .expect("Couldn't write to file");
-
-                                write_dir.write_all(b"\n").expect("Couldn't write to file");
-                            }
-                        // write_dir.write_all(lines.as_bytes());
-                        } else {
-                            std::io::copy(&mut file, &mut write_dir).unwrap();
-                        }
+    container: &Path,
+    payload: &[u8],
+) -> Result<(), Error> {
+    let respbytes_cursor = Cursor::new(payload);
+    let artifact_root = container.join(artifact_dir);
+    let invalid = |what: String| -> Error {
+        Error::Zip {
+            artifact: format!("{}/{}", package_id, artifact_dir),
+            reason: what,
+        }
+    };
+    let mut archive = zip::ZipArchive::new(respbytes_cursor)
+        .map_err(|e| invalid(format!("corrupted zip archive: {}", e)))?;
+
+    for i in 0..archive.len() {
+        let mut file = archive
+            .by_index(i)
+            .map_err(|e| invalid(format!("corrupted zip entry {}: {}", i, e)))?;
+
+        //absolute paths, `..` and NUL bytes would write outside the artifact folder
+        let outpath_str = match file.enclosed_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => {
+                return Err(invalid(format!(
+                    "zip entry {:?} has an unsafe path",
+                    file.name()
+                )))
+            }
+        };
+        let outpath: PathBuf = PathBuf::from_slash(&outpath_str);
+        let write_dir = contained_path(&artifact_root, &outpath).ok_or_else(|| {
+            invalid(format!(
+                "zip entry {:?} would be written outside of {}",
+                file.name(),
+                artifact_root.display()
+            ))
+        })?;
+
+        //directory entries, including empty folders like a `src/test/` scaffold
+        if file.is_dir() {
+            if !config.packages.skip_empty_dirs {
+                fs::create_dir_all(&write_dir)?;
+            }
+            continue;
+        }
+
+        if let Some(parent_dir) = write_dir.parent() {
+            fs::create_dir_all(parent_dir)?;
+        }
+        let mut write_dir = fs::File::create(&write_dir)?;
+
+        match config.packages.prop_comment_removal {
+            PropCommentRemoval::Disabled => {
+                std::io::copy(&mut file, &mut write_dir)?;
+            }
+            PropCommentRemoval::Enabled => {
+                if outpath_str.ends_with("parameters.prop") {
+                    let mut prop_content = String::new();
+                    file.read_to_string(&mut prop_content)?;
+
+                    let prop_lines: Vec<&str> = prop_content
+                        .lines()
+                        .filter(|l| !l.starts_with("#"))
+                        .collect();
+
+                    for line in prop_lines {
+                        write_dir.write_all(line.as_bytes())?;
+                        write_dir.write_all(b"\n")?;
                     }
+                } else {
+                    std::io::copy(&mut file, &mut write_dir)?;
                 }
             }
         }
@@ -121,229 +347,763 @@ async fn write_artifact(
     Ok(())
 }
 
+// the payload is downloaded once, "both" writes the zip and extracts it from the same bytes
+async fn write_artifact(
+    package_id: &str,
+    artifact_dir: &str,
+    kind: ArtifactKind,
+    config: &Config,
+    container: &Path,
+    payload: &[u8],
+) -> Result<(), Error> {
+    match config
+        .packages
+        .zip_extraction
+        .for_kind(kind)
+    {
+        ZipExtraction::Disabled => write_zip(container, artifact_dir, payload)?,
+        ZipExtraction::Enabled => extract_zip(package_id, artifact_dir, config, container, payload)?,
+        ZipExtraction::Both => {
+            write_zip(container, artifact_dir, payload)?;
+            extract_zip(package_id, artifact_dir, config, container, payload)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn fetch_artifact_payload(
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    endpoint: http::Endpoint,
+    url: &str,
+    timings: &http::Timings,
+) -> Result<http::Fetched, Error> {
+    let request = client.get(url);
+    authorization
+        .fetch(request, url, endpoint, Some(timings))
+        .await
+}
+
+// the id of the package's artifact that differs from the listed one only in casing
+async fn find_stored_artifact_id(
+    package_id: &str,
+    artifact_id: &str,
+    kind: ArtifactKind,
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    timings: &http::Timings,
+) -> Option<String> {
+    let artifacts =
+        match list_package_artifacts(package_id, kind, config, client, authorization, timings)
+            .await
+        {
+            Ok(artifacts) => artifacts,
+            Err(err) => {
+                log::warn!("Artifact list for the id casing check failed: {}", err);
+                return None;
+            }
+        };
+    artifacts
+        .into_iter()
+        .map(|a| a.id)
+        .find(|id| id != artifact_id && id.eq_ignore_ascii_case(artifact_id))
+}
+
+// the saved versions of an artifact for the error of an unknown pinned version, not every
+// tenant has the Versions feed
+async fn available_versions(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    kind: ArtifactKind,
+    artifact_id: &str,
+    timings: &http::Timings,
+) -> String {
+    let url = kind.versions_url(config, artifact_id);
+    let request = client.get(&url).header("Accept", "application/json");
+    let versions = match authorization
+        .fetch(request, &url, http::Endpoint::ArtifactList(kind), Some(timings))
+        .await
+    {
+        Ok(resp) if resp.status.is_success() => serde_json::from_slice::<APIResponseRoot>(&resp.body)
+            .map(|root| {
+                root.d
+                    .results
+                    .into_iter()
+                    .filter_map(|a| a.version)
+                    .collect::<Vec<_>>()
+            })
+            .ok(),
+        _ => None,
+    };
+    match versions {
+        Some(versions) if !versions.is_empty() => {
+            format!("Available versions: {}", versions.join(", "))
+        }
+        _ => "The available versions couldn't be listed, see the version history in the WebUI."
+            .to_string(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn download_artifact(
     package_id: String,
     artifact_id: String,
+    artifact_dir: String,
+    container: PathBuf,
     config: Config,
     data_dir: std::path::PathBuf,
     client: reqwest::Client,
-    authorization: String,
-    artifact_type: String,
+    authorization: Authorization,
+    kind: ArtifactKind,
+    deployed: Option<runtime::RuntimeArtifact>,
+    pinned_version: Option<String>,
+    listed_version: Option<String>,
     ignore_error_download: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
-    println!(
+    timings: http::Timings,
+) -> Result<ArtifactDownload, Error> {
+    let start = std::time::Instant::now();
+    let ignored = |failure: String| ArtifactDownload {
+        violations: Vec::new(),
+        bytes: 0,
+        duration: start.elapsed(),
+        failure: Some(failure),
+        skipped: None,
+        digest: None,
+        draft: false,
+    };
+    log::info!(
         "- Artifact: {:#?} , from Package: {:#?}",
         artifact_id, package_id
     );
 
-    let api_artifact_payload_url = format!(
-        "https://{host}/api/v1/{artifact_type}(Id='{artifact_id}',Version='Active')/$value",
-        host = config.tenant.management_host,
-        artifact_id = artifact_id,
-        artifact_type = artifact_type
-    );
-    let resp = client
-        .get(&api_artifact_payload_url)
-        .header("Authorization", authorization)
-        .send()
-        .await?;
+    let (mut api_artifact_payload_url, endpoint) = match deployed {
+        Some(_) => (
+            runtime::payload_url(&config, &artifact_id),
+            http::Endpoint::RuntimeArtifactPayload,
+        ),
+        None => (
+            kind.version_payload_url(
+                &config,
+                &artifact_id,
+                pinned_version.as_deref().unwrap_or("Active"),
+            ),
+            http::Endpoint::ArtifactPayload(kind),
+        ),
+    };
+    let mut resp = progress::with_download_label(
+        format!("{} {}", kind, artifact_id),
+        fetch_artifact_payload(
+            &client,
+            &authorization,
+            endpoint,
+            &api_artifact_payload_url,
+            &timings,
+        ),
+    )
+    .await?;
+
+    //some runtime artifacts have no content, they are reported and the sync goes on
+    if deployed.is_some()
+        && resp.status.is_client_error()
+        && resp.status != reqwest::StatusCode::UNAUTHORIZED
+        && resp.status != reqwest::StatusCode::FORBIDDEN
+    {
+        log::warn!(
+            "Deployed artifact {:?} has no downloadable content (API Response Code: {})",
+            artifact_id,
+            resp.status
+        );
+        return Ok(ArtifactDownload {
+            violations: Vec::new(),
+            bytes: 0,
+            duration: start.elapsed(),
+            failure: None,
+            skipped: Some(ArtifactSkipReason::NoRuntimePayload {
+                status: resp.status.as_u16(),
+            }),
+            digest: None,
+            draft: false,
+        });
+    }
+
+    //a pinned version that doesn't exist is a config error, the message lists the saved ones
+    if let (Some(version), reqwest::StatusCode::NOT_FOUND) = (&pinned_version, resp.status) {
+        let message = format!(
+            "Artifact {:?} has no version {:?}. {}",
+            artifact_id,
+            version,
+            available_versions(&config, &client, &authorization, kind, &artifact_id, &timings)
+                .await
+        );
+        log::error!("{}", message);
+        if ignore_error_download {
+            log::warn!("Ignoring error (Ignore Download Error Option: True)");
+            return Ok(ignored(message));
+        }
+        return Err(Error::ConfigValidation(message));
+    }
+
+    //after a copy the listing may show an id casing that $value doesn't resolve
+    let mut stored_id = None;
+    if resp.status == reqwest::StatusCode::NOT_FOUND && pinned_version.is_none() {
+        stored_id = find_stored_artifact_id(
+            &package_id,
+            &artifact_id,
+            kind,
+            &config,
+            &client,
+            &authorization,
+            &timings,
+        )
+        .await;
+        if let Some(stored_id) = &stored_id {
+            log::warn!(
+                "Artifact id case mismatch detected: listed as {:?}, stored as {:?}. Retrying with the stored id.",
+                artifact_id, stored_id
+            );
+            api_artifact_payload_url = kind.payload_url(&config, stored_id);
+            resp = fetch_artifact_payload(
+                &client,
+                &authorization,
+                endpoint,
+                &api_artifact_payload_url,
+                &timings,
+            )
+            .await?;
+        }
+    }
+
+    //an artifact that was only saved as draft has no Active version, the listing shows "Draft"
+    let draft_only = listed_version
+        .as_deref()
+        .is_some_and(|v| v.eq_ignore_ascii_case("Draft"));
+    let mut draft = false;
+    if resp.status == reqwest::StatusCode::NOT_FOUND
+        && draft_only
+        && pinned_version.is_none()
+        && deployed.is_none()
+    {
+        match config.packages.draft_handling {
+            DraftHandling::Download => {
+                log::warn!(
+                    "Artifact {:?} has no Active version, downloading its draft (draft_handling: download)",
+                    artifact_id
+                );
+                api_artifact_payload_url = kind.version_payload_url(
+                    &config,
+                    stored_id.as_deref().unwrap_or(&artifact_id),
+                    listed_version.as_deref().unwrap_or("Draft"),
+                );
+                resp = fetch_artifact_payload(
+                    &client,
+                    &authorization,
+                    endpoint,
+                    &api_artifact_payload_url,
+                    &timings,
+                )
+                .await?;
+                draft = true;
+            }
+            DraftHandling::Skip => {
+                log::warn!(
+                    "Artifact {:?} has no Active version, only a draft. Skipped (draft_handling: skip)",
+                    artifact_id
+                );
+                return Ok(ArtifactDownload {
+                    violations: Vec::new(),
+                    bytes: 0,
+                    duration: start.elapsed(),
+                    failure: None,
+                    skipped: Some(ArtifactSkipReason::Draft),
+                    digest: None,
+                    draft: false,
+                });
+            }
+            DraftHandling::Error => {
+                let message = format!(
+                    "Artifact {:?} has no Active version, only a draft. Save a version in the WebUI or set packages.draft_handling to \"download\" or \"skip\"",
+                    artifact_id
+                );
+                log::error!("{}", message);
+                if ignore_error_download {
+                    log::warn!("Ignoring error (Ignore Download Error Option: True)");
+                    return Ok(ignored(message));
+                }
+                return Err(Error::Limit(message));
+            }
+        }
+    }
 
-    let resp_success = &resp.status().is_success();
-    let resp_code = resp.status();
+    let resp_success = &resp.status.is_success();
+    let resp_code = resp.status;
 
     if !resp_success{
-        println!("Artifact Download Failed!");
-        println!("API URL: {}", &api_artifact_payload_url);
-        println!("API Response Code: {:#?}", &resp_code);
+        log::error!("Artifact Download Failed!");
+        log::error!("API URL: {}", &api_artifact_payload_url);
+        log::error!("API Response Code: {:#?}", &resp_code);
+        if let Some(stored_id) = &stored_id {
+            log::error!(
+                "Artifact missing: neither {:?} (listed) nor {:?} (stored) can be downloaded",
+                artifact_id, stored_id
+            );
+        }
     }
     if !resp_success && ignore_error_download{
-        println!("Ignoring error (Ignore Download Error Option: True)");
+        log::warn!("Ignoring error (Ignore Download Error Option: True)");
+        return Ok(ignored(format!("API Response Code: {}", resp_code)));
     }
     if !resp_success && !ignore_error_download{
-        println!("Response Body:");
-        let body_text = resp.text().await?;
-        println!("{}", &body_text);
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "API Artifact Download Failed!",
-        )
-        .into());
+        let body_text = resp.text();
+        logging::response_body(&body_text);
+        let what = match &stored_id {
+            Some(stored_id) => format!(
+                "Download of artifact {:?} (missing as {:?} and {:?})",
+                artifact_id, artifact_id, stored_id
+            ),
+            None => format!("Download of artifact {:?}", artifact_id),
+        };
+        return Err(Error::api_request(&what, &api_artifact_payload_url, resp_code, &body_text));
     }
 
     if *resp_success{
-        let respbytes = resp.bytes().await?;
-        let respbytes_cursor = Cursor::new(respbytes.deref());
+        let content_type = resp
+            .headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        let respbytes = resp.body;
+
+        //some gateways answer 200 with an HTML error page
+        if !config.packages.accept_any_content && !is_zip_content(&respbytes) {
+            log::error!("Artifact Download Failed! Response is not a zip file.");
+            log::error!("API URL: {}", &api_artifact_payload_url);
+            log::error!("Content-Type: {}", &content_type);
+            let body = String::from_utf8_lossy(&respbytes);
+            logging::response_body(&body);
+            //the start of the page says what the gateway answered, redacted like the support bundle
+            let body_prefix = error::body_prefix(&body, error::NOT_A_ZIP_BODY_CHARS);
+            if ignore_error_download {
+                log::warn!("Ignoring error (Ignore Download Error Option: True)");
+                return Ok(ignored(format!(
+                    "Response is not a zip file (Content-Type: {}): {}",
+                    content_type, body_prefix
+                )));
+            }
+            return Err(Error::api_response(
+                &format!("Download of artifact {:?}", artifact_id),
+                &api_artifact_payload_url,
+                format!("not a zip file (Content-Type: {}): {}", content_type, body_prefix),
+            ));
+        }
 
+        let bytes = respbytes.len() as u64;
         write_artifact(
             &package_id,
-            &artifact_id,
+            &artifact_dir,
+            kind,
             &config,
-            &data_dir,
-            respbytes_cursor,
+            &container,
+            respbytes.deref(),
         )
         .await?;
+
+        if let Some(deployed) = &deployed {
+            runtime::write_deployment(&container, &artifact_dir, deployed)?;
+        }
+
+        if kind == ArtifactKind::Iflow && config.packages.include_configurations {
+            let configured = configurations::download_configurations(
+                &config,
+                &client,
+                &authorization,
+                &timings,
+                &container,
+                stored_id.as_deref().unwrap_or(&artifact_id),
+                pinned_version.as_deref().unwrap_or("Active"),
+                &artifact_dir,
+            )
+            .await;
+            match configured {
+                Ok(0) => {}
+                Ok(count) => log::debug!(
+                    "{} externalized parameter(s) of {:?} written",
+                    count,
+                    artifact_id
+                ),
+                Err(err) if ignore_error_download => {
+                    log::warn!("{}", err);
+                    log::warn!("Ignoring error (Ignore Download Error Option: True)");
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        permissions::apply_artifact(&config, &container, &artifact_dir)?;
+
+        //disabled validation never reads the archive a second time
+        let mut violations = Vec::new();
+        if config.packages.validate_content {
+            let files = validate::archive_files(&respbytes)?;
+            violations = validate::check_artifact(
+                &package_id,
+                &artifact_id,
+                kind.entity_set(),
+                &files,
+                &config.packages.validate_content_checks,
+            );
+            if !violations.is_empty() && config.packages.validate_content_strict {
+                log::error!("Artifact Content Validation Failed!");
+                for v in &violations {
+                    log::error!("{}: {}", v.check, v.message);
+                }
+                if !ignore_error_download {
+                    return Err(Error::Validation(format!(
+                        "Artifact Content Validation Failed! {}",
+                        artifact_id
+                    )));
+                }
+                log::warn!("Ignoring error (Ignore Download Error Option: True)");
+            }
+        }
+        //the local hash covers the configurations file written into the folder
+        let digest = match config.packages.manifest {
+            true => Some(manifest::digest(
+                &config,
+                &data_dir,
+                &container,
+                &artifact_dir,
+                kind,
+                &respbytes,
+                stored_id.as_deref(),
+            )?),
+            false => None,
+        };
+        return Ok(ArtifactDownload {
+            violations,
+            bytes,
+            duration: start.elapsed(),
+            failure: None,
+            skipped: None,
+            digest,
+            draft,
+        });
     }
-    Ok(())
+    Ok(ignored(format!("API Response Code: {}", resp_code)))
 }
 
-async fn process_package_artifacts(
+async fn list_package_artifacts(
     package_id: &str,
-    artifact_type: &str,
+    kind: ArtifactKind,
     config: &Config,
     client: &reqwest::Client,
-    authorization: &str,
-    data_dir: &std::path::PathBuf,
-    ignore_error_download: &bool,
-) -> Result<
-    Vec<impl Future<Output = Result<(), Box<dyn std::error::Error>>>>,
-    Box<dyn std::error::Error>,
-> {
-    let api_package_artifact_list_url = format!(
-        "https://{host}/api/v1/IntegrationPackages('{package_id}')/{artifact_type}",
-        host = config.tenant.management_host,
-        package_id = package_id,
-        artifact_type = artifact_type
-    );
-    let resp = client
+    authorization: &Authorization,
+    timings: &http::Timings,
+) -> Result<Vec<APIResponseResult>, Error> {
+    let api_package_artifact_list_url = kind.list_url(config, package_id);
+    let request = client
         .get(&api_package_artifact_list_url)
-        .header("Authorization", authorization)
-        .header("Accept", "application/json")
-        .send()
+        .header("Accept", "application/json");
+    let resp = authorization
+        .fetch(
+            request,
+            &api_package_artifact_list_url,
+            http::Endpoint::ArtifactList(kind),
+            Some(timings),
+        )
         .await?;
 
-    let resp_success = &resp.status().is_success();
-    let resp_code = resp.status();
+    let resp_success = &resp.status.is_success();
+    let resp_code = resp.status;
 
-    let body_text = resp.text().await?;
+    let body_text = resp.text();
 
     if !resp_success {
-        println!("API Package List Artifacts Failed!");
-        println!("Artifact type: {}", &artifact_type);
-        println!("API URL: {}", &api_package_artifact_list_url);
-        println!("API Response Code: {:#?}", &resp_code);
-        println!("Response Body:");
-        println!("{}", &body_text);
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "API Package List Artifacts Failed!",
-        )
-        .into());
+        log::error!("API Package List Artifacts Failed!");
+        log::error!("Artifact type: {}", kind);
+        log::error!("API URL: {}", &api_package_artifact_list_url);
+        log::error!("API Response Code: {:#?}", &resp_code);
+        logging::response_body(&body_text);
+        return Err(Error::api_request(
+            &format!("Artifact list ({})", kind),
+            &api_package_artifact_list_url,
+            resp_code,
+            &body_text,
+        ));
     }
 
     let resp_obj: APIResponseRoot = match serde_json::from_slice(body_text.as_bytes()) {
         Ok(api_resp) => api_resp,
         Err(err) => {
-            println!("API Package List Artifacts Parse Failed!");
-            println!("Artifact type: {}", &artifact_type);
-            println!("API URL: {}", &api_package_artifact_list_url);
-            println!("API Response Code: {:#?}", &resp_code);
-            println!("Response Body:");
-            println!("{}", &body_text);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, err).into());
+            log::error!("API Package List Artifacts Parse Failed!");
+            log::error!("Artifact type: {}", kind);
+            log::error!("API URL: {}", &api_package_artifact_list_url);
+            log::error!("API Response Code: {:#?}", &resp_code);
+            logging::response_body(&body_text);
+            return Err(Error::api_response(
+                &format!("Artifact list ({})", kind),
+                &api_package_artifact_list_url,
+                err,
+            ));
         }
     };
 
+    Ok(resp_obj.d.results)
+}
+
+// the download tasks of the selected artifacts of a package, each with the folder it resolved to
+#[allow(clippy::too_many_arguments)]
+fn artifact_tasks(
+    package_id: &str,
+    artifacts: Vec<ArtifactSource>,
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    data_dir: &Path,
+    ignore_error_download: &bool,
+    timings: &http::Timings,
+) -> Vec<ArtifactTask<impl Future<Output = Result<ArtifactDownload, Error>>>> {
     let mut tasks = Vec::new();
-    for artifact in resp_obj.d.results {
-        tasks.push(download_artifact(
-            package_id.to_owned(),
-            artifact.id.to_owned(),
-            config.clone(),
-            data_dir.clone(),
-            client.clone(),
-            authorization.to_string(),
-            artifact_type.to_string(),
-            *ignore_error_download,
-        ));
+    for ArtifactSource {
+        kind,
+        artifact,
+        artifact_dir,
+        container,
+        deployed,
+        pinned_version,
+    } in artifacts
+    {
+        tasks.push(ArtifactTask {
+            kind,
+            artifact_id: artifact.id.to_owned(),
+            artifact_dir: artifact_dir.clone(),
+            container: container.clone(),
+            zip_extraction: config.packages.zip_extraction.for_kind(kind).clone(),
+            //the deployed version with packages.source "deployed"
+            version: match (&pinned_version, &deployed) {
+                (Some(pinned_version), _) => Some(pinned_version.clone()),
+                (None, Some(deployed)) => deployed.version.clone(),
+                (None, None) => artifact.version.clone(),
+            },
+            active_version: artifact.version.clone(),
+            modified_at: artifact.modified_at.clone(),
+            download: download_artifact(
+                package_id.to_owned(),
+                artifact.id.to_owned(),
+                artifact_dir,
+                container,
+                config.clone(),
+                data_dir.to_path_buf(),
+                client.clone(),
+                authorization.clone(),
+                kind,
+                deployed,
+                pinned_version,
+                artifact.version.clone(),
+                *ignore_error_download,
+                timings.clone(),
+            ),
+        });
     }
-    Ok(tasks)
+    tasks
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn process_package(
     package_id: &str,
+    package_name: &str,
     config: &Config,
     client: &reqwest::Client,
-    authorization: &str,
-    data_dir: &std::path::PathBuf,
+    authorization: &Authorization,
+    data_dir: &Path,
     ignore_error_download: &bool,
+    selection: &filter::ArtifactSelection,
+    runtime_artifacts: Option<&[runtime::RuntimeArtifact]>,
+    timings: &http::Timings,
 ) -> Result<
-    Vec<impl Future<Output = Result<(), Box<dyn std::error::Error>>>>,
-    Box<dyn std::error::Error>,
+    (
+        String,
+        Vec<ArtifactTask<impl Future<Output = Result<ArtifactDownload, Error>>>>,
+        Vec<ArtifactSkip>,
+    ),
+    Error,
 > {
-    //remove local package contents before download
-    let package_dir = data_dir.join(&package_id);
-    remove_dir_all::ensure_empty_dir(&package_dir)?;
-    // let _ = fs::remove_dir_all(package_dir);
-
-    println!("Processing Package: {:?}", package_id);
+    log::info!("Processing Package: {:?}", package_id);
+    //the downloads of the package write with the zip_extraction of its rule
+    let rule_config;
+    let config = match &selection.zip_extraction {
+        Some(zip_extraction) => {
+            let mut package_config = config.clone();
+            package_config.packages.zip_extraction = zip_extraction.clone();
+            rule_config = package_config;
+            &rule_config
+        }
+        None => config,
+    };
 
-    let mut tasks1 = process_package_artifacts(
-        package_id,
-        "IntegrationDesigntimeArtifacts",
-        config,
-        client,
-        authorization,
-        data_dir,
-        ignore_error_download,
-    )
-    .await?;
+    //all types are listed first, artifacts of different types can share an id
+    let mut selected = Vec::new();
+    let mut skipped = Vec::new();
+    for kind in ArtifactKind::ALL.iter().filter(|k| k.enabled(config)) {
+        let artifacts =
+            list_package_artifacts(package_id, *kind, config, client, authorization, timings)
+                .await?;
+        for artifact in artifacts {
+            if let Some(reason) =
+                selection.skip_reason(config, &artifact.id, artifact.modified_at.as_deref())
+            {
+                skipped.push(ArtifactSkip {
+                    package_id: package_id.to_string(),
+                    artifact_id: artifact.id.clone(),
+                    artifact_type: kind.to_string(),
+                    reason,
+                });
+                continue;
+            }
+            //packages.source "deployed" only syncs what runs on the tenant
+            let deployed = match runtime_artifacts {
+                Some(runtime_artifacts) => match runtime::find(runtime_artifacts, *kind, &artifact.id) {
+                    Some(deployed) => Some(deployed.clone()),
+                    None => {
+                        skipped.push(ArtifactSkip {
+                            package_id: package_id.to_string(),
+                            artifact_id: artifact.id.clone(),
+                            artifact_type: kind.to_string(),
+                            reason: ArtifactSkipReason::NotDeployed,
+                        });
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            selected.push((*kind, artifact, deployed));
+        }
+    }
 
-    let mut tasks2 = process_package_artifacts(
+    let ids: Vec<(ArtifactKind, &str)> = selected
+        .iter()
+        .map(|(kind, artifact, _)| (*kind, artifact.id.as_str()))
+        .collect();
+    let dirs = artifact::artifact_dirs(package_id, &ids, config.packages.type_collision)?;
+    let mut resolved = Vec::new();
+    let location = |kind, artifact_dir: &str| {
+        layout::artifact_location(
+            config.packages.layout.as_deref(),
+            data_dir,
+            &layout::LayoutValues {
+                package_id,
+                package_name,
+                artifact_dir,
+                kind,
+            },
+        )
+    };
+    for ((kind, artifact, deployed), dir) in selected.into_iter().zip(dirs) {
+        match dir {
+            //a pinned version gets its own folder next to the active one
+            Ok(artifact_dir) => {
+                for version in selection.versions(&artifact.id) {
+                    match version {
+                        Some(version) => {
+                            let (container, artifact_dir) =
+                                location(kind, &format!("{}_{}", artifact_dir, version));
+                            resolved.push(ArtifactSource {
+                                kind,
+                                artifact_dir,
+                                container,
+                                artifact: artifact.clone(),
+                                deployed: None,
+                                pinned_version: Some(version),
+                            })
+                        }
+                        None => {
+                            let (container, artifact_dir) = location(kind, &artifact_dir);
+                            resolved.push(ArtifactSource {
+                                kind,
+                                artifact_dir,
+                                container,
+                                artifact: artifact.clone(),
+                                deployed: deployed.clone(),
+                                pinned_version: None,
+                            })
+                        }
+                    }
+                }
+            }
+            Err(reason) => skipped.push(ArtifactSkip {
+                package_id: package_id.to_string(),
+                artifact_id: artifact.id.clone(),
+                artifact_type: kind.to_string(),
+                reason,
+            }),
+        }
+    }
+    let tasks = artifact_tasks(
         package_id,
-        "ValueMappingDesigntimeArtifacts",
+        resolved,
         config,
         client,
         authorization,
         data_dir,
         ignore_error_download,
-    )
-    .await?;
+        timings,
+    );
 
-    tasks1.append(&mut tasks2);
-    Ok(tasks1)
+    if tasks.is_empty() && !skipped.is_empty() {
+        log::info!(
+            "Package {:?}: no artifacts left after filtering ({} skipped)",
+            package_id,
+            skipped.len()
+        );
+    }
+    Ok((package_id.to_string(), tasks, skipped))
 }
 
 async fn get_all_packages(
     config: &Config,
     client: &reqwest::Client,
-    authorization: &str,
-) -> Result<APIResponseRoot, Box<dyn std::error::Error>> {
-    let api_package_list_url = format!(
-        "https://{host}/api/v1/IntegrationPackages",
-        host = config.tenant.management_host
-    );
-    let resp = client
+    authorization: &Authorization,
+    timings: Option<&http::Timings>,
+) -> Result<APIResponseRoot, Error> {
+    let api_package_list_url = format!("{api}IntegrationPackages", api = check_api_url(config));
+    let request = client
         .get(&api_package_list_url)
-        .header("Authorization", authorization)
-        .header("Accept", "application/json")
-        .send()
+        .header("Accept", "application/json");
+    let resp = authorization
+        .fetch(
+            request,
+            &api_package_list_url,
+            http::Endpoint::PackageList,
+            timings,
+        )
         .await?;
 
-    let resp_success = &resp.status().is_success();
-    let resp_code = resp.status();
+    let resp_success = &resp.status.is_success();
+    let resp_code = resp.status;
 
-    let body_text = resp.text().await?;
+    let body_text = resp.text();
 
     if !resp_success {
-        println!("Package List Failed!");
-        println!("API URL: {}", &api_package_list_url);
-        println!("API Response Code: {:#?}", &resp_code);
-        println!("Response Body:");
-        println!("{}", &body_text);
+        log::error!("Package List Failed!");
+        log::error!("API URL: {}", &api_package_list_url);
+        log::error!("API Response Code: {:#?}", &resp_code);
+        logging::response_body(&body_text);
         return Err(
-            std::io::Error::new(std::io::ErrorKind::Other, "API Package List  Failed!").into(),
+            Error::api_request("Package list", &api_package_list_url, resp_code, &body_text),
         );
     }
 
     let resp_obj: APIResponseRoot = match serde_json::from_slice(body_text.as_bytes()) {
         Ok(api_resp) => api_resp,
         Err(err) => {
-            println!("Package List Failed!");
-            println!("API URL: {}", &api_package_list_url);
-            println!("API Response Code: {:#?}", &resp_code);
-            println!("Response Body:");
-            println!("{}", &body_text);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, err).into());
+            log::error!("Package List Failed!");
+            log::error!("API URL: {}", &api_package_list_url);
+            log::error!("API Response Code: {:#?}", &resp_code);
+            logging::response_body(&body_text);
+            return Err(Error::api_response("Package list", &api_package_list_url, err));
         }
     };
     //println!("{:?}", &resp_obj);
@@ -351,292 +1111,1067 @@ async fn get_all_packages(
     Ok(resp_obj)
 }
 
-pub async fn run_with_config(
-    config: &Config,
-    config_path: &String,
-    no_input: bool,
-    ignore_error_download: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
-    //println!("config: {:?}", config);
-    //println!("Using input file: {:?}", opts);
+// the prefix of every API URL with a trailing '/', also the URL of the API first check
+pub(crate) fn check_api_url(config: &Config) -> String {
+    match &config.tenant.api_base_url {
+        Some(base) => format!("{}/", base),
+        None => format!(
+            "https://{host}/api/v1/",
+            host = &config.tenant.management_host
+        ),
+    }
+}
 
-    let now = tokio::time::Instant::now();
+fn secret_fingerprint(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    let len = chars.len();
+    //short secrets would be mostly revealed by a fingerprint
+    if len < 8 {
+        return format!("{} characters", len);
+    }
+    format!(
+        "{} characters, {}{}...{}{}",
+        len,
+        chars[0],
+        chars[1],
+        chars[len - 2],
+        chars[len - 1]
+    )
+}
 
-    let client = reqwest::Client::new();
+// pasted secrets often carry trailing CR/LF or spaces from the clipboard
+fn read_secret<F>(mut prompt: F, confirm_secret: bool) -> Result<String, Error>
+where
+    F: FnMut(&str) -> std::io::Result<String>,
+{
+    let pass = prompt("Password: ")?;
+    let secret = pass.trim().to_string();
+    if secret.len() != pass.len() {
+        println!("Note: removed surrounding whitespace and line breaks from the entered secret.");
+    }
 
-    // let mut authorization: Option<String> = None;
+    if confirm_secret {
+        println!("Entered secret: {}", secret_fingerprint(&secret));
+        let again = prompt("Confirm password: ")?;
+        if again.trim() != secret {
+            return Err(Error::Auth("Entered secrets do not match".to_string()));
+        }
+    }
 
-    let mut password: Option<String> = None;
+    Ok(secret)
+}
 
-    //get secret from environment variable
-    match &config.tenant.credential {
-        CredentialInside::SUser(c) => {
-            match &c.password_environment_variable {
-                Some(varkey) => {
-                    match env::var(varkey) {
-                        Ok(val) => {
-                            password = Some(val);
-                        }
-                        Err(e) => {
-                            println!(
-                                "Can not find S-user Pass in environment variable: {}: {}",
-                                &varkey, e
-                            );
-                            // return Err(e.into());
-                        }
-                    };
-                }
-                None => (),
-            };
+// a missing environment variable only warns, a configured file that can't be read is an error
+fn configured_secret(
+    what: &str,
+    varkey: Option<&str>,
+    file: Option<&str>,
+) -> Result<Option<String>, Error> {
+    if let Some(varkey) = varkey {
+        match env::var(varkey) {
+            Ok(val) => return Ok(Some(val)),
+            Err(e) => {
+                log::warn!(
+                    "Can not find {} in environment variable: {}: {}",
+                    what, varkey, e
+                );
+            }
         }
-        CredentialInside::OauthClientCredentials(c) => {
-            match &c.client_secret_environment_variable {
-                Some(varkey) => {
-                    match env::var(varkey) {
-                        Ok(val) => {
-                            password = Some(val);
-                        }
-                        Err(e) => {
-                            println!(
-                                "Can not find Client Secret environment variable: {}: {}",
-                                &varkey, e
-                            );
-                        }
-                    };
-                }
-                None => (),
-            };
+    }
+    if let Some(file) = file {
+        let content = fs::read_to_string(file).map_err(|e| {
+            Error::Auth(format!("Can not read {} from file {}: {}", what, file, e))
+        })?;
+        //mounted secrets usually end with a line break
+        let secret = content.trim_end_matches(['\r', '\n']);
+        if secret.is_empty() {
+            return Err(Error::Auth(format!("{} file {} is empty", what, file)));
         }
+        return Ok(Some(secret.to_string()));
     }
+    Ok(None)
+}
+
+pub(crate) fn get_password(
+    config: &Config,
+    no_input: bool,
+    confirm_secret: bool,
+) -> Result<String, Error> {
+    //the environment variable comes before the file, the prompt is the last resort
+    let mut password: Option<String> = match config.tenant.credential.active() {
+        CredentialInside::SUser(c) => configured_secret(
+            "S-user Pass",
+            c.password_environment_variable.as_deref(),
+            c.password_file.as_deref(),
+        )?,
+        CredentialInside::OauthClientCredentials(c) => configured_secret(
+            "Client Secret",
+            c.client_secret_environment_variable.as_deref(),
+            c.client_secret_file.as_deref(),
+        )?,
+        CredentialInside::ApiKey(c) => configured_secret(
+            "API Key",
+            c.api_key_environment_variable.as_deref(),
+            c.api_key_file.as_deref(),
+        )?,
+        //a service key with a certificate has no secret
+        CredentialInside::ServiceKey(c) => {
+            let key = service_key::read(c)?;
+            Some(key.client_secret.unwrap_or_default())
+        }
+        //the "secret" is the passphrase of a PKCS#12 file, a PEM key has none
+        CredentialInside::OauthClientCertificate(c) => match &c.pkcs12_path {
+            None => Some(String::new()),
+            Some(_) => match configured_secret(
+                "PKCS#12 passphrase",
+                c.pkcs12_passphrase_environment_variable.as_deref(),
+                None,
+            )? {
+                Some(passphrase) => Some(passphrase),
+                None if no_input => Some(String::new()),
+                None => None,
+            },
+        },
+    };
 
-    let username: String = match &config.tenant.credential {
+    let username: String = match config.tenant.credential.active() {
         CredentialInside::OauthClientCredentials(c) => c.client_id.to_string(),
+        CredentialInside::OauthClientCertificate(c) => c.client_id.to_string(),
+        CredentialInside::ServiceKey(_) => "service key".to_string(),
         CredentialInside::SUser(c) => c.username.to_string(),
+        CredentialInside::ApiKey(c) => format!("{} header", c.header_name),
     };
     //try to get password from command line
-    if !no_input {
-        match &password {
-            None => {
-                let message = format!(
-                    "Would you like to enter a password for user: {user} to connect host: {host}?",
-                    user = username,
-                    host = config.tenant.management_host
-                );
+    if !no_input && password.is_none() {
+        let message = format!(
+            "Would you like to enter a password for user: {user} to connect host: {host}?",
+            user = username,
+            host = config.tenant.management_host
+        );
 
-                println!("{}", message);
+        println!("{}", message);
 
-                let pass = rpassword::prompt_password_stdout("Password: ")?;
-                password = Some(pass);
-                //println!("Your password is {}", pass);
-            }
-            _ => {}
-        }
+        let pass = read_secret(rpassword::prompt_password_stdout, confirm_secret)?;
+        password = Some(pass);
+        //println!("Your password is {}", pass);
     }
 
-    let password: String = match password {
-        Some(p) => p,
-        None => {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Could not use any password/secret",
-            )
-            .into())
-        }
-    };
+    match password {
+        Some(p) => Ok(p),
+        None => Err(Error::Auth("Could not use any password/secret".to_string())),
+    }
+}
 
-    let check_api_url = format!(
-        "https://{host}/api/v1/",
-        host = &config.tenant.management_host
-    );
+// the token request of the OAuth credentials, the access token becomes the bearer authorization
+async fn request_token(
+    config: &Config,
+    request: reqwest::RequestBuilder,
+    timings: Option<&http::Timings>,
+) -> Result<AuthResult, Error> {
+    let resp = http::fetch(config, request, "Token API", http::Endpoint::Token, timings).await?;
+
+    let resp_code = resp.status;
+    if !resp_code.is_success() {
+        return Err(Error::Auth(format!("Token API Failed! API Response Code: {}", resp_code)));
+    }
+    let respbody: TokenAPIResponseRoot = serde_json::from_slice(&resp.body)?;
 
+    Ok(AuthResult {
+        authorization: format!("Bearer {token}", token = respbody.access_token),
+        expires_in: respbody.expires_in,
+        scope: respbody.scope,
+    })
+}
+
+pub(crate) async fn authenticate(
+    config: &Config,
+    client: &reqwest::Client,
+    password: &str,
+    timings: Option<&http::Timings>,
+) -> Result<AuthResult, Error> {
     //for oauth we need to get the token
-    let authorization = match &config.tenant.credential {
+    let auth_result = match config.tenant.credential.active() {
         CredentialInside::OauthClientCredentials(c) => {
             let api_token_url = format!(
                 "{url}?grant_type=client_credentials",
                 url = c.token_endpoint_url
             );
-            let auth = basic_auth(&c.client_id, &password);
+            let auth = basic_auth(&c.client_id, password);
 
-            let resp = client
-                .post(&api_token_url)
-                .header("Authorization", auth)
-                .send()
-                .await?;
-            println!("Token API status: {:?}", resp.status());
-            let respbody = resp.json::<TokenAPIResponseRoot>().await?;
+            let request = client.post(&api_token_url).header("Authorization", auth);
+            request_token(config, request, timings).await?
+        }
+        //mTLS: the certificate authenticates the client, the token is used like a client secret's
+        CredentialInside::OauthClientCertificate(c) => {
+            //a certificate that can't be loaded is a config error, not a rejected credential
+            let token_client = http::read_client_identity(c, password)
+                .and_then(|identity| http::certificate_client(config, &identity))?;
+            let api_token_url = format!(
+                "{url}?grant_type=client_credentials&client_id={client_id}",
+                url = c.token_endpoint_url,
+                client_id = c.client_id
+            );
 
-            format!("Bearer {token}", token = respbody.access_token)
+            let request = token_client.post(&api_token_url);
+            request_token(config, request, timings).await?
         }
-        CredentialInside::SUser(c) => basic_auth(&c.username, &password),
+        //the secret was read with the key by get_password, a certificate key authenticates by mTLS
+        CredentialInside::ServiceKey(c) => {
+            let key = service_key::read(c)?;
+            let request = match (&key.client_secret, &key.certificate, &key.key) {
+                (None, Some(certificate), Some(private_key)) => {
+                    let identity = http::ClientIdentity::Pem {
+                        certificate: certificate.as_bytes().to_vec(),
+                        key: private_key.as_bytes().to_vec(),
+                    };
+                    let token_client = http::certificate_client(config, &identity)?;
+                    token_client.post(format!(
+                        "{url}?grant_type=client_credentials&client_id={client_id}",
+                        url = key.token_url,
+                        client_id = key.client_id
+                    ))
+                }
+                _ => client
+                    .post(format!(
+                        "{url}?grant_type=client_credentials",
+                        url = key.token_url
+                    ))
+                    .header("Authorization", basic_auth(&key.client_id, password)),
+            };
+            request_token(config, request, timings).await?
+        }
+        CredentialInside::SUser(c) => AuthResult {
+            authorization: basic_auth(&c.username, password),
+            expires_in: None,
+            scope: None,
+        },
+        //APIM fronted tenants: the key itself is the header value, no token or basic auth
+        CredentialInside::ApiKey(_) => AuthResult {
+            authorization: password.to_string(),
+            expires_in: None,
+            scope: None,
+        },
     };
 
-    let resp = client
-        .get(&check_api_url)
-        .header("Authorization", &authorization)
-        .send()
-        .await?;
+    Ok(auth_result)
+}
 
-    let resp_success = &resp.status().is_success();
-    let resp_code = resp.status();
+// the returned config holds the credential picked from tenant.credential, use it for the rest of the run
+pub(crate) async fn get_authorization(
+    config: &Config,
+    client: &reqwest::Client,
+    no_input: bool,
+    confirm_secret: bool,
+) -> Result<(Config, Authorization), Error> {
+    let selected = auth::select_credential(config, client, no_input, confirm_secret, None).await?;
+    let authorization = Authorization::new(
+        &selected.config,
+        client,
+        &selected.password,
+        &selected.auth_result,
+        None,
+    );
+    Ok((selected.config, authorization))
+}
 
-    if !resp_success {
-        println!("API First Check Failed!");
-        println!("API Response Code: {:#?}", resp_code);
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, "API Check Failed!").into());
-    } else {
-        println!("API First Check Successful.");
+// header that carries the value of an `Authorization`
+pub(crate) fn authorization_header(config: &Config) -> &str {
+    match config.tenant.credential.active() {
+        CredentialInside::ApiKey(c) => &c.header_name,
+        _ => "Authorization",
     }
+}
+
+// a dry run authenticates, lists and evaluates the rules like a sync, but writes nothing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DryRun {
+    Packages,
+    // also lists the artifacts of every selected package
+    Artifacts,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn run_with_config(
+    config: &Config,
+    config_path: &str,
+    no_input: bool,
+    yes: bool,
+    ignore_error_download: bool,
+    confirm_secret: bool,
+    dry_run: Option<DryRun>,
+    print_timings: bool,
+    checkpoint_path: Option<&Path>,
+    report_path: Option<&Path>,
+    cancel: &CancellationToken,
+) -> Result<RunStats, Error> {
+    let mut stats = RunStats::default();
+    let mut report = ReportBuilder::new(config);
+    //a dry run lists what a sync would do, it has nothing to report
+    let report_path = report_path.filter(|_| dry_run.is_none());
+    let result = sync(
+        config,
+        config_path,
+        no_input,
+        yes,
+        ignore_error_download,
+        confirm_secret,
+        dry_run,
+        print_timings,
+        checkpoint_path,
+        cancel,
+        &mut stats,
+        &mut report,
+    )
+    .await;
+
+    //the report is also written for a failed run, a write error doesn't hide the run's error
+    if let Some(path) = report_path {
+        let run_report = report.finish(&stats, result.as_ref().err());
+        match run_report.write(path) {
+            Ok(()) => log::info!("Report written to {}", path.display()),
+            Err(err) if result.is_err() => {
+                log::error!("Can not write report {}: {}", path.display(), err)
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    result.map(|()| stats)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn sync(
+    config: &Config,
+    config_path: &str,
+    no_input: bool,
+    yes: bool,
+    ignore_error_download: bool,
+    confirm_secret: bool,
+    dry_run: Option<DryRun>,
+    print_timings: bool,
+    checkpoint_path: Option<&Path>,
+    cancel: &CancellationToken,
+    stats: &mut RunStats,
+    report: &mut ReportBuilder,
+) -> Result<(), Error> {
+    //println!("config: {:?}", config);
+    //println!("Using input file: {:?}", opts);
+
+    let now = tokio::time::Instant::now();
+    let cancelled = |stats: &RunStats| -> Error {
+        Cancelled {
+            stats: stats.clone(),
+        }
+        .into()
+    };
+
+    let client = http::build_client(config)?;
+
+    //requests of the sync are timed per endpoint, printed with --timings
+    let timings = http::Timings::default();
+
+    let selected =
+        auth::select_credential(config, &client, no_input, confirm_secret, Some(&timings)).await?;
+    let config = &selected.config;
+    let auth_result = &selected.auth_result;
+    let authorization =
+        Authorization::new(config, &client, &selected.password, auth_result, Some(&timings));
+
+    //a credential chain already checked the API with the credential it picked
+    if !selected.api_checked {
+        let api_check_url = check_api_url(config);
+        let request = client.get(&api_check_url);
+        let resp = authorization
+            .fetch(
+                request,
+                &api_check_url,
+                http::Endpoint::ApiCheck,
+                Some(&timings),
+            )
+            .await?;
+
+        let resp_code = resp.status;
+        if !resp_code.is_success() {
+            log::error!("API First Check Failed!");
+            log::error!("API Response Code: {:#?}", resp_code);
+            return Err(Error::api_check(&api_check_url, resp_code, &resp.text()));
+        }
+    }
+    log::info!("API First Check Successful.");
+
+    let identity = selected.identity();
+    log::info!("Authenticated as: {}", identity);
+    report.identity(&identity);
 
     //https://doc.rust-lang.org/std/fs/fn.canonicalize.html
 
-    let normalized_localdir = normalize_path(Path::new(&config.packages.local_dir));
-    let mut data_dir = std::path::PathBuf::from(".");
-    //config path as starting point:
-    data_dir.push(normalize_path(Path::new(&config_path)));
-    data_dir = data_dir.parent().unwrap().to_path_buf();
+    let mut data_dir = local_data_dir(config, config_path);
 
-    //localdir can be relative or absolute
-    data_dir.push(normalized_localdir);
+    if dry_run.is_none() {
+        tokio::fs::create_dir_all(&data_dir).await?;
+        //UNC paths for long windows paths over 260 chars
+        data_dir = data_dir.canonicalize().unwrap();
+    }
+
+    if cancel.is_cancelled() {
+        return Err(cancelled(stats));
+    }
+    let api_package_list =
+        get_all_packages(config, &client, &authorization, Some(&timings)).await?;
+
+    //--interactive-select: the packages picked in the menu replace filter_rules like --package
+    let picked_config;
+    let config = match select::is_enabled() {
+        true => {
+            let picked = select::pick_packages(&api_package_list.d.results)?;
+            let mut config = config.clone();
+            filter::select_packages(&mut config, &picked);
+            picked_config = config;
+            &picked_config
+        }
+        false => config,
+    };
 
-    tokio::fs::create_dir_all(&data_dir).await?;
-    //UNC paths for long windows paths over 260 chars
-    data_dir = data_dir.canonicalize().unwrap();
+    let package_decisions =
+        filter::evaluate_filter_rules(config, &api_package_list.d.results)?;
+    report.decisions(&package_decisions);
+    //packages are processed in id order, a checkpoint resumes the same order
+    let mut selected_decisions: Vec<&PackageDecision> =
+        package_decisions.iter().filter(|d| d.selected).collect();
+    selected_decisions.sort_by(|a, b| a.package_id.cmp(&b.package_id));
+    let mut package_list: Vec<String> = selected_decisions
+        .iter()
+        .map(|d| d.package_id.clone())
+        .collect();
+
+    stats.packages_selected = package_list.len();
+    //packages done in earlier runs of a checkpoint are no late additions
+    let seen_packages = package_list.clone();
+
+    let mut checkpoint = match checkpoint_path {
+        Some(path) if dry_run.is_none() => {
+            let checkpoint = CheckpointFile::open(path, &package_list)?;
+            package_list.retain(|p| checkpoint.pending().contains(p));
+            for package_id in seen_packages.iter().filter(|p| !package_list.contains(p)) {
+                report.resumed(package_id);
+            }
+            selected_decisions.retain(|d| checkpoint.pending().contains(&d.package_id));
+            Some(checkpoint)
+        }
+        _ => None,
+    };
+    //list entries of the selected packages for the package metadata file
+    let mut package_metadata: HashMap<String, APIResponseResult> = api_package_list
+        .d
+        .results
+        .iter()
+        .filter(|p| seen_packages.contains(&p.id))
+        .map(|p| (p.id.clone(), p.clone()))
+        .collect();
+    //artifact folders written and ids skipped per package folder, prune keeps those
+    let mut listed_artifacts: HashMap<String, prune::ListedArtifacts> = HashMap::new();
+    let mut package_selections: Vec<filter::ArtifactSelection> = selected_decisions
+        .iter()
+        .map(|d| filter::artifact_selection(config, d))
+        .collect::<Result<_, _>>()?;
+
+    log::info!("Downloading These Packages:");
+    log::info!("{:?}", &package_list);
+    filter::print_skip_summary(&package_decisions);
+
+    if dry_run == Some(DryRun::Packages) {
+        println!("Dry run: {} package(s) selected, nothing was written.", package_list.len());
+        finish_timings(stats, &timings, print_timings);
+        return Ok(());
+    }
 
-    let api_package_list = get_all_packages(&config, &client, &authorization).await?;
+    //the runtime is listed once, deployed artifacts are grouped by the package listings
+    let runtime_artifacts = match config.packages.source {
+        Source::Deployed => {
+            let runtime_artifacts =
+                runtime::list_runtime_artifacts(config, &client, &authorization, &timings).await?;
+            log::info!("Deployed artifacts on the tenant: {}", runtime_artifacts.len());
+            Some(runtime_artifacts)
+        }
+        Source::Designtime => None,
+    };
 
-    let mut api_package_set: HashSet<String> = HashSet::new();
-    let mut api_package_name_map: HashMap<String, String> = HashMap::new();
-    for package in api_package_list.d.results.iter() {
-        api_package_set.insert(package.id.to_string());
-        match api_package_name_map.entry(package.name.to_string()) {
-            std::collections::hash_map::Entry::Occupied(mut e) => {
-                e.insert(e.get().clone() + "," + &package.id);
+    let mut artifact_results = Vec::new();
+    let mut manifest_entries = Vec::new();
+    //paths of packages.layout across both passes, lowercase
+    let mut layout_targets: HashMap<String, String> = HashMap::new();
+    //a second pass syncs the packages a fresh listing adds, it never runs a third time
+    let mut late_pass = false;
+    loop {
+        let mut futs = FuturesUnordered::new();
+        let mut outputs = Vec::new();
+
+        //fetch package artifacts
+        for (package_id, selection) in package_list.iter().zip(package_selections.iter()) {
+            let package_name = package_metadata
+                .get(package_id)
+                .map(|p| p.name.as_str())
+                .unwrap_or(package_id);
+            futs.push(process_package(
+                package_id,
+                package_name,
+                config,
+                &client,
+                &authorization,
+                &data_dir,
+                &ignore_error_download,
+                selection,
+                runtime_artifacts.as_deref(),
+                &timings,
+            ));
+
+            if futs.len() >= config.packages.worker_count {
+                //fail fast, dropping the pending futures aborts their requests on cancel
+                let item = tokio::select! {
+                    item = futs.next() => item.unwrap(),
+                    _ = cancel.cancelled() => return Err(cancelled(stats)),
+                };
+                outputs.push(item?);
+                stats.packages_listed += 1;
             }
-            std::collections::hash_map::Entry::Vacant(e) => {
-                e.insert(package.id.to_string());
+        }
+        // wait for remaining
+        loop {
+            let item = tokio::select! {
+                item = futs.next() => item,
+                _ = cancel.cancelled() => return Err(cancelled(stats)),
+            };
+            match item {
+                Some(item) => {
+                    outputs.push(item?);
+                    stats.packages_listed += 1;
+                }
+                None => break,
             }
+        }
+
+        let deferred_packages = match config.packages.size_budget {
+            Some(budget) => budget::defer_over_budget(
+                budget,
+                config.packages.size_budget_assumed_size,
+                &mut outputs,
+            ),
+            None => HashSet::new(),
         };
-    }
 
-    let mut operating_package_set: HashSet<String> = HashSet::new();
+        //only listing metadata is read, it runs in a dry run too
+        if config.packages.check_stale_active {
+            let mut active_artifacts: Vec<stale::ActiveArtifact> = Vec::new();
+            for (package_id, task) in outputs
+                .iter()
+                .flat_map(|(package_id, tasks, _)| tasks.iter().map(move |t| (package_id, t)))
+            {
+                let artifact = (
+                    package_id.clone(),
+                    task.kind,
+                    task.artifact_id.clone(),
+                    task.active_version.clone(),
+                );
+                if !active_artifacts.contains(&artifact) {
+                    active_artifacts.push(artifact);
+                }
+            }
+            let check = stale::check_stale_active(
+                config,
+                &client,
+                &authorization,
+                Some(&timings),
+                active_artifacts,
+            )
+            .await?;
+            stale::print_summary(&check);
+            report.stale_active(check);
+        }
+
+        for (package_id, tasks, skipped) in outputs.iter() {
+            let artifacts: Vec<_> = tasks
+                .iter()
+                .map(|t| (t.kind, t.artifact_id.clone(), t.version.clone()))
+                .collect();
+            report.listed(package_id, &artifacts, skipped);
+            listed_artifacts.insert(
+                paths::dir_name(package_id),
+                prune::ListedArtifacts {
+                    names: tasks.iter().map(|t| t.artifact_dir.clone()).collect(),
+                    ids: skipped.iter().map(|s| s.artifact_id.clone()).collect(),
+                },
+            );
+        }
+        let artifact_skips: Vec<ArtifactSkip> = outputs
+            .iter_mut()
+            .flat_map(|(_, _, skipped)| std::mem::take(skipped))
+            .collect();
+        filter::print_artifact_skip_summary(&artifact_skips);
+
+        //two artifacts rendered to the same path would overwrite each other, also in a dry run
+        if config.packages.layout.is_some() {
+            for (package_id, task) in outputs
+                .iter()
+                .flat_map(|(package_id, tasks, _)| tasks.iter().map(move |t| (package_id, t)))
+            {
+                let mut path = layout::relative_path(&data_dir, &task.container, &task.artifact_dir);
+                if let ZipExtraction::Disabled = task.zip_extraction {
+                    path.push_str(".zip");
+                }
+                let artifact = format!("{} {} of package {}", task.kind, task.artifact_id, package_id);
+                //case-insensitive file systems would merge them too
+                if let Some(other) = layout_targets.insert(path.to_lowercase(), artifact.clone()) {
+                    return Err(Error::ConfigValidation(format!(
+                        "packages.layout writes {} and {} to the same path {}, add a placeholder that tells them apart",
+                        other, artifact, path
+                    )));
+                }
+            }
+        }
 
-    for package_rule in config.packages.filter_rules.iter() {
-        let mut rule_package_set: HashSet<String> = HashSet::new();
-        match package_rule {
-            PackageRuleEnum::Regex(rule) => {
-                let re = Regex::new(&rule.pattern)?;
+        if dry_run.is_some() {
+            let mut artifact_count = 0;
+            for (package_id, tasks, _) in outputs.iter() {
+                println!("Package {}: {} artifact(s)", package_id, tasks.len());
+                for task in tasks.iter() {
+                    println!("  {} {}", task.kind, task.artifact_id);
+                }
+                artifact_count += tasks.len();
+            }
+            stats.artifacts_listed = artifact_count;
+            if let Some(max_artifacts) = config.packages.max_artifacts {
+                if artifact_count > max_artifacts {
+                    println!(
+                        "Artifact count {} exceeds max_artifacts {}, a sync would stop here.",
+                        artifact_count, max_artifacts
+                    );
+                }
+            }
+            println!(
+                "Dry run: {} package(s), {} artifact(s) selected, nothing was written.",
+                package_list.len(),
+                artifact_count
+            );
+            finish_timings(stats, &timings, print_timings);
+            return Ok(());
+        }
 
-                for p in &api_package_set {
-                    if re.is_match(&p) {
-                        rule_package_set.insert(p.clone());
-                    }
+        //safety valve before any payload download
+        if let Some(max_artifacts) = config.packages.max_artifacts {
+            let artifact_count: usize = outputs.iter().map(|(_, tasks, _)| tasks.len()).sum();
+            if artifact_count > max_artifacts {
+                let mut text = format!(
+                    "Artifact count {} exceeds max_artifacts {}. Artifacts per package:",
+                    artifact_count, max_artifacts
+                );
+                let mut package_counts: Vec<(&String, usize)> = outputs
+                    .iter()
+                    .map(|(package_id, tasks, _)| (package_id, tasks.len()))
+                    .collect();
+                package_counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+                for (package_id, count) in package_counts {
+                    text.push_str(&format!("\n  {}: {}", package_id, count));
                 }
+                log::error!("{}", text);
+                return Err(Error::Limit("Max Artifacts Exceeded!".to_string()));
+            }
+        }
 
-                // rule.operation
-                match rule.operation {
-                    OperationEnum::Include => {
-                        //operating_package_set.union(&rule_package_set);
-                        operating_package_set.extend(rule_package_set);
+        stats.artifacts_listed += outputs.iter().map(|(_, tasks, _)| tasks.len()).sum::<usize>();
+        for kind in ArtifactKind::ALL.iter().filter(|k| k.enabled(config)) {
+            stats.artifacts_listed_by_type.entry(kind.to_string()).or_insert(0);
+            stats.artifacts_downloaded_by_type.entry(kind.to_string()).or_insert(0);
+        }
+        for task in outputs.iter().flat_map(|(_, tasks, _)| tasks.iter()) {
+            *stats
+                .artifacts_listed_by_type
+                .entry(task.kind.to_string())
+                .or_insert(0) += 1;
+        }
+
+        //nothing is removed locally once the run is cancelled
+        if cancel.is_cancelled() {
+            return Err(cancelled(stats));
+        }
+
+        //remove local artifacts before download, folders of a layout can be shared with
+        //packages that aren't synced, only the artifacts themselves are replaced
+        let recorded = managed::ManagedPaths::read(&data_dir)?;
+        for (package_id, tasks, _) in outputs.iter() {
+            let package_dir = paths::package_dir(&data_dir, package_id);
+            if deferred_packages.contains(package_id) || config.packages.layout.is_some() {
+                budget::clear_artifacts(tasks)?;
+                continue;
+            }
+            //the artifacts of the last sync go as well, the user's own folders next to them
+            //stay. A package folder synced before artifacts were recorded is emptied once.
+            let dir_name = paths::dir_name(package_id);
+            if recorded.has_artifacts(&dir_name) {
+                for name in recorded.artifacts(&dir_name).keys() {
+                    budget::clear_artifact(&package_dir, name)?;
+                }
+                budget::clear_artifacts(tasks)?;
+                fs::create_dir_all(&package_dir)?;
+            } else {
+                remove_dir_all::ensure_empty_dir(&package_dir)?;
+            }
+            permissions::apply_package_dir(config, &package_dir)?;
+        }
+        let package_dirs: Vec<String> = outputs
+            .iter()
+            .map(|(id, _, _)| paths::dir_name(id))
+            .collect();
+        //ids that aren't valid file names keep their original in paths::NAMES_FILE
+        let mut encoded = Vec::new();
+        match config.packages.layout {
+            Some(_) => {
+                let mut top_dirs = Vec::new();
+                for task in outputs.iter().flat_map(|(_, tasks, _)| tasks.iter()) {
+                    let path = layout::relative_path(&data_dir, &task.container, &task.artifact_dir);
+                    if let Some(top_dir) = path.split('/').next() {
+                        if !top_dirs.iter().any(|d| d == top_dir) {
+                            top_dirs.push(top_dir.to_string());
+                        }
                     }
-                    OperationEnum::Exclude => {
-                        operating_package_set = operating_package_set
-                            .difference(&rule_package_set)
-                            .cloned()
-                            .collect();
+                    if paths::dir_name(&task.artifact_id) != task.artifact_id {
+                        encoded.push((path, task.artifact_id.clone()));
                     }
                 }
+                managed::ManagedPaths::read(&data_dir)?.add_layout_dirs(top_dirs.iter())?;
             }
-            PackageRuleEnum::Single(rule) => {
-                //if single package rule not found in original package list check names and inform.
-                if !api_package_set.contains(&rule.id) {
-                    println!("Package ID not found: {}", &rule.id);
-
-                    match api_package_name_map.get(&rule.id) {
-                        Some(id_for_name) => {
-                            println!(
-                                "Did you enter the Package name instead of this Package ID?: '{}'",
-                                id_for_name
-                            );
+            None => {
+                let mut managed = managed::ManagedPaths::read(&data_dir)?;
+                managed.add(package_dirs.iter())?;
+                for ((package_id, tasks, _), package_dir) in outputs.iter().zip(package_dirs.iter()) {
+                    //the next sync and prune only remove the artifact entries recorded here,
+                    //a package with deferred artifacts keeps the records of their local copies
+                    let artifacts = tasks.iter().map(|t| (&t.artifact_dir, &t.artifact_id));
+                    if deferred_packages.contains(package_id) {
+                        managed.add_artifacts(package_dir, artifacts)?;
+                    } else {
+                        managed.set_artifacts(package_dir, artifacts)?;
+                    }
+                    if package_dir != package_id {
+                        encoded.push((package_dir.clone(), package_id.clone()));
+                    }
+                    for task in tasks.iter() {
+                        if paths::dir_name(&task.artifact_id) != task.artifact_id {
+                            encoded.push((
+                                format!("{}/{}", package_dir, task.artifact_dir),
+                                task.artifact_id.clone(),
+                            ));
                         }
-                        None => {}
                     }
+                }
+            }
+        }
+        paths::record_names(&data_dir, encoded)?;
+        //a layout has no folder per package to write package.json to
+        if config.packages.include_package_metadata && config.packages.layout.is_none() {
+            for (package_id, _, _) in outputs.iter() {
+                if let Some(package) = package_metadata.get(package_id) {
+                    let package_dir = paths::package_dir(&data_dir, package_id);
+                    package_metadata::write_package_metadata(&package_dir, package)?;
+                    permissions::apply(config, &package_dir.join(package_metadata::FILE_NAME))?;
+                }
+            }
+        }
 
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        "Package ID not found!",
-                    )
-                    .into());
+        //a package is complete once all its artifacts are downloaded, one with deferred
+        //artifacts stays pending in the checkpoint
+        let mut remaining: HashMap<String, usize> = HashMap::new();
+        for (package_id, tasks, _) in outputs.iter() {
+            if deferred_packages.contains(package_id) {
+                remaining.insert(package_id.clone(), tasks.len() + 1);
+                continue;
+            }
+            remaining.insert(package_id.clone(), tasks.len());
+            if tasks.is_empty() {
+                if let Some(checkpoint) = checkpoint.as_mut() {
+                    checkpoint.complete(package_id)?;
                 }
+            }
+        }
 
-                match rule.operation {
-                    OperationEnum::Include => {
-                        operating_package_set.insert(rule.id.clone());
-                    }
-                    OperationEnum::Exclude => {
-                        operating_package_set.remove(&rule.id);
-                    }
+        let mut futs2 = FuturesUnordered::new();
+        let artifacts_bar =
+            progress::artifacts_bar(outputs.iter().map(|(_, tasks, _)| tasks.len()).sum());
+
+        // let mut outputs2 = outputs.into_iter().flatten().collect::<Vec<_>>();
+        // outputs2.shuffle(&mut thread_rng());
+        // for task in outputs2.into_iter() {
+        for (package_id, task) in outputs
+            .into_iter()
+            .flat_map(|(package_id, tasks, _)| tasks.into_iter().map(move |t| (package_id.clone(), t)))
+        {
+            // task.await;
+            let (kind, artifact_id, version) = (task.kind, task.artifact_id, task.version);
+            futs2.push(
+                task.download
+                    .map(move |result| (package_id, artifact_id, version, kind, result)),
+            );
+
+            if futs2.len() >= config.packages.worker_count {
+                //fail fast
+                let item = tokio::select! {
+                    item = futs2.next() => item.unwrap(),
+                    _ = cancel.cancelled() => return Err(cancelled(stats)),
+                };
+                download_done(
+                    item,
+                    stats,
+                    report,
+                    &mut artifact_results,
+                    &mut manifest_entries,
+                    &mut remaining,
+                    checkpoint.as_mut(),
+                    &artifacts_bar,
+                )?;
+            }
+        }
+
+        // wait for remaining
+        loop {
+            let item = tokio::select! {
+                item = futs2.next() => item,
+                _ = cancel.cancelled() => return Err(cancelled(stats)),
+            };
+            match item {
+                Some(item) => {
+                    download_done(
+                        item,
+                        stats,
+                        report,
+                        &mut artifact_results,
+                        &mut manifest_entries,
+                        &mut remaining,
+                        checkpoint.as_mut(),
+                        &artifacts_bar,
+                    )?;
                 }
+                None => break,
             }
         }
+        //the summary is printed below the cleared bar
+        artifacts_bar.finish_and_clear();
+
+        if late_pass || !config.packages.refresh_listing_at_end {
+            break;
+        }
+        late_pass = true;
+        //the listing futures of this pass borrow the package list
+        drop(futs);
+        let (late_decisions, late_packages) =
+            late_additions(config, &client, &authorization, &timings, &seen_packages).await?;
+        package_metadata.extend(late_packages.into_iter().map(|p| (p.id.clone(), p)));
+        if late_decisions.is_empty() {
+            log::info!("Package list refreshed, no late additions.");
+            break;
+        }
+        report.decisions(&late_decisions);
+        package_list = late_decisions.iter().map(|d| d.package_id.clone()).collect();
+        package_selections = late_decisions
+            .iter()
+            .map(|d| filter::artifact_selection(config, d))
+            .collect::<Result<_, _>>()?;
+        log::info!("Late Additions, Downloading These Packages:");
+        log::info!("{:?}", &package_list);
+        stats.packages_selected += package_list.len();
+        stats.late_additions = package_list.clone();
     }
 
-    let package_list: Vec<String> = Vec::from_iter(operating_package_set);
+    if config.packages.validate_content {
+        let violations: Vec<ContentViolation> =
+            artifact_results.into_iter().flatten().collect();
+        validate::print_summary(&violations);
+    }
 
-    println!("Downloading These Packages:");
-    println!("{:?}", &package_list);
+    stats.print_type_summary();
+    if !stats.late_additions.is_empty() {
+        log::info!(
+            "Late additions: {} package(s) {:?}",
+            stats.late_additions.len(),
+            stats.late_additions
+        );
+    }
 
-    let mut futs = FuturesUnordered::new();
-    let mut outputs = Vec::new();
+    log::info!(
+        "Download time elapsed in seconds: {}",
+        now.elapsed().as_secs()
+    );
 
-    //fetch package artifacts
-    for package_id in package_list.iter() {
-        futs.push(process_package(
-            package_id,
-            &config,
-            &client,
-            &authorization,
-            &data_dir,
-            &ignore_error_download,
-        ));
+    if config.packages.prune {
+        let selected: Vec<String> = seen_packages
+            .iter()
+            .chain(stats.late_additions.iter())
+            .map(|id| paths::dir_name(id))
+            .collect();
+        //a failed listing or download must not look like deleted content
+        if report.failed_artifacts() > 0 {
+            log::warn!(
+                "Prune skipped: {} artifact download(s) failed in this run, nothing is removed",
+                report.failed_artifacts()
+            );
+        } else if selected.is_empty() {
+            log::warn!("Prune skipped: no package selected, nothing is removed");
+        } else {
+            let plan = prune::plan(
+                &data_dir,
+                &config.tenant.management_host,
+                &selected,
+                &listed_artifacts,
+            )?;
+            prune::print_plan(&plan);
+            if prune::confirm(&plan, yes, no_input)? {
+                stats.pruned = prune::execute(&data_dir, &plan, None)?;
+                log::info!("Pruned: {} package(s) and artifact(s)", stats.pruned.len());
+            } else {
+                log::warn!("Prune cancelled, nothing is removed");
+            }
+        }
+    }
+
+    //after prune, entries of removed paths are dropped
+    if config.packages.manifest {
+        manifest::write(&data_dir, manifest_entries, &identity)?;
+    }
 
-        if futs.len() >= config.packages.download_worker_count {
-            //fail fast
-            outputs.push(futs.next().await.unwrap()?);
+    let mirror = hash::hash_mirror(&data_dir)?;
+    log::info!("Mirror root hash: {}", mirror.root);
+
+    if config.git.commit {
+        //a partial mirror must not be committed as the state of the tenant
+        if report.failed_artifacts() > 0 {
+            log::warn!(
+                "Git commit skipped: {} artifact download(s) failed in this run",
+                report.failed_artifacts()
+            );
+        } else {
+            stats.git_commit =
+                git::commit(config, &data_dir, stats.artifacts_downloaded, &identity)?;
         }
     }
-    // wait for remaining
-    while let Some(item) = futs.next().await {
-        outputs.push(item?);
+
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.finish()?;
     }
 
-    let mut futs2 = FuturesUnordered::new();
-    let mut artifact_results = Vec::new();
+    finish_timings(stats, &timings, print_timings);
+    Ok(())
+}
 
-    // let mut outputs2 = outputs.into_iter().flatten().collect::<Vec<_>>();
-    // outputs2.shuffle(&mut thread_rng());
-    // for task in outputs2.into_iter() {
-    for task in outputs.into_iter().flatten() {
-        // task.await;
-        futs2.push(task);
+// packages selected by a fresh listing that the sync didn't see, e.g. created while it ran
+async fn late_additions(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    timings: &http::Timings,
+    seen_packages: &[String],
+) -> Result<(Vec<PackageDecision>, Vec<APIResponseResult>), Error> {
+    let api_package_list = get_all_packages(config, client, authorization, Some(timings)).await?;
+    let mut late_decisions: Vec<PackageDecision> =
+        filter::evaluate_filter_rules(config, &api_package_list.d.results)?
+            .into_iter()
+            .filter(|d| d.selected && !seen_packages.contains(&d.package_id))
+            .collect();
+    late_decisions.sort_by(|a, b| a.package_id.cmp(&b.package_id));
+    let late_packages = api_package_list
+        .d
+        .results
+        .into_iter()
+        .filter(|p| late_decisions.iter().any(|d| d.package_id == p.id))
+        .collect();
+    Ok((late_decisions, late_packages))
+}
 
-        if futs2.len() >= config.packages.download_worker_count {
-            //fail fast
-            artifact_results.push(futs2.next().await.unwrap()?);
+// a finished download: counted, booked into the report and the checkpoint.
+// A failed download that wasn't ignored ends the sync with its error.
+#[allow(clippy::too_many_arguments)]
+fn download_done(
+    (package_id, artifact_id, version, kind, result): DownloadResult,
+    stats: &mut RunStats,
+    report: &mut ReportBuilder,
+    artifact_results: &mut Vec<Vec<ContentViolation>>,
+    manifest_entries: &mut Vec<manifest::ManifestEntry>,
+    remaining: &mut HashMap<String, usize>,
+    checkpoint: Option<&mut CheckpointFile>,
+    artifacts_bar: &indicatif::ProgressBar,
+) -> Result<(), Error> {
+    artifacts_bar.inc(1);
+    let download = match result {
+        Ok(download) => download,
+        Err(err) => {
+            report.failed(&package_id, &artifact_id, version.as_deref(), &err.to_string());
+            return Err(Error::Download(Box::new(err)));
         }
+    };
+    let version = version.as_deref();
+    match (&download.failure, download.skipped) {
+        (Some(failure), _) => report.failed(&package_id, &artifact_id, version, failure),
+        (None, Some(reason)) => report.skipped(&package_id, &artifact_id, version, reason),
+        (None, None) => report.downloaded(
+            &package_id,
+            &artifact_id,
+            version,
+            download.bytes,
+            download.duration,
+        ),
+    }
+    if download.draft {
+        report.draft(&package_id, &artifact_id, version);
     }
+    if let Some(digest) = download.digest {
+        manifest_entries.push(manifest::ManifestEntry::new(
+            &package_id,
+            &artifact_id,
+            kind,
+            version,
+            digest,
+        ));
+    }
+    artifact_results.push(download.violations);
+    stats.count_download(kind);
+    artifact_done(remaining, checkpoint, &package_id)
+}
 
-    // wait for remaining
-    while let Some(item) = futs2.next().await {
-        artifact_results.push(item?);
+fn artifact_done(
+    remaining: &mut HashMap<String, usize>,
+    checkpoint: Option<&mut CheckpointFile>,
+    package_id: &str,
+) -> Result<(), Error> {
+    if let Some(count) = remaining.get_mut(package_id) {
+        *count -= 1;
+        if *count == 0 {
+            if let Some(checkpoint) = checkpoint {
+                checkpoint.complete(package_id)?;
+            }
+        }
     }
+    Ok(())
+}
 
-    println!(
-        "Download time elapsed in seconds: {}",
-        now.elapsed().as_secs()
-    );
+fn finish_timings(stats: &mut RunStats, timings: &http::Timings, print_timings: bool) {
+    stats.timings = timings.summary();
+    if print_timings {
+        http::print_timings(&stats.timings);
+    }
+}
 
-    Ok(())
+// local_dir is relative to the config file, or absolute
+pub fn local_data_dir(config: &Config, config_path: &str) -> PathBuf {
+    let normalized_localdir = normalize_path(Path::new(&config.packages.local_dir));
+    let mut data_dir = std::path::PathBuf::from(".");
+    //config path as starting point:
+    data_dir.push(normalize_path(Path::new(config_path)));
+    data_dir = data_dir.parent().unwrap().to_path_buf();
+
+    //localdir can be relative or absolute
+    data_dir.push(normalized_localdir);
+    data_dir
+}
+
+pub(crate) fn is_zip_content(bytes: &[u8]) -> bool {
+    // local file header, or end of central directory for an empty archive
+    bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06")
 }
 
 fn basic_auth(user: &str, pass: &str) -> String {
@@ -671,3 +2206,142 @@ fn normalize_path(path: &Path) -> PathBuf {
     }
     ret
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zip_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn extract(payload: &[u8]) -> (tempfile::TempDir, Result<(), Error>) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config::test_config(serde_json::json!({ "filter_rules": [] }));
+        let container = dir.path().join("Pkg");
+        let result = extract_zip("Pkg", "Flow", &config, &container, payload);
+        (dir, result)
+    }
+
+    fn zip_reason(err: &Error) -> String {
+        match err {
+            Error::Zip { artifact, reason } => {
+                assert_eq!(artifact, "Pkg/Flow");
+                reason.clone()
+            }
+            other => panic!("not a zip error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn extracts_plain_entries() {
+        let payload = zip_of(&[("META-INF/MANIFEST.MF", b"Bundle-Version: 1.0.0\n")]);
+        let (dir, result) = extract(&payload);
+        result.unwrap();
+        let manifest = dir.path().join("Pkg/Flow/META-INF/MANIFEST.MF");
+        assert_eq!(
+            fs::read_to_string(manifest).unwrap(),
+            "Bundle-Version: 1.0.0\n"
+        );
+    }
+
+    #[test]
+    fn parent_dir_entry_is_rejected() {
+        let payload = zip_of(&[("ok.txt", b"ok"), ("../../evil.txt", b"evil")]);
+        let (dir, result) = extract(&payload);
+        let err = result.unwrap_err();
+        assert!(zip_reason(&err).contains("unsafe path"));
+        assert!(!dir.path().join("evil.txt").exists());
+        assert!(!dir.path().join("Pkg/evil.txt").exists());
+    }
+
+    #[test]
+    fn absolute_entry_is_rejected() {
+        let target = std::env::temp_dir().join("cpi-sync-absolute-entry.txt");
+        let name = target.to_string_lossy().replace('\\', "/");
+        let payload = zip_of(&[(name.as_str(), b"evil")]);
+        let (_dir, result) = extract(&payload);
+        let err = result.unwrap_err();
+        assert!(zip_reason(&err).contains("unsafe path"));
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn corrupted_archive_is_an_error() {
+        let (_dir, result) = extract(b"PK\x03\x04 not really a zip");
+        let err = result.unwrap_err();
+        assert!(zip_reason(&err).starts_with("corrupted zip archive"));
+
+        //a download cut off in the middle, the central directory is missing
+        let payload = zip_of(&[("a.txt", b"aaaaaaaaaaaaaaaaaaaa")]);
+        let (_dir, result) = extract(&payload[..payload.len() / 2]);
+        let err = result.unwrap_err();
+        assert!(zip_reason(&err).starts_with("corrupted zip"));
+    }
+
+    // answers the prompts in order and records what was asked
+    fn read_with(
+        answers: &[&str],
+        confirm_secret: bool,
+    ) -> (Vec<String>, Result<String, Error>) {
+        let mut asked = Vec::new();
+        let mut answers = answers.iter();
+        let result = read_secret(
+            |prompt| {
+                asked.push(prompt.to_string());
+                answers
+                    .next()
+                    .map(|answer| answer.to_string())
+                    .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))
+            },
+            confirm_secret,
+        );
+        (asked, result)
+    }
+
+    #[test]
+    fn secret_is_trimmed() {
+        let (asked, result) = read_with(&["  s3cret \r\n"], false);
+        assert_eq!(result.unwrap(), "s3cret");
+        assert_eq!(asked, vec!["Password: "]);
+
+        let (_, result) = read_with(&["\ts3cret"], false);
+        assert_eq!(result.unwrap(), "s3cret");
+        //only the surrounding whitespace, not the inner one
+        let (_, result) = read_with(&["s3 cret\n"], false);
+        assert_eq!(result.unwrap(), "s3 cret");
+    }
+
+    #[test]
+    fn confirmed_secret_is_compared_trimmed() {
+        let (asked, result) = read_with(&["s3cret\r\n", " s3cret"], true);
+        assert_eq!(result.unwrap(), "s3cret");
+        assert_eq!(asked, vec!["Password: ", "Confirm password: "]);
+    }
+
+    #[test]
+    fn different_confirmation_is_an_auth_error() {
+        let (_, result) = read_with(&["s3cret", "s3creT"], true);
+        let err = result.unwrap_err();
+        match err {
+            Error::Auth(message) => assert_eq!(message, "Entered secrets do not match"),
+            other => panic!("not an auth error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn failed_prompt_is_an_error() {
+        let (_, result) = read_with(&[], false);
+        assert!(result.is_err());
+        let (asked, result) = read_with(&["s3cret"], true);
+        assert!(result.is_err());
+        assert_eq!(asked.len(), 2);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::config::{Config, PackageRegex, PackageRuleEnum, RegexField};
+use std::fmt;
+
+// lint codes are stable, they can be suppressed per rule with `lint_ignore`
+pub const LINT_EMPTY_PATTERN: &str = "CPI001";
+pub const LINT_MATCH_ALL: &str = "CPI002";
+pub const LINT_UNANCHORED: &str = "CPI003";
+pub const LINT_GLOB_STAR: &str = "CPI004";
+
+#[derive(Debug, Clone)]
+pub struct ConfigLint {
+    pub code: &'static str,
+    pub rule_index: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigLint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (filter_rules[{}]): {}",
+            self.code, self.rule_index, self.message
+        )
+    }
+}
+
+fn is_match_all(pattern: &str) -> bool {
+    let inner = pattern.strip_prefix('^').unwrap_or(pattern);
+    let inner = inner.strip_suffix('$').unwrap_or(inner);
+    matches!(inner, ".*" | ".+" | "(.*)" | "(.+)" | "(?:.*)" | "(?:.+)")
+}
+
+fn lint_regex_rule(rule: &PackageRegex, require_anchors: bool) -> Vec<(&'static str, String)> {
+    let pattern = rule.pattern.as_str();
+    let mut lints = Vec::new();
+
+    if pattern.is_empty() {
+        lints.push((
+            LINT_EMPTY_PATTERN,
+            "empty pattern matches every package".to_string(),
+        ));
+    } else if is_match_all(pattern) {
+        lints.push((
+            LINT_MATCH_ALL,
+            format!("pattern '{}' matches every package", pattern),
+        ));
+    }
+
+    if pattern.starts_with('*') {
+        lints.push((
+            LINT_GLOB_STAR,
+            format!(
+                "pattern '{}' starts with '*', this is a glob, did you mean '.{}'?",
+                pattern, pattern
+            ),
+        ));
+    }
+
+    let anchored = pattern.starts_with('^') && pattern.ends_with('$');
+    if require_anchors && !pattern.is_empty() && !is_match_all(pattern) && !anchored {
+        lints.push((
+            LINT_UNANCHORED,
+            format!(
+                "pattern '{}' is not anchored with '^' and '$', it also matches {} containing it",
+                pattern,
+                match rule.field {
+                    RegexField::Id => "ids",
+                    RegexField::Name => "names",
+                }
+            ),
+        ));
+    }
+
+    lints
+}
+
+pub fn lint_config(config: &Config) -> Vec<ConfigLint> {
+    let mut lints = Vec::new();
+
+    for (rule_index, package_rule) in config.packages.filter_rules.iter().enumerate() {
+        if let PackageRuleEnum::Regex(rule) = package_rule {
+            for (code, message) in lint_regex_rule(rule, config.packages.lint_require_anchors) {
+                if rule.lint_ignore.iter().any(|c| c == code) {
+                    continue;
+                }
+                lints.push(ConfigLint {
+                    code,
+                    rule_index,
+                    message,
+                });
+            }
+        }
+    }
+
+    lints
+}
@@ -0,0 +1,106 @@
+use log::{Level, LevelFilter};
+use std::future::Future;
+use std::io::Write;
+
+tokio::task_local! {
+    static LOG_PREFIX: String;
+}
+
+// messages logged while the future runs start with `[prefix] `, so tenants synced at the same
+// time can be told apart. Only the tool's own formatter shows it.
+pub async fn with_log_prefix<F: Future>(prefix: String, future: F) -> F::Output {
+    LOG_PREFIX.scope(prefix, future).await
+}
+
+// `[prefix] ` of the current task, empty outside with_log_prefix
+pub(crate) fn log_prefix() -> String {
+    LOG_PREFIX
+        .try_with(|tenant| format!("[{}] ", tenant))
+        .unwrap_or_default()
+}
+
+// progress bars are cleared while a message is written and drawn again below it
+struct ProgressLogger(env_logger::Logger);
+
+impl log::Log for ProgressLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.0.matches(record) {
+            crate::progress::suspend(|| self.0.log(record));
+        }
+    }
+
+    fn flush(&self) {
+        self.0.flush()
+    }
+}
+
+// bodies of failed responses are logged under their own target, they can contain tenant data
+// or secrets echoed back and are only shown with -v or --show-response-body
+pub(crate) const RESPONSE_BODY_TARGET: &str = "cpi_sync::response_body";
+
+pub(crate) fn response_body(body: &str) {
+    log::debug!(target: RESPONSE_BODY_TARGET, "Response Body:\n{}", body);
+}
+
+// Authorization header values, secret-looking key/value pairs and credentials in URLs, for text
+// that leaves the debug log, e.g. a part of a response body in an error
+pub(crate) fn redact(text: &str) -> String {
+    let patterns = [
+        (
+            r"(?i)\b(bearer|basic)\s+[A-Za-z0-9\-._~+/]{8,}=*",
+            "$1 <redacted>",
+        ),
+        (
+            r#"(?i)("?(?:authorization|password|client_secret|access_token|token|apikey)"?\s*[:=]\s*)("[^"]*"|[^\s,}]+)"#,
+            "$1\"<redacted>\"",
+        ),
+        (r"(://[^/\s:@]+):[^/\s@]+@", "$1:<redacted>@"),
+    ];
+    let mut text = text.to_string();
+    for (pattern, replacement) in patterns.iter() {
+        let re = regex::Regex::new(pattern).unwrap();
+        text = re.replace_all(&text, *replacement).into_owned();
+    }
+    text
+}
+
+// level of the tool's own messages: --quiet keeps warnings and errors, -v adds the HTTP requests,
+// -vv everything. Other crates only log warnings, RUST_LOG overrides all of it.
+fn level_filter(verbose: u64, quiet: bool) -> LevelFilter {
+    match (quiet, verbose) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    }
+}
+
+// installs the logger of the command line tool, an embedder brings its own.
+// Messages go to stderr, stdout keeps the output of the subcommands and the prompts.
+pub fn init_logging(verbose: u64, quiet: bool, show_response_body: bool) {
+    let mut builder = env_logger::Builder::new();
+    builder
+        .target(env_logger::Target::Stderr)
+        .filter_level(LevelFilter::Warn)
+        .filter_module("cpi_sync", level_filter(verbose, quiet));
+    if show_response_body {
+        builder.filter_module(RESPONSE_BODY_TARGET, LevelFilter::Debug);
+    }
+    builder.parse_default_env().format(|buf, record| {
+        let prefix = match record.level() {
+            Level::Error => "Error: ",
+            Level::Warn => "Warning: ",
+            Level::Info => "",
+            Level::Debug => "Debug: ",
+            Level::Trace => "Trace: ",
+        };
+        writeln!(buf, "{}{}{}", log_prefix(), prefix, record.args())
+    });
+    let logger = builder.build();
+    log::set_max_level(logger.filter());
+    log::set_boxed_logger(Box::new(ProgressLogger(logger))).expect("logger is installed once");
+}
@@ -2,7 +2,9 @@ use clap::Clap;
 use crossterm::event::{read, Event};
 use jsonschema::{self, Draft, JSONSchema};
 use serde_json::{self, Value};
-use std::{fs::File, io::Read};
+use std::{fs::File, io::IsTerminal, io::Read};
+use futures::stream::StreamExt;
+use cpi_sync::Error;
 
 //config types
 
@@ -10,85 +12,840 @@ use std::{fs::File, io::Read};
 #[derive(Clap, Debug)]
 #[clap(version = "0.3.1", author = "Fatih.Pense @ pizug.com")]
 struct Opts {
-    #[clap(short, long, default_value = "./cpi-sync.json")]
-    config: String,
+    #[clap(short, long, about = "Config file, searched in ./cpi-sync.json, ./cpi-sync.yaml and the user config directory if not given")]
+    config: Option<String>,
     #[clap(long, about = "Disable features that require user input")]
     no_input: bool,
+    #[clap(long, about = "Delete what prune planned without asking, needed for prune with --no-input")]
+    yes: bool,
     #[clap(short,long, about = "Ignore error: Download")]
     ignore_error_download: bool,
+    #[clap(long, about = "Show a fingerprint of the entered secret and ask for it twice")]
+    confirm_secret: bool,
+    #[clap(long, about = "Abort before downloading when more artifacts are selected")]
+    max_artifacts: Option<usize>,
+    #[clap(long, about = "Authenticate, list and evaluate the filter rules without writing files")]
+    dry_run: bool,
+    #[clap(long, requires = "dry-run", about = "With --dry-run, also list the artifacts per package")]
+    list_artifacts: bool,
+    #[clap(short, long, parse(from_occurrences), about = "Show more detail, -v adds every HTTP request, -vv everything")]
+    verbose: u64,
+    #[clap(short, long, conflicts_with = "verbose", about = "Only show warnings, errors and the final result")]
+    quiet: bool,
+    #[clap(long, about = "Show the body of failed API responses, may contain tenant data")]
+    show_response_body: bool,
+    #[clap(long, about = "Print request timings per endpoint after the sync")]
+    timings: bool,
+    #[clap(long, parse(try_from_str = cpi_sync::parse_size), about = "Only download the most recently modified artifacts that fit into this size, e.g. 500MB")]
+    size_budget: Option<u64>,
+    #[clap(long, about = "Remove local packages and artifacts that are gone from the tenant after the sync")]
+    prune: bool,
+    #[clap(long = "package", multiple_occurrences = true, number_of_values = 1, conflicts_with = "prune", about = "Only sync this package id instead of packages.filter_rules, repeat for more")]
+    package: Vec<String>,
+    #[clap(long, conflicts_with_all = &["no-input", "package", "prune"], about = "Pick the packages to sync from a list of the tenant's packages")]
+    interactive_select: bool,
+    #[clap(long, about = "Commit local_dir to git after a successful sync")]
+    git_commit: bool,
+    #[clap(long, about = "Save progress to this file after each package and resume from it in the next run")]
+    checkpoint: Option<String>,
+    #[clap(long, about = "Write a JSON report of the sync to this file, also when it fails")]
+    report_json: Option<String>,
+    #[clap(long, about = "Only run this entry of the tenants array")]
+    tenant: Option<String>,
+    #[clap(long, about = "Stop at the first failed tenant instead of running the others")]
+    fail_fast: bool,
+    #[clap(long, default_value = "1", about = "Sync this many tenants at the same time, needs --no-input above 1")]
+    tenant_concurrency: usize,
+    #[clap(long, about = "Tenants with the same management host share this many parallel requests")]
+    share_host_limit: Option<usize>,
+    #[clap(long, about = "Treat config lint warnings as errors")]
+    strict_config: bool,
+    #[clap(long, about = "Validate the config with this JSON schema instead of the bundled one")]
+    schema: Option<String>,
+    #[clap(long, requires = "schema", about = "Validate with --schema in addition to the bundled schema")]
+    schema_extra: bool,
+    #[clap(subcommand)]
+    subcmd: Option<SubCommand>,
+}
+
+#[derive(Clap, Debug)]
+enum SubCommand {
+    #[clap(about = "Check connectivity and measure latency to the tenant")]
+    Ping(PingOpts),
+    #[clap(about = "Fetch and summarize the tenant's OData service metadata")]
+    Metadata,
+    #[clap(about = "Open a package or artifact in the tenant WebUI")]
+    Open(OpenOpts),
+    #[clap(about = "Verify credentials and API access without syncing")]
+    Check(CheckOpts),
+    #[clap(about = "Print content hashes of the local mirror, works offline")]
+    Hash(HashOpts),
+    #[clap(about = "Compare local_dir with manifest.json of the last sync, works offline")]
+    Verify,
+    #[clap(about = "Replace deprecated fields in the config file")]
+    MigrateConfig(MigrateConfigOpts),
+    #[clap(about = "Upload local integration flows back to the tenant")]
+    Push(PushOpts),
+    #[clap(about = "Create a config file to start from")]
+    Init(InitOpts),
+    #[clap(about = "Collect redacted config and platform info into a zip for bug reports")]
+    SupportBundle(SupportBundleOpts),
+    #[clap(about = "Export the selected packages as a Cloud Transport Management archive")]
+    ExportTransport(ExportTransportOpts),
+    #[clap(about = "List the selected artifacts with their Active version, without downloading")]
+    List(ListOpts),
+    #[clap(about = "Compare the tenant with local_dir and print what a sync would change, writes nothing")]
+    Diff(DiffOpts),
+    #[clap(about = "Remove the packages and artifacts a sync wrote that are gone from the tenant, without downloading")]
+    Prune(PruneOpts),
+}
+
+#[derive(Clap, Debug)]
+struct PruneOpts {
+    #[clap(long, conflicts_with = "apply", about = "Write the deletions to this JSON plan instead of running them")]
+    plan: Option<String>,
+    #[clap(long, about = "Run the deletions of a plan written by --plan")]
+    apply: Option<String>,
+}
+
+#[derive(Clap, Debug)]
+struct ListOpts {
+    #[clap(long, about = "Only list artifacts with a saved version above the Active one")]
+    stale_active: bool,
+    #[clap(long, conflicts_with = "stale-active", about = "Add the deploy status of every artifact and list the deployed artifacts no selected package has")]
+    with_deploy_status: bool,
+}
+
+#[derive(Clap, Debug)]
+struct DiffOpts {
+    #[clap(long, about = "Add the deploy status of added and changed artifacts and list the deployed artifacts no selected package has")]
+    with_deploy_status: bool,
+}
+
+#[derive(Clap, Debug)]
+struct ExportTransportOpts {
+    #[clap(long, default_value = "transport.zip", about = "Archive to write, the description goes next to it as .json")]
+    out: String,
+    #[clap(long, default_value = "cpi-sync-transport", about = "ID of the transport in mtad.yaml")]
+    mta_id: String,
+    #[clap(long, default_value = "1.0.0", about = "Version of the transport in mtad.yaml")]
+    mta_version: String,
+    #[clap(long, about = "Write the archive even if some packages failed to export")]
+    allow_partial: bool,
+}
+
+#[derive(Clap, Debug)]
+struct SupportBundleOpts {
+    #[clap(long, default_value = "cpi-sync-support.zip", about = "Zip file to write")]
+    out: String,
+}
+
+#[derive(Clap, Debug)]
+struct InitOpts {
+    #[clap(long, about = "Create the per-user config instead of ./cpi-sync.json")]
+    global: bool,
+    #[clap(long, about = "Overwrite an existing config file")]
+    force: bool,
+}
+
+#[derive(Clap, Debug)]
+struct PushOpts {
+    #[clap(long, about = "Only print what would be uploaded")]
+    dry_run: bool,
+    #[clap(long, conflicts_with_all = &["dry-run", "apply"], about = "Write the uploads to this JSON plan instead of running them")]
+    plan: Option<String>,
+    #[clap(long, conflicts_with = "dry-run", about = "Run the uploads of a plan written by --plan")]
+    apply: Option<String>,
+}
+
+#[derive(Clap, Debug)]
+struct MigrateConfigOpts {
+    #[clap(long, about = "Write the migrated config back to the file instead of printing it")]
+    write: bool,
+}
+
+#[derive(Clap, Debug)]
+struct HashOpts {
+    #[clap(long, about = "Only hash this package directory")]
+    package: Option<String>,
+}
+
+#[derive(Clap, Debug)]
+struct CheckOpts {
+    #[clap(long, about = "Only authenticate, don't list packages")]
+    auth_only: bool,
+    #[clap(long, default_value = "text", possible_values = &["text", "json"], about = "Output format")]
+    output: String,
+}
+
+#[derive(Clap, Debug)]
+struct OpenOpts {
+    #[clap(about = "Package id, package name or artifact id")]
+    id: String,
+    #[clap(long, about = "Print the URL without opening a browser")]
+    print_only: bool,
+}
+
+#[derive(Clap, Debug)]
+struct PingOpts {
+    #[clap(long, default_value = "5", about = "Number of requests")]
+    count: usize,
+    #[clap(long, default_value = "1s", parse(try_from_str = cpi_sync::parse_duration), about = "Time to wait between requests, e.g. 500ms, 2s, 1m")]
+    interval: std::time::Duration,
+    #[clap(long, default_value = "text", possible_values = &["text", "json"], about = "Output format")]
+    output: String,
 }
 
 fn pause() {
     println!("Press any key to continue...");
     loop {
         // `read()` blocks until an `Event` is available
-        match read().unwrap() {
-            Event::Key(_) => {
-                // println!("{:?}", event);
-                break;
-            }
-            _ => {}
+        if let Event::Key(_) = read().unwrap() {
+            // println!("{:?}", event);
+            break;
         }
     }
 }
 
-async fn run_console(opts: &Opts) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Start CPI Sync?");
-    if !opts.no_input {
-        pause();
+//draft is detected from `$schema`, Draft 7 is the default
+fn schema_draft(schema: &Value) -> Draft {
+    match schema.get("$schema").and_then(|v| v.as_str()) {
+        Some(s) if s.contains("draft-04") => Draft::Draft4,
+        Some(s) if s.contains("draft-06") => Draft::Draft6,
+        Some(s) if s.contains("2019-09") => Draft::Draft201909,
+        Some(s) if s.contains("2020-12") => Draft::Draft202012,
+        _ => Draft::Draft7,
     }
+}
 
-    let schema_str = include_str!("../resources/config.schema.json");
-    let json_schema: Value = serde_json::from_str(schema_str).unwrap();
-
+fn validate_config_json(
+    schema_name: &str,
+    schema: &Value,
+    config_json: &Value,
+) -> Result<bool, Error> {
     let compiled_schema = JSONSchema::options()
-        .with_draft(Draft::Draft7)
-        .compile(&json_schema)?;
+        .with_draft(schema_draft(schema))
+        .compile(schema)
+        .map_err(|e| Error::ConfigValidation(format!("Invalid {}: {}", schema_name, e)))?;
+
+    let result = compiled_schema.validate(config_json);
+    if let Err(errors) = result {
+        for error in errors {
+            log::error!("Validation error ({}): {}", schema_name, error);
+        }
+        return Ok(false);
+    }
+    Ok(true)
+}
 
+//the name is None without a tenants array
+type TenantConfig = (Option<String>, cpi_sync::Config);
+
+//one config per tenant
+fn read_config(
+    opts: &Opts,
+    config_path: &str,
+) -> Result<Vec<TenantConfig>, Error> {
     let mut config_str = String::new();
-    File::open(&opts.config)?.read_to_string(&mut config_str)?;
+    File::open(config_path)?.read_to_string(&mut config_str)?;
     // let reader = BufReader::new(file);
 
-    // Read the JSON contents of the file as an instance of `User`.
-    let config_json: serde_json::Value = serde_json::from_str(&config_str)?;
+    // Read the JSON (or YAML) contents of the file as an instance of `User`.
+    let mut config_json = cpi_sync::parse_config_text(config_path, &config_str)?;
+    cpi_sync::check_config_version(&config_json)?;
 
-    let result = compiled_schema.validate(&config_json);
-    if let Err(errors) = result {
-        for error in errors {
-            println!("Validation error: {}", error);
+    //deprecated fields are mapped first, schemas only know the current names
+    let deprecations = cpi_sync::apply_deprecations(&mut config_json);
+    for deprecation in &deprecations {
+        log::warn!("Config deprecation: {}", deprecation);
+    }
+    if !deprecations.is_empty() {
+        log::warn!("Run `cpisync migrate-config --write` to update {}", config_path);
+    }
+
+    let mut valid = true;
+    if opts.schema.is_none() || opts.schema_extra {
+        let schema_str = include_str!("../resources/config.schema.json");
+        let json_schema: Value = serde_json::from_str(schema_str).unwrap();
+        valid &= validate_config_json("bundled schema", &json_schema, &config_json)?;
+    }
+    if let Some(schema_path) = &opts.schema {
+        let mut schema_str = String::new();
+        File::open(schema_path)?.read_to_string(&mut schema_str)?;
+        let json_schema: Value = serde_json::from_str(&schema_str)?;
+        let schema_name = format!("user schema {}", schema_path);
+        valid &= validate_config_json(&schema_name, &json_schema, &config_json)?;
+    }
+    if !valid {
+        return Err(Error::ConfigValidation("JSON Schema validation error.".to_string()));
+    }
+
+    let tenants =
+        cpi_sync::select_tenant(cpi_sync::split_tenants(&config_json)?, opts.tenant.as_deref())?;
+    let mut configs = Vec::new();
+    let mut has_lints = false;
+    for tenant in tenants {
+        let mut config: cpi_sync::Config = serde_json::from_value(tenant.value)?;
+        cpi_sync::resolve_service_keys(&mut config)?;
+
+        let lints = cpi_sync::lint_config(&config);
+        for lint in &lints {
+            match &tenant.name {
+                Some(name) => log::warn!("Config ({}): {}", name, lint),
+                None => log::warn!("Config: {}", lint),
+            }
+        }
+        has_lints |= !lints.is_empty();
+        configs.push((tenant.name, config));
+    }
+    if opts.strict_config && has_lints {
+        return Err(Error::ConfigValidation("Config lint error (--strict-config).".to_string()));
+    }
+
+    Ok(configs)
+}
+
+//every tenant keeps its own checkpoint and report, sync.json becomes sync.dev.json
+fn tenant_file_path(path: &str, tenant: Option<&str>) -> std::path::PathBuf {
+    let path = std::path::PathBuf::from(path);
+    let tenant = match tenant {
+        Some(tenant) => tenant,
+        None => return path,
+    };
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, tenant, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, tenant),
+    };
+    path.with_file_name(file_name)
+}
+
+fn subcommand_name(subcmd: &Option<SubCommand>) -> Option<&'static str> {
+    match subcmd {
+        Some(SubCommand::Ping(_)) => Some("ping"),
+        Some(SubCommand::Metadata) => Some("metadata"),
+        Some(SubCommand::Open(_)) => Some("open"),
+        Some(SubCommand::Check(_)) => Some("check"),
+        Some(SubCommand::Hash(_)) => Some("hash"),
+        Some(SubCommand::Verify) => Some("verify"),
+        Some(SubCommand::MigrateConfig(_)) => Some("migrate-config"),
+        Some(SubCommand::Push(_)) => Some("push"),
+        Some(SubCommand::Init(_)) => Some("init"),
+        Some(SubCommand::SupportBundle(_)) => Some("support-bundle"),
+        Some(SubCommand::ExportTransport(_)) => Some("export-transport"),
+        Some(SubCommand::List(_)) => Some("list"),
+        Some(SubCommand::Diff(_)) => Some("diff"),
+        Some(SubCommand::Prune(_)) => Some("prune"),
+        None => None,
+    }
+}
+
+//option conflicts are reported before authentication and before any prompt
+fn check_options(opts: &Opts, config: &cpi_sync::Config) -> Result<(), Error> {
+    let settings = cpi_sync::EffectiveSettings {
+        config,
+        subcommand: subcommand_name(&opts.subcmd),
+        no_input: opts.no_input,
+        yes: opts.yes,
+        confirm_secret: opts.confirm_secret,
+        ignore_error_download: opts.ignore_error_download,
+        max_artifacts_flag: opts.max_artifacts.is_some(),
+        dry_run: opts.dry_run,
+        checkpoint: opts.checkpoint.is_some(),
+        report_json: opts.report_json.is_some(),
+    };
+    let issues = cpi_sync::validate_options(&settings);
+    let mut has_error = false;
+    for issue in &issues {
+        match issue.severity {
+            cpi_sync::OptionSeverity::Error => {
+                has_error = true;
+                log::error!("Option: {}", issue);
+            }
+            cpi_sync::OptionSeverity::Warning => log::warn!("Option: {}", issue),
+        }
+    }
+    if has_error {
+        return Err(Error::ConfigValidation("Invalid option combination.".to_string()));
+    }
+    Ok(())
+}
+
+//concurrent tenants can't share the console for prompts or write into the same folder
+fn check_concurrent_tenants(
+    opts: &Opts,
+    tenants: &[(Option<String>, cpi_sync::Config)],
+    config_path: &str,
+) -> Result<(), Error> {
+    if !opts.no_input {
+        return Err(Error::ConfigValidation(
+            "--tenant-concurrency above 1 needs --no-input".to_string(),
+        ));
+    }
+    let mut data_dirs: Vec<(std::path::PathBuf, &str)> = Vec::new();
+    for (name, config) in tenants {
+        let name = name.as_deref().unwrap_or("");
+        let data_dir = cpi_sync::local_data_dir(config, config_path);
+        if let Some((_, other)) = data_dirs.iter().find(|(dir, _)| *dir == data_dir) {
+            return Err(Error::ConfigValidation(format!(
+                "Tenants {} and {} use the same local_dir {}, they can't run concurrently",
+                other,
+                name,
+                data_dir.display()
+            )));
+        }
+        data_dirs.push((data_dir, name));
+    }
+    Ok(())
+}
+
+//a config file that can't be read or deserialized ends with the config exit code as well
+fn config_error(err: Error) -> Error {
+    match cpi_sync::ExitCode::of(&err) {
+        cpi_sync::ExitCode::Failure => Error::ConfigValidation(err.to_string()),
+        _ => err,
+    }
+}
+
+async fn run_console(opts: &Opts) -> Result<(), Error> {
+    if let Some(SubCommand::Init(init_opts)) = &opts.subcmd {
+        let path = if init_opts.global {
+            cpi_sync::global_config_path().ok_or_else(|| {
+                Error::ConfigValidation(
+                    "No user config directory, set XDG_CONFIG_HOME or HOME".to_string(),
+                )
+            })?
+        } else {
+            std::path::PathBuf::from(opts.config.as_deref().unwrap_or("./cpi-sync.json"))
+        };
+        //without a terminal or with --no-input the template is written like before
+        if opts.no_input || !std::io::stdin().is_terminal() {
+            return cpi_sync::run_init(&path, None, init_opts.force);
+        }
+        if path.exists() && !init_opts.force {
+            return Err(Error::ConfigValidation(format!(
+                "Config file already exists: {}, use --force to overwrite it",
+                path.display()
+            )));
+        }
+        let config_json = cpi_sync::ask_init_config()?;
+        let schema_str = include_str!("../resources/config.schema.json");
+        let json_schema: Value = serde_json::from_str(schema_str).unwrap();
+        if !validate_config_json("bundled schema", &json_schema, &config_json)? {
+            return Err(Error::ConfigValidation(
+                "The answers don't make a valid config.".to_string(),
+            ));
+        }
+        if cpi_sync::ask_connection_check()? {
+            let mut config: cpi_sync::Config = serde_json::from_value(config_json.clone())?;
+            let checked = match cpi_sync::resolve_service_keys(&mut config) {
+                Ok(()) => cpi_sync::run_check(&config, false, false, false, false).await,
+                Err(err) => Err(err),
+            };
+            //the config is written anyway, the secret may just not be exported yet
+            if let Err(err) = checked {
+                log::error!("Connection test failed: {}", err);
+            }
+        }
+        return cpi_sync::run_init(&path, Some(&config_json), init_opts.force);
+    }
+
+    let config_path = cpi_sync::discover_config(opts.config.as_deref())?;
+    if opts.config.is_none() {
+        log::info!("Using config: {}", config_path);
+    }
+
+    //works on the raw file, the config doesn't need to be valid for the current version yet
+    if let Some(SubCommand::MigrateConfig(migrate_opts)) = &opts.subcmd {
+        return cpi_sync::run_migrate_config(&config_path, migrate_opts.write);
+    }
+    //a bug report should also work with a config that doesn't validate
+    if let Some(SubCommand::SupportBundle(bundle_opts)) = &opts.subcmd {
+        return cpi_sync::run_support_bundle(&config_path, std::path::Path::new(&bundle_opts.out));
+    }
+
+    let mut tenants = read_config(opts, &config_path).map_err(config_error)?;
+    for (_, config) in tenants.iter_mut() {
+        if opts.max_artifacts.is_some() {
+            config.packages.max_artifacts = opts.max_artifacts;
+        }
+        if opts.size_budget.is_some() {
+            config.packages.size_budget = opts.size_budget;
+        }
+        if opts.prune {
+            config.packages.prune = true;
+        }
+        if !opts.package.is_empty() {
+            cpi_sync::select_packages(config, &opts.package);
+            //every other package would look removed
+            if config.packages.prune {
+                log::warn!("packages.prune is skipped with --package");
+                config.packages.prune = false;
+            }
+        }
+        if opts.interactive_select && config.packages.prune {
+            log::warn!("packages.prune is skipped with --interactive-select");
+            config.packages.prune = false;
+        }
+        if opts.git_commit {
+            config.git.commit = true;
+        }
+        check_options(opts, config)?;
+    }
+
+    //subcommands work on a single tenant
+    if let (Some(subcommand), true) = (subcommand_name(&opts.subcmd), tenants.len() > 1) {
+        let names: Vec<&str> = tenants.iter().filter_map(|(name, _)| name.as_deref()).collect();
+        return Err(Error::ConfigValidation(format!(
+            "The config has {} tenants ({}), select one with --tenant for {}",
+            tenants.len(),
+            names.join(", "),
+            subcommand
+        )));
+    }
+    if opts.interactive_select && opts.subcmd.is_none() {
+        if tenants.len() > 1 {
+            return Err(Error::ConfigValidation(
+                "--interactive-select picks the packages of one tenant, select it with --tenant".to_string(),
+            ));
+        }
+        if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+            return Err(Error::ConfigValidation(
+                "--interactive-select needs a terminal, use --package or filter_rules instead".to_string(),
+            ));
+        }
+        cpi_sync::enable_interactive_select();
+    }
+    if opts.tenant_concurrency > 1 && tenants.len() > 1 {
+        check_concurrent_tenants(opts, &tenants, &config_path)?;
+    }
+    let config = &tenants[0].1;
+
+    match &opts.subcmd {
+        Some(SubCommand::Ping(ping_opts)) => {
+            return cpi_sync::run_ping(
+                config,
+                opts.no_input,
+                opts.confirm_secret,
+                ping_opts.count,
+                ping_opts.interval,
+                ping_opts.output == "json",
+            )
+            .await;
+        }
+        Some(SubCommand::Metadata) => {
+            return cpi_sync::run_metadata(config, opts.no_input, opts.confirm_secret).await;
+        }
+        Some(SubCommand::Open(open_opts)) => {
+            return cpi_sync::run_open(
+                config,
+                &open_opts.id,
+                opts.no_input,
+                opts.confirm_secret,
+                open_opts.print_only,
+            )
+            .await;
+        }
+        Some(SubCommand::Check(check_opts)) => {
+            return cpi_sync::run_check(
+                config,
+                opts.no_input,
+                opts.confirm_secret,
+                check_opts.auth_only,
+                check_opts.output == "json",
+            )
+            .await;
+        }
+        Some(SubCommand::List(list_opts)) => {
+            return cpi_sync::run_list(
+                config,
+                opts.no_input,
+                opts.confirm_secret,
+                list_opts.stale_active,
+                list_opts.with_deploy_status,
+            )
+            .await;
+        }
+        Some(SubCommand::Diff(diff_opts)) => {
+            return cpi_sync::run_diff(
+                config,
+                &config_path,
+                opts.no_input,
+                opts.confirm_secret,
+                diff_opts.with_deploy_status,
+            )
+            .await;
+        }
+        Some(SubCommand::Prune(prune_opts)) => {
+            return cpi_sync::run_prune(
+                config,
+                &config_path,
+                opts.no_input,
+                opts.yes,
+                opts.confirm_secret,
+                prune_opts.plan.as_deref().map(std::path::Path::new),
+                prune_opts.apply.as_deref().map(std::path::Path::new),
+            )
+            .await;
+        }
+        Some(SubCommand::Hash(hash_opts)) => {
+            return cpi_sync::run_hash(config, &config_path, hash_opts.package.as_deref());
+        }
+        Some(SubCommand::Verify) => {
+            return cpi_sync::run_verify(config, &config_path);
+        }
+        Some(SubCommand::Push(push_opts)) => {
+            if !push_opts.dry_run && push_opts.plan.is_none() {
+                println!("Start CPI Push? Artifacts on the tenant will be overwritten.");
+                if !opts.no_input {
+                    pause();
+                }
+            }
+            if let Some(plan) = &push_opts.apply {
+                return cpi_sync::run_push_apply(
+                    config,
+                    &config_path,
+                    opts.no_input,
+                    opts.confirm_secret,
+                    std::path::Path::new(plan),
+                )
+                .await;
+            }
+            return cpi_sync::run_push(
+                config,
+                &config_path,
+                opts.no_input,
+                opts.confirm_secret,
+                push_opts.dry_run,
+                push_opts.plan.as_deref().map(std::path::Path::new),
+            )
+            .await;
+        }
+        Some(SubCommand::ExportTransport(export_opts)) => {
+            return cpi_sync::run_export_transport(
+                config,
+                opts.no_input,
+                opts.confirm_secret,
+                std::path::Path::new(&export_opts.out),
+                &export_opts.mta_id,
+                &export_opts.mta_version,
+                export_opts.allow_partial,
+            )
+            .await;
+        }
+        Some(SubCommand::MigrateConfig(_))
+        | Some(SubCommand::Init(_))
+        | Some(SubCommand::SupportBundle(_)) => {
+            unreachable!("handled before the config is read")
+        }
+        None => {}
+    }
+
+    let dry_run = match (opts.dry_run, opts.list_artifacts) {
+        (false, _) => None,
+        (true, false) => Some(cpi_sync::DryRun::Packages),
+        (true, true) => Some(cpi_sync::DryRun::Artifacts),
+    };
+
+    //a dry run writes nothing, there is nothing to confirm
+    if dry_run.is_none() {
+        println!("Start CPI Sync?");
+        if !opts.no_input {
+            pause();
         }
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "JSON Schema validation error.",
-        )
-        .into());
     }
 
-    let config: cpi_sync::Config = serde_json::from_str(&config_str)?;
+    //first Ctrl+C cancels the sync through the same token an embedder would use, second one exits
+    let cancel = cpi_sync::CancellationToken::new();
+    let ctrl_c_cancel = cancel.clone();
+    let ctrl_c = tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            log::warn!("Cancelling... (press Ctrl+C again to exit immediately)");
+            ctrl_c_cancel.cancel();
+        }
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
 
-    return cpi_sync::run_with_config(&config, &opts.config, opts.no_input, opts.ignore_error_download).await;
+    if let Some(limit) = opts.share_host_limit {
+        for (_, config) in &tenants {
+            cpi_sync::share_host_limit(&config.tenant.management_host, limit);
+        }
+    }
+
+    //tenant_concurrency tenants run at a time, a failed tenant doesn't stop the others unless
+    //--fail-fast. Concurrent tenants prefix their messages with the tenant name.
+    let prefixed = opts.tenant_concurrency > 1 && tenants.len() > 1;
+    let runs = tenants.iter().enumerate().map(|(index, (name, config))| {
+        let cancel = &cancel;
+        let config_path = &config_path;
+        async move {
+            //tenants after a --fail-fast stop aren't started
+            if cancel.is_cancelled() {
+                return (index, None, std::time::Duration::default());
+            }
+            let start = std::time::Instant::now();
+            if let (Some(name), false) = (name, prefixed) {
+                log::info!("=== Tenant: {} ===", name);
+            }
+            let checkpoint = opts
+                .checkpoint
+                .as_deref()
+                .map(|path| tenant_file_path(path, name.as_deref()));
+            let report = opts
+                .report_json
+                .as_deref()
+                .map(|path| tenant_file_path(path, name.as_deref()));
+            let run = cpi_sync::run_with_config(
+                config,
+                config_path,
+                opts.no_input,
+                opts.yes,
+                opts.ignore_error_download,
+                opts.confirm_secret,
+                dry_run,
+                opts.timings,
+                checkpoint.as_deref(),
+                report.as_deref(),
+                cancel,
+            );
+            let result = match name {
+                Some(name) if prefixed => cpi_sync::with_log_prefix(name.clone(), run).await,
+                _ => run.await,
+            };
+            (index, Some(result), start.elapsed())
+        }
+    });
+    let mut runs = futures::stream::iter(runs).buffer_unordered(opts.tenant_concurrency.max(1));
+
+    let mut outcomes: Vec<Option<TenantOutcome>> = tenants.iter().map(|_| None).collect();
+    let mut stop: Option<Error> = None;
+    while let Some((index, result, duration)) = runs.next().await {
+        let name = &tenants[index].0;
+        let result = match result {
+            Some(result) => result,
+            None => continue,
+        };
+        let code = match result {
+            Ok(_) => None,
+            Err(err) => match name {
+                Some(name) if !opts.fail_fast && !cancel.is_cancelled() => {
+                    log::error!("Tenant {} failed: {}", name, err);
+                    Some(cpi_sync::ExitCode::of(&err))
+                }
+                //a single tenant, --fail-fast or Ctrl+C: the other tenants are cancelled
+                _ => {
+                    let code = cpi_sync::ExitCode::of(&err);
+                    if stop.is_none() {
+                        cancel.cancel();
+                        stop = Some(err);
+                    }
+                    Some(code)
+                }
+            },
+        };
+        outcomes[index] = Some(TenantOutcome { code, duration });
+    }
+    drop(runs);
+    ctrl_c.abort();
+
+    if tenants.len() > 1 {
+        print_tenant_summary(&tenants, &outcomes);
+    }
+    if let Some(err) = stop {
+        return Err(err);
+    }
+    let failed: Vec<(&str, cpi_sync::ExitCode)> = tenants
+        .iter()
+        .zip(outcomes.iter())
+        .filter_map(|((name, _), outcome)| {
+            let code = outcome.as_ref()?.code?;
+            Some((name.as_deref().unwrap_or(""), code))
+        })
+        .collect();
+    if !failed.is_empty() {
+        //the highest code of the failed tenants
+        let exit_code = failed
+            .iter()
+            .map(|(_, code)| *code)
+            .max_by_key(|code| code.code())
+            .unwrap_or(cpi_sync::ExitCode::Failure);
+        return Err(Error::Tenants {
+            failed: failed.iter().map(|(name, _)| name.to_string()).collect(),
+            total: tenants.len(),
+            exit_code,
+        });
+    }
+    Ok(())
+}
+
+// how a tenant of a multi-tenant run ended, None for a tenant that wasn't started
+struct TenantOutcome {
+    code: Option<cpi_sync::ExitCode>,
+    duration: std::time::Duration,
+}
+
+fn print_tenant_summary(
+    tenants: &[(Option<String>, cpi_sync::Config)],
+    outcomes: &[Option<TenantOutcome>],
+) {
+    let width = tenants
+        .iter()
+        .filter_map(|(name, _)| name.as_ref().map(|n| n.len()))
+        .max()
+        .unwrap_or(0)
+        .max("Tenant".len());
+    println!("{:<width$}  {:<16}  {:>8}", "Tenant", "Result", "Seconds", width = width);
+    for ((name, _), outcome) in tenants.iter().zip(outcomes.iter()) {
+        let (result, seconds) = match outcome {
+            Some(TenantOutcome { code: None, duration }) => ("success".to_string(), duration.as_secs().to_string()),
+            Some(TenantOutcome { code: Some(code), duration }) => (
+                format!("failed (exit {})", code.code()),
+                duration.as_secs().to_string(),
+            ),
+            None => ("not run".to_string(), "-".to_string()),
+        };
+        println!(
+            "{:<width$}  {:<16}  {:>8}",
+            name.as_deref().unwrap_or(""),
+            result,
+            seconds,
+            width = width
+        );
+    }
 }
 
 #[allow(clippy::needless_return)]
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<(), Error> {
     let opts: Opts = Opts::parse();
+    cpi_sync::init_logging(opts.verbose, opts.quiet, opts.show_response_body);
+    //CI logs get the plain log lines
+    let interactive = std::io::stdout().is_terminal() && std::io::stderr().is_terminal();
+    if interactive && !opts.no_input && !opts.quiet {
+        cpi_sync::enable_progress();
+    }
     let result = run_console(&opts).await;
+    //also after a failed run, the selection is worth keeping
+    cpi_sync::print_selected_filter_rules();
 
     match result {
         Ok(()) => {
-            println!("Completed successfully.");
+            if opts.subcmd.is_none() {
+                println!("Completed successfully.");
+            }
             if !opts.no_input {
                 pause();
             }
             return Ok(());
         }
         Err(err) => {
-            println!("{:?}", err);
+            eprintln!("Error: {}", err);
             if !opts.no_input {
                 pause();
             }
-            return Err(err);
+            std::process::exit(cpi_sync::ExitCode::of(&err).code());
         }
     };
 }
@@ -0,0 +1,315 @@
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+// package directories the sync created below packages.local_dir. With the default local_dir "."
+// the config file and the user's own files and folders live next to the packages, prune and the
+// mirror hash only look at the directories listed here. Inside a package directory the sync and
+// prune only replace or remove the artifact folders and files recorded here, a `docs/` folder
+// next to them stays.
+pub(crate) const FILE_NAME: &str = ".cpi-sync-packages.json";
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct ManagedState {
+    packages: BTreeSet<String>,
+    // package directory -> artifact folder or file name -> artifact id
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    artifacts: BTreeMap<String, BTreeMap<String, String>>,
+    // top-level folders of packages.layout, e.g. "iflows". They aren't packages, the mirror hash
+    // covers them but prune and diff don't look at them.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    layout_dirs: BTreeSet<String>,
+}
+
+pub(crate) struct ManagedPaths {
+    path: PathBuf,
+    // None for a local_dir synced before the file existed
+    state: Option<ManagedState>,
+}
+
+impl ManagedPaths {
+    pub(crate) fn read(data_dir: &Path) -> Result<ManagedPaths, Error> {
+        let path = data_dir.join(FILE_NAME);
+        let state = crate::atomic::read_with_backup::<ManagedState>(&path)?;
+        Ok(ManagedPaths { path, state })
+    }
+
+    pub(crate) fn is_known(&self) -> bool {
+        self.state.is_some()
+    }
+
+    pub(crate) fn is_managed(&self, package_id: &str) -> bool {
+        match &self.state {
+            Some(state) => state.packages.contains(package_id),
+            None => false,
+        }
+    }
+
+    // package directories of a local_dir without the file are treated as the user's own, the
+    // mirror hash keeps counting every directory there
+    pub(crate) fn includes(&self, package_id: &str) -> bool {
+        !self.is_known() || self.is_managed(package_id)
+    }
+
+    pub(crate) fn add<'a>(
+        &mut self,
+        package_ids: impl Iterator<Item = &'a String>,
+    ) -> Result<(), Error> {
+        let state = self.state.get_or_insert_with(ManagedState::default);
+        let before = state.packages.len();
+        state.packages.extend(package_ids.cloned());
+        if state.packages.len() == before && self.path.exists() {
+            return Ok(());
+        }
+        self.write()
+    }
+
+    // a folder recorded as a package by an earlier version is moved over
+    pub(crate) fn add_layout_dirs<'a>(
+        &mut self,
+        dirs: impl Iterator<Item = &'a String>,
+    ) -> Result<(), Error> {
+        let state = self.state.get_or_insert_with(ManagedState::default);
+        let mut changed = false;
+        for dir in dirs {
+            changed |= state.packages.remove(dir);
+            changed |= state.layout_dirs.insert(dir.clone());
+        }
+        if !changed && self.path.exists() {
+            return Ok(());
+        }
+        self.write()
+    }
+
+    pub(crate) fn layout_dirs(&self) -> Vec<String> {
+        match &self.state {
+            Some(state) => state.layout_dirs.iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // records the artifact names the sync wrote in a package directory, with their ids
+    pub(crate) fn add_artifacts<'a>(
+        &mut self,
+        package_id: &str,
+        artifacts: impl Iterator<Item = (&'a String, &'a String)>,
+    ) -> Result<(), Error> {
+        let state = self.state.get_or_insert_with(ManagedState::default);
+        let recorded = state.artifacts.entry(package_id.to_string()).or_default();
+        let mut changed = false;
+        for (name, artifact_id) in artifacts {
+            if recorded.get(name) != Some(artifact_id) {
+                recorded.insert(name.clone(), artifact_id.clone());
+                changed = true;
+            }
+        }
+        if recorded.is_empty() {
+            state.artifacts.remove(package_id);
+        }
+        if !changed && self.path.exists() {
+            return Ok(());
+        }
+        self.write()
+    }
+
+    // replaces the recorded artifacts of a package directory with the ones synced now. A package
+    // without artifacts keeps an empty entry, it is recorded all the same.
+    pub(crate) fn set_artifacts<'a>(
+        &mut self,
+        package_id: &str,
+        artifacts: impl Iterator<Item = (&'a String, &'a String)>,
+    ) -> Result<(), Error> {
+        let state = self.state.get_or_insert_with(ManagedState::default);
+        let recorded: BTreeMap<String, String> = artifacts
+            .map(|(name, artifact_id)| (name.clone(), artifact_id.clone()))
+            .collect();
+        if state.artifacts.get(package_id) == Some(&recorded) && self.path.exists() {
+            return Ok(());
+        }
+        state.artifacts.insert(package_id.to_string(), recorded);
+        self.write()
+    }
+
+    // false for a package directory synced before artifacts were recorded
+    pub(crate) fn has_artifacts(&self, package_id: &str) -> bool {
+        self.state
+            .as_ref()
+            .is_some_and(|state| state.artifacts.contains_key(package_id))
+    }
+
+    // artifact name -> artifact id, empty for a package synced before artifacts were recorded
+    pub(crate) fn artifacts(&self, package_id: &str) -> BTreeMap<String, String> {
+        self.state
+            .as_ref()
+            .and_then(|state| state.artifacts.get(package_id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn remove_artifact(&mut self, package_id: &str, name: &str) -> Result<(), Error> {
+        if let Some(state) = self.state.as_mut() {
+            if let Some(recorded) = state.artifacts.get_mut(package_id) {
+                if recorded.remove(name).is_some() {
+                    if recorded.is_empty() {
+                        state.artifacts.remove(package_id);
+                    }
+                    return self.write();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn remove(&mut self, package_id: &str) -> Result<(), Error> {
+        if let Some(state) = self.state.as_mut() {
+            let artifacts = state.artifacts.remove(package_id).is_some();
+            if state.packages.remove(package_id) || artifacts {
+                return self.write();
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self) -> Result<(), Error> {
+        crate::atomic::write_with_backup::<ManagedState>(
+            &self.path,
+            (serde_json::to_string_pretty(&self.state)? + "\n").as_bytes(),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+
+    // local_dir "." puts the packages next to the config file and the user's own files
+    fn config_dir() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = crate::config::test_config(serde_json::json!({
+            "local_dir": ".",
+            "filter_rules": []
+        }));
+        let config_path = dir.path().join("cpi-sync.json");
+        fs::write(&config_path, "{}").unwrap();
+        let data_dir = crate::local_data_dir(&config, config_path.to_str().unwrap());
+        fs::create_dir_all(data_dir.join("docs/notes")).unwrap();
+        fs::write(data_dir.join("docs/notes/todo.md"), "mine").unwrap();
+        fs::create_dir(data_dir.join("scripts")).unwrap();
+        fs::write(data_dir.join("scripts/deploy.sh"), "mine").unwrap();
+        fs::write(data_dir.join("README.md"), "mine").unwrap();
+        fs::create_dir_all(data_dir.join("PkgA/Flow")).unwrap();
+        fs::write(data_dir.join("PkgA/Flow/flow.iflw"), "synced").unwrap();
+        (dir, data_dir)
+    }
+
+    // what a sync with nothing selected any more would remove
+    fn prune_unselected(data_dir: &Path) -> Vec<String> {
+        let plan =
+            crate::prune::plan(data_dir, "tenant.example.com", &[], &HashMap::new()).unwrap();
+        crate::prune::execute(data_dir, &plan, None).unwrap()
+    }
+
+    fn assert_user_files_kept(data_dir: &Path) {
+        assert!(data_dir.join("cpi-sync.json").is_file());
+        assert!(data_dir.join("docs/notes/todo.md").is_file());
+        assert!(data_dir.join("scripts/deploy.sh").is_file());
+        assert!(data_dir.join("README.md").is_file());
+    }
+
+    #[test]
+    fn user_files_next_to_packages_are_left_alone() {
+        let (_dir, data_dir) = config_dir();
+        ManagedPaths::read(&data_dir)
+            .unwrap()
+            .add(["PkgA".to_string()].iter())
+            .unwrap();
+
+        assert_eq!(
+            crate::diff::local_packages(&data_dir).unwrap(),
+            vec!["PkgA"]
+        );
+        let mirror = crate::hash::hash_mirror(&data_dir).unwrap();
+        let hashed: Vec<&str> = mirror
+            .packages
+            .iter()
+            .map(|p| p.package_id.as_str())
+            .collect();
+        assert_eq!(hashed, vec!["PkgA"]);
+
+        // nothing is selected any more, only the synced package goes
+        let removed = prune_unselected(&data_dir);
+        assert_eq!(removed, vec!["PkgA"]);
+        assert!(!data_dir.join("PkgA").exists());
+        assert_user_files_kept(&data_dir);
+        assert!(!ManagedPaths::read(&data_dir).unwrap().is_managed("PkgA"));
+    }
+
+    #[test]
+    fn artifacts_are_recorded_per_package() {
+        let (_dir, data_dir) = config_dir();
+        let flow = ("Flow".to_string(), "Flow".to_string());
+        let mut managed = ManagedPaths::read(&data_dir).unwrap();
+        managed.add(["PkgA".to_string()].iter()).unwrap();
+        managed
+            .add_artifacts("PkgA", std::iter::once((&flow.0, &flow.1)))
+            .unwrap();
+
+        let managed = ManagedPaths::read(&data_dir).unwrap();
+        assert_eq!(managed.artifacts("PkgA").get("Flow"), Some(&flow.1));
+        assert!(managed.artifacts("PkgB").is_empty());
+
+        let mut managed = managed;
+        managed.remove("PkgA").unwrap();
+        let managed = ManagedPaths::read(&data_dir).unwrap();
+        assert!(managed.artifacts("PkgA").is_empty());
+    }
+
+    #[test]
+    fn layout_folders_are_not_packages() {
+        let (_dir, data_dir) = config_dir();
+        fs::create_dir_all(data_dir.join("iflows/Flow")).unwrap();
+        fs::write(data_dir.join("iflows/Flow/flow.iflw"), "synced").unwrap();
+        let mut managed = ManagedPaths::read(&data_dir).unwrap();
+        managed.add(["PkgA".to_string()].iter()).unwrap();
+        // an earlier version recorded the folder as a package
+        managed.add(["iflows".to_string()].iter()).unwrap();
+        managed
+            .add_layout_dirs(["iflows".to_string()].iter())
+            .unwrap();
+
+        let managed = ManagedPaths::read(&data_dir).unwrap();
+        assert!(!managed.is_managed("iflows"));
+        assert_eq!(managed.layout_dirs(), vec!["iflows"]);
+        assert_eq!(
+            crate::diff::local_packages(&data_dir).unwrap(),
+            vec!["PkgA"]
+        );
+        let mirror = crate::hash::hash_mirror(&data_dir).unwrap();
+        let hashed: Vec<&str> = mirror
+            .packages
+            .iter()
+            .map(|p| p.package_id.as_str())
+            .collect();
+        assert_eq!(hashed, vec!["PkgA"]);
+        assert_eq!(mirror.layout_dirs.len(), 1);
+        assert_eq!(mirror.layout_dirs[0].dir, "iflows");
+        assert_eq!(mirror.layout_dirs[0].file_count, 1);
+        assert_eq!(prune_unselected(&data_dir), vec!["PkgA"]);
+        assert!(data_dir.join("iflows/Flow/flow.iflw").is_file());
+    }
+
+    #[test]
+    fn nothing_is_pruned_without_the_file() {
+        let (_dir, data_dir) = config_dir();
+        assert!(!ManagedPaths::read(&data_dir).unwrap().is_known());
+
+        let removed = prune_unselected(&data_dir);
+        assert!(removed.is_empty());
+        assert!(data_dir.join("PkgA/Flow/flow.iflw").is_file());
+        assert_user_files_kept(&data_dir);
+    }
+}
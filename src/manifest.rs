@@ -0,0 +1,369 @@
+use crate::artifact::ArtifactKind;
+use crate::config::{Config, ZipExtraction};
+use crate::error::Error;
+use crate::units;
+use crate::Identity;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+// checksums of the downloaded artifacts for audits, written to local_dir by packages.manifest
+pub(crate) const FILE_NAME: &str = "manifest.json";
+const MANIFEST_VERSION: u32 = 1;
+
+// what a download adds to the manifest, the version and type are known to the caller
+#[derive(Debug, Clone)]
+pub(crate) struct PayloadDigest {
+    pub bytes: u64,
+    pub sha256: String,
+    pub path: String,
+    pub local_sha256: String,
+    pub stored_id: Option<String>,
+    pub file_mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ManifestEntry {
+    pub package_id: String,
+    pub artifact_id: String,
+    pub artifact_type: String,
+    pub version: Option<String>,
+    pub bytes: u64,
+    // SHA-256 of the zip as downloaded, also when it is extracted
+    pub sha256: String,
+    // the zip or the extracted folder below local_dir, with '/'
+    pub path: String,
+    // hash of that file or folder after the sync, computed like `cpisync hash`
+    pub local_sha256: String,
+    // the id the tenant stores the artifact under when the listing shows another casing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stored_id: Option<String>,
+    // packages.file_mode and dir_mode the artifact was written with, e.g. "0644", checked by verify
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_mode: Option<String>,
+    pub downloaded_at: DateTime<Utc>,
+}
+
+impl ManifestEntry {
+    pub(crate) fn new(
+        package_id: &str,
+        artifact_id: &str,
+        kind: ArtifactKind,
+        version: Option<&str>,
+        digest: PayloadDigest,
+    ) -> ManifestEntry {
+        ManifestEntry {
+            package_id: package_id.to_string(),
+            artifact_id: artifact_id.to_string(),
+            artifact_type: kind.to_string(),
+            version: version.map(str::to_string),
+            bytes: digest.bytes,
+            sha256: digest.sha256,
+            path: digest.path,
+            local_sha256: digest.local_sha256,
+            stored_id: digest.stored_id,
+            file_mode: digest.file_mode.map(|mode| format!("{:04o}", mode)),
+            dir_mode: digest.dir_mode.map(|mode| format!("{:04o}", mode)),
+            downloaded_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Manifest {
+    manifest_version: u32,
+    updated_at: DateTime<Utc>,
+    // who ran the sync that wrote the file, missing in files of earlier versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    identity: Option<Identity>,
+    artifacts: Vec<ManifestEntry>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// the path the artifact was written to relative to local_dir, a zip with zip_extraction disabled
+fn artifact_path(
+    config: &Config,
+    data_dir: &Path,
+    container: &Path,
+    artifact_dir: &str,
+    kind: ArtifactKind,
+) -> String {
+    //with "both" the folder is recorded, sha256 already covers the zip
+    let name = match config.packages.zip_extraction.for_kind(kind) {
+        ZipExtraction::Disabled => format!("{}.zip", artifact_dir),
+        ZipExtraction::Enabled | ZipExtraction::Both => artifact_dir.to_string(),
+    };
+    crate::layout::relative_path(data_dir, container, &name)
+}
+
+pub(crate) fn local_sha256(path: &Path) -> Result<String, Error> {
+    match path.is_dir() {
+        true => crate::hash::hash_dir(path),
+        false => Ok(hex(&Sha256::digest(std::fs::read(path)?))),
+    }
+}
+
+// runs after the artifact and its configurations are written
+pub(crate) fn digest(
+    config: &Config,
+    data_dir: &Path,
+    container: &Path,
+    artifact_dir: &str,
+    kind: ArtifactKind,
+    payload: &[u8],
+    stored_id: Option<&str>,
+) -> Result<PayloadDigest, Error> {
+    let path = artifact_path(config, data_dir, container, artifact_dir, kind);
+    Ok(PayloadDigest {
+        bytes: payload.len() as u64,
+        sha256: hex(&Sha256::digest(payload)),
+        local_sha256: local_sha256(&local_path(data_dir, &path))?,
+        path,
+        stored_id: stored_id.map(str::to_string),
+        file_mode: config.packages.file_mode,
+        dir_mode: config.packages.dir_mode,
+    })
+}
+
+fn local_path(data_dir: &Path, path: &str) -> PathBuf {
+    path.split('/')
+        .fold(data_dir.to_path_buf(), |dir, part| dir.join(part))
+}
+
+// a damaged manifest.json falls back to manifest.json.bak of the run before
+fn read(data_dir: &Path) -> Result<Option<Manifest>, Error> {
+    crate::atomic::read_with_backup(&data_dir.join(FILE_NAME))
+}
+
+// the version each recorded zip or folder was downloaded with, empty without a manifest
+pub(crate) fn recorded_versions(data_dir: &Path) -> Result<HashMap<String, String>, Error> {
+    let artifacts = read(data_dir)?.map(|m| m.artifacts).unwrap_or_default();
+    Ok(artifacts
+        .into_iter()
+        .filter_map(|entry| Some((entry.path, entry.version?)))
+        .collect())
+}
+
+// entries of earlier runs are kept while their path exists, e.g. packages this run didn't select;
+// a downloaded artifact replaces the entry of its path
+pub(crate) fn write(
+    data_dir: &Path,
+    downloaded: Vec<ManifestEntry>,
+    identity: &Identity,
+) -> Result<(), Error> {
+    let mut artifacts: BTreeMap<String, ManifestEntry> = BTreeMap::new();
+    if let Some(previous) = read(data_dir)? {
+        for entry in previous.artifacts {
+            if local_path(data_dir, &entry.path).exists() {
+                artifacts.insert(entry.path.clone(), entry);
+            }
+        }
+    }
+    for entry in downloaded {
+        artifacts.insert(entry.path.clone(), entry);
+    }
+    let manifest = Manifest {
+        manifest_version: MANIFEST_VERSION,
+        updated_at: Utc::now(),
+        identity: Some(identity.clone()),
+        artifacts: artifacts.into_values().collect(),
+    };
+    let path = data_dir.join(FILE_NAME);
+    crate::atomic::write_with_backup::<Manifest>(
+        &path,
+        (serde_json::to_string_pretty(&manifest)? + "\n").as_bytes(),
+    )?;
+    log::info!(
+        "Manifest: {} artifact(s) in {}",
+        manifest.artifacts.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+// the files and folders of an entry whose mode isn't the recorded file_mode or dir_mode
+fn mode_differences(entry: &ManifestEntry, path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let parse = |mode: &Option<String>| mode.as_deref().and_then(|m| units::parse_mode(m).ok());
+    let (file_mode, dir_mode) = (parse(&entry.file_mode), parse(&entry.dir_mode));
+    if file_mode.is_none() && dir_mode.is_none() {
+        return Ok(Vec::new());
+    }
+    Ok(crate::permissions::mode_differences(
+        path, file_mode, dir_mode,
+    )?)
+}
+
+// compares local_dir with the manifest without contacting the tenant
+pub fn run_verify(config: &Config, config_path: &str) -> Result<(), Error> {
+    let data_dir = crate::local_data_dir(config, config_path);
+    let manifest = read(&data_dir)?.ok_or_else(|| {
+        Error::NotFound(format!(
+            "No {} in {}, set packages.manifest and sync first",
+            FILE_NAME,
+            data_dir.display()
+        ))
+    })?;
+    let (mut missing, mut changed) = (0, 0);
+    for entry in manifest.artifacts.iter() {
+        let path = local_path(&data_dir, &entry.path);
+        if !path.exists() {
+            println!("missing\t{}", entry.path);
+            missing += 1;
+        } else if local_sha256(&path)? != entry.local_sha256 {
+            println!("changed\t{}", entry.path);
+            changed += 1;
+        } else if !mode_differences(entry, &path)?.is_empty() {
+            println!("mode\t{}", entry.path);
+            changed += 1;
+        }
+    }
+    let total = manifest.artifacts.len();
+    println!(
+        "Manifest: {} artifact(s), {} unchanged, {} changed, {} missing",
+        total,
+        total - missing - changed,
+        changed,
+        missing
+    );
+    if missing + changed > 0 {
+        return Err(Error::Validation(format!(
+            "{} artifact(s) differ from {}",
+            missing + changed,
+            FILE_NAME
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> ManifestEntry {
+        ManifestEntry::new(
+            "Pkg",
+            path,
+            ArtifactKind::Iflow,
+            Some("1.0.0"),
+            PayloadDigest {
+                bytes: 3,
+                sha256: "00".to_string(),
+                path: path.to_string(),
+                local_sha256: "11".to_string(),
+                stored_id: None,
+                file_mode: None,
+                dir_mode: None,
+            },
+        )
+    }
+
+    fn identity(redact: bool) -> Identity {
+        let mut config = crate::config::test_config(serde_json::json!({ "filter_rules": [] }));
+        config.tenant.redact_identity = redact;
+        crate::identity::resolve_identity(&config, "Basic czAwMDE6c2VjcmV0")
+    }
+
+    #[test]
+    fn manifest_records_the_identity() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), Vec::new(), &identity(false)).unwrap();
+        let manifest = read(dir.path()).unwrap().unwrap();
+        let recorded = manifest.identity.unwrap();
+        assert_eq!(recorded.user, "S0001");
+        assert_eq!(recorded.credential_type, "s_user");
+
+        write(dir.path(), Vec::new(), &identity(true)).unwrap();
+        let content = std::fs::read_to_string(dir.path().join(FILE_NAME)).unwrap();
+        assert!(!content.contains("S0001"));
+        let manifest = read(dir.path()).unwrap().unwrap();
+        assert_eq!(manifest.identity.unwrap().user, "<redacted>");
+    }
+
+    #[test]
+    fn manifest_without_identity_is_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let earlier =
+            r#"{"manifest_version": 1, "updated_at": "2024-03-01T12:00:00Z", "artifacts": []}"#;
+        std::fs::write(dir.path().join(FILE_NAME), earlier).unwrap();
+        assert!(read(dir.path()).unwrap().unwrap().identity.is_none());
+    }
+
+    #[test]
+    fn truncated_manifest_falls_back_to_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("A")).unwrap();
+        std::fs::create_dir(dir.path().join("B")).unwrap();
+        write(dir.path(), vec![entry("A")], &identity(false)).unwrap();
+        write(dir.path(), vec![entry("B")], &identity(false)).unwrap();
+
+        //a disk that ran full in the middle of the file
+        let path = dir.path().join(FILE_NAME);
+        let content = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &content[..content.len() / 2]).unwrap();
+
+        let manifest = read(dir.path()).unwrap().unwrap();
+        let paths: Vec<&str> = manifest.artifacts.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["A"]);
+
+        //the next sync writes a complete file again and keeps the backup good
+        write(dir.path(), vec![entry("B")], &identity(false)).unwrap();
+        let versions = recorded_versions(dir.path()).unwrap();
+        assert_eq!(versions.len(), 2);
+        let backup = crate::atomic::backup_path(&path);
+        let backup: Manifest = serde_json::from_slice(&std::fs::read(backup).unwrap()).unwrap();
+        assert_eq!(backup.artifacts.len(), 1);
+    }
+
+    #[test]
+    fn damaged_manifest_without_backup_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(FILE_NAME), "{\"manifest_version\": 1, ").unwrap();
+        let err = read(dir.path()).unwrap_err().to_string();
+        assert!(err.starts_with("Can not read"), "{}", err);
+    }
+
+    #[test]
+    fn write_leaves_no_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), Vec::new(), &identity(false)).unwrap();
+        let names: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec![FILE_NAME.to_string()]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn recorded_modes_are_checked() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("A");
+        std::fs::create_dir(&folder).unwrap();
+        std::fs::write(folder.join("a.txt"), "a").unwrap();
+        std::fs::set_permissions(&folder, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::set_permissions(folder.join("a.txt"), std::fs::Permissions::from_mode(0o644))
+            .unwrap();
+
+        let mut recorded = entry("A");
+        assert!(mode_differences(&recorded, &folder).unwrap().is_empty());
+        recorded.file_mode = Some("0644".to_string());
+        recorded.dir_mode = Some("0755".to_string());
+        assert!(mode_differences(&recorded, &folder).unwrap().is_empty());
+
+        std::fs::set_permissions(folder.join("a.txt"), std::fs::Permissions::from_mode(0o666))
+            .unwrap();
+        assert_eq!(
+            mode_differences(&recorded, &folder).unwrap(),
+            vec![folder.join("a.txt")]
+        );
+    }
+}
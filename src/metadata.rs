@@ -0,0 +1,145 @@
+use crate::error::Error;
+use crate::{authorization_header, check_api_url, get_authorization, http, ArtifactKind, Config};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::{env, fs};
+
+#[derive(Debug, Default)]
+struct ServiceMetadata {
+    // entity set name -> entity type name (without namespace)
+    entity_sets: Vec<(String, String)>,
+    // entity type name -> property names
+    entity_types: HashMap<String, Vec<String>>,
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .map(|a| String::from_utf8_lossy(&a.value).to_string())
+}
+
+// namespace prefixes (edmx:, edm:) differ between tenants, only local names are compared
+fn parse_metadata(xml: &str) -> Result<ServiceMetadata, Error> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut metadata = ServiceMetadata::default();
+    let mut current_type: Option<String> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"EntityType" => {
+                    if let Some(name) = attribute(&e, b"Name") {
+                        metadata.entity_types.entry(name.clone()).or_default();
+                        current_type = Some(name);
+                    }
+                }
+                b"Property" => {
+                    if let (Some(type_name), Some(name)) = (&current_type, attribute(&e, b"Name")) {
+                        metadata
+                            .entity_types
+                            .entry(type_name.clone())
+                            .or_default()
+                            .push(name);
+                    }
+                }
+                b"EntitySet" => {
+                    if let Some(name) = attribute(&e, b"Name") {
+                        let entity_type = attribute(&e, b"EntityType").unwrap_or_default();
+                        let entity_type = entity_type.rsplit('.').next().unwrap_or("").to_string();
+                        metadata.entity_sets.push((name, entity_type));
+                    }
+                }
+                _ => {}
+            },
+            Event::End(e) if e.local_name().as_ref() == b"EntityType" => {
+                current_type = None;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(metadata)
+}
+
+fn print_summary(metadata: &ServiceMetadata) {
+    println!("Entity sets ({}):", metadata.entity_sets.len());
+    for (name, _) in &metadata.entity_sets {
+        println!("  - {}", name);
+    }
+
+    println!("Artifact types:");
+    // artifact types the tool knows how to download
+    for kind in ArtifactKind::ALL.iter() {
+        let artifact_set = kind.entity_set();
+        let present = metadata
+            .entity_sets
+            .iter()
+            .any(|(name, _)| name == artifact_set);
+        println!("  {}: {}", artifact_set, if present { "yes" } else { "no" });
+    }
+
+    let iflow_type = metadata
+        .entity_sets
+        .iter()
+        .find(|(name, _)| name == "IntegrationDesigntimeArtifacts")
+        .map(|(_, entity_type)| entity_type);
+    if let Some(properties) = iflow_type.and_then(|t| metadata.entity_types.get(t)) {
+        println!(
+            "IntegrationDesigntimeArtifacts properties: {}",
+            properties.join(", ")
+        );
+    }
+}
+
+pub async fn run_metadata(
+    config: &Config,
+    no_input: bool,
+    confirm_secret: bool,
+) -> Result<(), Error> {
+    let client = http::build_client(config)?;
+    let (config, authorization) =
+        get_authorization(config, &client, no_input, confirm_secret).await?;
+    let config = &config;
+    let authorization = authorization.value().await?;
+
+    let api_metadata_url = format!("{}$metadata", check_api_url(config));
+    let request = client
+        .get(&api_metadata_url)
+        .header(authorization_header(config), &authorization);
+    let resp = http::send(config, request).await?;
+
+    let resp_success = resp.status().is_success();
+    let resp_code = resp.status();
+    let body_text = resp.text().await?;
+
+    if !resp_success {
+        log::error!("API Metadata Failed!");
+        log::error!("API URL: {}", &api_metadata_url);
+        log::error!("API Response Code: {:#?}", &resp_code);
+        crate::logging::response_body(&body_text);
+        return Err(Error::api_request(
+            "API metadata",
+            &api_metadata_url,
+            resp_code,
+            &body_text,
+        ));
+    }
+
+    let diagnostics_dir = env::temp_dir().join("cpi-sync").join("diagnostics");
+    fs::create_dir_all(&diagnostics_dir)?;
+    let metadata_path =
+        diagnostics_dir.join(format!("metadata-{}.xml", config.tenant.management_host));
+    fs::write(&metadata_path, &body_text)?;
+    log::info!("Metadata saved to: {}", metadata_path.display());
+
+    let metadata = parse_metadata(&body_text)?;
+    print_summary(&metadata);
+
+    Ok(())
+}
@@ -0,0 +1,588 @@
+use crate::artifact::ArtifactKind;
+use crate::config::{Config, ModifiedMissing, PackageRuleEnum, PropCommentRemoval, ZipExtraction};
+use std::fmt;
+
+// settings after CLI flags were applied to the config, this is what the option rules look at
+pub struct EffectiveSettings<'a> {
+    pub config: &'a Config,
+    // None for a sync run
+    pub subcommand: Option<&'a str>,
+    pub no_input: bool,
+    // --yes, prune deletes without asking
+    pub yes: bool,
+    pub confirm_secret: bool,
+    pub ignore_error_download: bool,
+    pub max_artifacts_flag: bool,
+    pub dry_run: bool,
+    pub checkpoint: bool,
+    pub report_json: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionSeverity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptionIssue {
+    pub severity: OptionSeverity,
+    pub message: &'static str,
+}
+
+impl fmt::Display for OptionIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+struct OptionRule {
+    severity: OptionSeverity,
+    applies: fn(&EffectiveSettings) -> bool,
+    message: &'static str,
+}
+
+// also the zip_extraction of filter rules, they can extract a few packages
+fn zip_disabled_for_all(config: &Config) -> bool {
+    let rule_overrides = config
+        .packages
+        .filter_rules
+        .iter()
+        .filter_map(|rule| match rule {
+            PackageRuleEnum::Single(rule) => rule.zip_extraction.as_ref(),
+            PackageRuleEnum::Regex(rule) => rule.zip_extraction.as_ref(),
+            _ => None,
+        });
+    std::iter::once(&config.packages.zip_extraction)
+        .chain(rule_overrides)
+        .all(|z| {
+            ArtifactKind::ALL
+                .iter()
+                .all(|k| matches!(z.for_kind(*k), ZipExtraction::Disabled))
+        })
+}
+
+fn has_modified_window(config: &Config) -> bool {
+    let global = &config.packages.modified;
+    global.modified_after.is_some() || global.modified_before.is_some()
+}
+
+// one entry per known conflict, new combinations are added here
+const OPTION_RULES: &[OptionRule] = &[
+    OptionRule {
+        severity: OptionSeverity::Warning,
+        applies: |s| s.subcommand.is_some() && s.max_artifacts_flag,
+        message: "--max-artifacts has no effect with a subcommand, it only applies to a sync",
+    },
+    OptionRule {
+        severity: OptionSeverity::Warning,
+        applies: |s| s.subcommand.is_some() && s.ignore_error_download,
+        message: "--ignore-error-download has no effect with a subcommand, it only applies to a sync",
+    },
+    OptionRule {
+        severity: OptionSeverity::Warning,
+        applies: |s| s.subcommand.is_some() && s.dry_run,
+        message: "--dry-run has no effect with a subcommand, it only applies to a sync (push has its own --dry-run)",
+    },
+    OptionRule {
+        severity: OptionSeverity::Warning,
+        applies: |s| s.checkpoint && (s.dry_run || s.subcommand.is_some()),
+        message: "--checkpoint has no effect with --dry-run or a subcommand, it only applies to a sync",
+    },
+    OptionRule {
+        severity: OptionSeverity::Warning,
+        applies: |s| s.config.packages.prune && s.dry_run,
+        message: "packages.prune has no effect with --dry-run, nothing is removed",
+    },
+    OptionRule {
+        severity: OptionSeverity::Error,
+        applies: |s| {
+            s.config.packages.prune && s.subcommand.is_none() && !s.dry_run && s.no_input && !s.yes
+        },
+        message: "packages.prune asks before it deletes, with --no-input it needs --yes",
+    },
+    OptionRule {
+        severity: OptionSeverity::Warning,
+        applies: |s| s.report_json && (s.dry_run || s.subcommand.is_some()),
+        message: "--report-json has no effect with --dry-run or a subcommand, it only applies to a sync",
+    },
+    OptionRule {
+        severity: OptionSeverity::Warning,
+        applies: |s| s.no_input && s.confirm_secret,
+        message: "--confirm-secret has no effect with --no-input, the secret is never prompted",
+    },
+    OptionRule {
+        severity: OptionSeverity::Warning,
+        applies: |s| {
+            matches!(
+                s.config.packages.prop_comment_removal,
+                PropCommentRemoval::Enabled
+            ) && zip_disabled_for_all(s.config)
+        },
+        message: "packages.prop_comment_removal has no effect when zip_extraction is disabled",
+    },
+    OptionRule {
+        severity: OptionSeverity::Warning,
+        applies: |s| s.config.packages.skip_empty_dirs && zip_disabled_for_all(s.config),
+        message: "packages.skip_empty_dirs has no effect when zip_extraction is disabled",
+    },
+    OptionRule {
+        severity: OptionSeverity::Warning,
+        applies: |s| {
+            s.config.packages.validate_content_strict && !s.config.packages.validate_content
+        },
+        message: "packages.validate_content_strict has no effect without packages.validate_content",
+    },
+    OptionRule {
+        severity: OptionSeverity::Warning,
+        applies: |s| {
+            matches!(
+                s.config.packages.modified_missing,
+                ModifiedMissing::Exclude
+            ) && !has_modified_window(s.config)
+                && s.config
+                    .packages
+                    .filter_rules
+                    .iter()
+                    .all(|r| !crate::filter::rule_has_window(r))
+        },
+        message: "packages.modified_missing has no effect without modified_after or modified_before",
+    },
+    OptionRule {
+        severity: OptionSeverity::Error,
+        applies: |s| s.config.packages.worker_count == 0,
+        message: "packages.worker_count must be at least 1",
+    },
+    OptionRule {
+        severity: OptionSeverity::Error,
+        applies: |s| s.config.packages.layout.is_some() && s.config.packages.prune,
+        message: "packages.prune needs a folder per package, it can't be combined with packages.layout",
+    },
+    OptionRule {
+        severity: OptionSeverity::Error,
+        applies: |s| {
+            s.config.packages.layout.is_some()
+                && matches!(s.subcommand, Some("push") | Some("diff") | Some("prune"))
+        },
+        message: "push, diff and prune read a folder per package, they can't be used with packages.layout",
+    },
+];
+
+pub fn validate_options(settings: &EffectiveSettings) -> Vec<OptionIssue> {
+    OPTION_RULES
+        .iter()
+        .filter(|rule| (rule.applies)(settings))
+        .map(|rule| OptionIssue {
+            severity: rule.severity,
+            message: rule.message,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use serde_json::json;
+
+    // the messages of a sync run with the given packages and flags
+    fn messages(
+        packages: serde_json::Value,
+        flags: impl FnOnce(&mut EffectiveSettings),
+    ) -> Vec<&'static str> {
+        let mut packages = packages;
+        if packages.get("filter_rules").is_none() {
+            packages["filter_rules"] = json!([]);
+        }
+        let config = test_config(packages);
+        let mut settings = EffectiveSettings {
+            config: &config,
+            subcommand: None,
+            no_input: false,
+            yes: false,
+            confirm_secret: false,
+            ignore_error_download: false,
+            max_artifacts_flag: false,
+            dry_run: false,
+            checkpoint: false,
+            report_json: false,
+        };
+        flags(&mut settings);
+        validate_options(&settings)
+            .into_iter()
+            .map(|issue| issue.message)
+            .collect()
+    }
+
+    fn severity_of(message: &str) -> OptionSeverity {
+        OPTION_RULES
+            .iter()
+            .find(|rule| rule.message == message)
+            .unwrap()
+            .severity
+    }
+
+    #[test]
+    fn defaults_have_no_issues() {
+        assert!(messages(json!({}), |_| {}).is_empty());
+    }
+
+    #[test]
+    fn max_artifacts_with_a_subcommand() {
+        let expected = "--max-artifacts has no effect with a subcommand, it only applies to a sync";
+        assert!(messages(json!({}), |s| s.max_artifacts_flag = true).is_empty());
+        assert_eq!(
+            messages(json!({}), |s| {
+                s.max_artifacts_flag = true;
+                s.subcommand = Some("check");
+            }),
+            vec![expected]
+        );
+        assert_eq!(severity_of(expected), OptionSeverity::Warning);
+    }
+
+    #[test]
+    fn ignore_error_download_with_a_subcommand() {
+        let expected =
+            "--ignore-error-download has no effect with a subcommand, it only applies to a sync";
+        assert!(messages(json!({}), |s| s.ignore_error_download = true).is_empty());
+        assert_eq!(
+            messages(json!({}), |s| {
+                s.ignore_error_download = true;
+                s.subcommand = Some("verify");
+            }),
+            vec![expected]
+        );
+        assert_eq!(severity_of(expected), OptionSeverity::Warning);
+    }
+
+    #[test]
+    fn dry_run_with_a_subcommand() {
+        let expected = "--dry-run has no effect with a subcommand, it only applies to a sync (push has its own --dry-run)";
+        assert!(messages(json!({}), |s| s.dry_run = true).is_empty());
+        assert_eq!(
+            messages(json!({}), |s| {
+                s.dry_run = true;
+                s.subcommand = Some("check");
+            }),
+            vec![expected]
+        );
+        assert_eq!(severity_of(expected), OptionSeverity::Warning);
+    }
+
+    #[test]
+    fn checkpoint_with_dry_run_or_a_subcommand() {
+        let expected =
+            "--checkpoint has no effect with --dry-run or a subcommand, it only applies to a sync";
+        assert!(messages(json!({}), |s| s.checkpoint = true).is_empty());
+        let with_dry_run = messages(json!({}), |s| {
+            s.checkpoint = true;
+            s.dry_run = true;
+        });
+        assert_eq!(with_dry_run, vec![expected]);
+        let with_subcommand = messages(json!({}), |s| {
+            s.checkpoint = true;
+            s.subcommand = Some("verify");
+        });
+        assert_eq!(with_subcommand, vec![expected]);
+        assert_eq!(severity_of(expected), OptionSeverity::Warning);
+    }
+
+    #[test]
+    fn prune_with_dry_run() {
+        let expected = "packages.prune has no effect with --dry-run, nothing is removed";
+        assert!(messages(json!({ "prune": true }), |_| {}).is_empty());
+        assert_eq!(
+            messages(json!({ "prune": true }), |s| s.dry_run = true),
+            vec![expected]
+        );
+        assert_eq!(severity_of(expected), OptionSeverity::Warning);
+    }
+
+    #[test]
+    fn prune_with_no_input_without_yes() {
+        let expected = "packages.prune asks before it deletes, with --no-input it needs --yes";
+        assert_eq!(
+            messages(json!({ "prune": true }), |s| s.no_input = true),
+            vec![expected]
+        );
+        let with_yes = messages(json!({ "prune": true }), |s| {
+            s.no_input = true;
+            s.yes = true;
+        });
+        assert!(with_yes.is_empty());
+        // the prune subcommand asks on its own, also for --plan that deletes nothing
+        let subcommand = messages(json!({ "prune": true }), |s| {
+            s.no_input = true;
+            s.subcommand = Some("prune");
+        });
+        assert!(subcommand.is_empty());
+        assert_eq!(severity_of(expected), OptionSeverity::Error);
+    }
+
+    #[test]
+    fn report_json_with_dry_run_or_a_subcommand() {
+        let expected =
+            "--report-json has no effect with --dry-run or a subcommand, it only applies to a sync";
+        assert!(messages(json!({}), |s| s.report_json = true).is_empty());
+        let with_dry_run = messages(json!({}), |s| {
+            s.report_json = true;
+            s.dry_run = true;
+        });
+        assert_eq!(with_dry_run, vec![expected]);
+        let with_subcommand = messages(json!({}), |s| {
+            s.report_json = true;
+            s.subcommand = Some("check");
+        });
+        assert_eq!(with_subcommand, vec![expected]);
+        assert_eq!(severity_of(expected), OptionSeverity::Warning);
+    }
+
+    #[test]
+    fn confirm_secret_with_no_input() {
+        let expected =
+            "--confirm-secret has no effect with --no-input, the secret is never prompted";
+        assert!(messages(json!({}), |s| s.confirm_secret = true).is_empty());
+        assert_eq!(
+            messages(json!({}), |s| {
+                s.confirm_secret = true;
+                s.no_input = true;
+            }),
+            vec![expected]
+        );
+        assert_eq!(severity_of(expected), OptionSeverity::Warning);
+    }
+
+    #[test]
+    fn prop_comment_removal_without_extraction() {
+        let expected =
+            "packages.prop_comment_removal has no effect when zip_extraction is disabled";
+        let disabled = json!({
+            "prop_comment_removal": "enabled",
+            "zip_extraction": "disabled"
+        });
+        assert_eq!(messages(disabled, |_| {}), vec![expected]);
+        let extracted = json!({
+            "prop_comment_removal": "enabled",
+            "zip_extraction": "both"
+        });
+        assert!(messages(extracted, |_| {}).is_empty());
+        // a filter rule that extracts a few packages still uses it
+        let rule_extracts = json!({
+            "prop_comment_removal": "enabled",
+            "zip_extraction": "disabled",
+            "filter_rules": [{ "type": "regex", "pattern": "^Z", "zip_extraction": "enabled" }]
+        });
+        assert!(messages(rule_extracts, |_| {}).is_empty());
+        assert_eq!(severity_of(expected), OptionSeverity::Warning);
+    }
+
+    #[test]
+    fn skip_empty_dirs_without_extraction() {
+        let expected = "packages.skip_empty_dirs has no effect when zip_extraction is disabled";
+        let disabled = json!({ "skip_empty_dirs": true, "zip_extraction": "disabled" });
+        assert_eq!(messages(disabled, |_| {}), vec![expected]);
+        let one_type_extracted = json!({
+            "skip_empty_dirs": true,
+            "zip_extraction": {
+                "iflow": "enabled",
+                "value_mapping": "disabled",
+                "script_collection": "disabled",
+                "message_mapping": "disabled"
+            }
+        });
+        assert!(messages(one_type_extracted, |_| {}).is_empty());
+        assert_eq!(severity_of(expected), OptionSeverity::Warning);
+    }
+
+    #[test]
+    fn validate_content_strict_without_validate_content() {
+        let expected =
+            "packages.validate_content_strict has no effect without packages.validate_content";
+        assert_eq!(
+            messages(json!({ "validate_content_strict": true }), |_| {}),
+            vec![expected]
+        );
+        let both = json!({ "validate_content": true, "validate_content_strict": true });
+        assert!(messages(both, |_| {}).is_empty());
+        assert_eq!(severity_of(expected), OptionSeverity::Warning);
+    }
+
+    #[test]
+    fn modified_missing_without_a_window() {
+        let expected =
+            "packages.modified_missing has no effect without modified_after or modified_before";
+        assert_eq!(
+            messages(json!({ "modified_missing": "exclude" }), |_| {}),
+            vec![expected]
+        );
+        let global_window = json!({
+            "modified_missing": "exclude",
+            "modified_after": "2024-01-01"
+        });
+        assert!(messages(global_window, |_| {}).is_empty());
+        let rule_window = json!({
+            "modified_missing": "exclude",
+            "filter_rules": [{ "type": "regex", "pattern": ".*", "modified_before": "2024-01-01" }]
+        });
+        assert!(messages(rule_window, |_| {}).is_empty());
+        assert_eq!(severity_of(expected), OptionSeverity::Warning);
+    }
+
+    #[test]
+    fn worker_count_zero() {
+        let expected = "packages.worker_count must be at least 1";
+        assert_eq!(
+            messages(json!({ "worker_count": 0 }), |_| {}),
+            vec![expected]
+        );
+        assert!(messages(json!({ "worker_count": 1 }), |_| {}).is_empty());
+        assert_eq!(severity_of(expected), OptionSeverity::Error);
+    }
+
+    #[test]
+    fn prune_with_layout() {
+        let expected =
+            "packages.prune needs a folder per package, it can't be combined with packages.layout";
+        let layout = "{artifact_type}/{package_id}/{artifact_id}";
+        assert_eq!(
+            messages(json!({ "prune": true, "layout": layout }), |_| {}),
+            vec![expected]
+        );
+        assert!(messages(json!({ "layout": layout }), |_| {}).is_empty());
+        assert_eq!(severity_of(expected), OptionSeverity::Error);
+    }
+
+    #[test]
+    fn push_diff_and_prune_with_layout() {
+        let expected = "push, diff and prune read a folder per package, they can't be used with packages.layout";
+        let layout = json!({ "layout": "{artifact_type}/{package_id}/{artifact_id}" });
+        for subcommand in &["push", "diff", "prune"] {
+            assert_eq!(
+                messages(layout.clone(), |s| s.subcommand = Some(subcommand)),
+                vec![expected]
+            );
+        }
+        assert!(messages(layout, |s| s.subcommand = Some("check")).is_empty());
+        assert_eq!(severity_of(expected), OptionSeverity::Error);
+    }
+
+    // one combination per rule that it has to report, a new rule needs an entry here
+    type Conflict = (
+        &'static str,
+        fn() -> serde_json::Value,
+        fn(&mut EffectiveSettings),
+    );
+    const CONFLICTS: &[Conflict] = &[
+        (
+            "--max-artifacts has",
+            || json!({}),
+            |s| {
+                s.max_artifacts_flag = true;
+                s.subcommand = Some("check");
+            },
+        ),
+        (
+            "--ignore-error-download has",
+            || json!({}),
+            |s| {
+                s.ignore_error_download = true;
+                s.subcommand = Some("check");
+            },
+        ),
+        (
+            "--dry-run has",
+            || json!({}),
+            |s| {
+                s.dry_run = true;
+                s.subcommand = Some("check");
+            },
+        ),
+        (
+            "--checkpoint has",
+            || json!({}),
+            |s| {
+                s.checkpoint = true;
+                s.dry_run = true;
+            },
+        ),
+        (
+            "packages.prune has",
+            || json!({ "prune": true }),
+            |s| s.dry_run = true,
+        ),
+        (
+            "packages.prune asks",
+            || json!({ "prune": true }),
+            |s| s.no_input = true,
+        ),
+        (
+            "--report-json has",
+            || json!({}),
+            |s| {
+                s.report_json = true;
+                s.dry_run = true;
+            },
+        ),
+        (
+            "--confirm-secret has",
+            || json!({}),
+            |s| {
+                s.confirm_secret = true;
+                s.no_input = true;
+            },
+        ),
+        (
+            "packages.prop_comment_removal has",
+            || json!({ "prop_comment_removal": "enabled", "zip_extraction": "disabled" }),
+            |_| {},
+        ),
+        (
+            "packages.skip_empty_dirs has",
+            || json!({ "skip_empty_dirs": true, "zip_extraction": "disabled" }),
+            |_| {},
+        ),
+        (
+            "packages.validate_content_strict has",
+            || json!({ "validate_content_strict": true }),
+            |_| {},
+        ),
+        (
+            "packages.modified_missing has",
+            || json!({ "modified_missing": "exclude" }),
+            |_| {},
+        ),
+        (
+            "packages.worker_count must",
+            || json!({ "worker_count": 0 }),
+            |_| {},
+        ),
+        (
+            "packages.prune needs",
+            || json!({ "prune": true, "layout": "{artifact_type}/{package_id}/{artifact_id}" }),
+            |_| {},
+        ),
+        (
+            "push, diff and prune read",
+            || json!({ "layout": "{artifact_type}/{package_id}/{artifact_id}" }),
+            |s| s.subcommand = Some("diff"),
+        ),
+    ];
+
+    #[test]
+    fn every_rule_reports_its_conflict() {
+        for rule in OPTION_RULES {
+            let (_, packages, flags) = CONFLICTS
+                .iter()
+                .find(|(prefix, _, _)| rule.message.starts_with(prefix))
+                .unwrap_or_else(|| panic!("no conflict in CONFLICTS for {:?}", rule.message));
+            let reported = messages(packages(), flags);
+            assert!(
+                reported.contains(&rule.message),
+                "{:?} isn't reported, got {:?}",
+                rule.message,
+                reported
+            );
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::error::Error;
+use crate::APIResponseResult;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+// the fields of the package list entry, so renames and description changes show up in the
+// history of the mirror. The OData bookkeeping (__metadata, deferred navigation links) differs
+// between tenants and is left out.
+pub(crate) const FILE_NAME: &str = "package.json";
+
+// keys sorted at every level, serde_json keeps the response order otherwise
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sorted(value)))
+                    .collect::<Map<String, Value>>(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sorted).collect()),
+        value => value,
+    }
+}
+
+fn is_deferred(value: &Value) -> bool {
+    value.get("__deferred").is_some()
+}
+
+pub(crate) fn write_package_metadata(
+    package_dir: &Path,
+    package: &APIResponseResult,
+) -> Result<(), Error> {
+    let mut value = serde_json::to_value(package)?;
+    if let Value::Object(map) = &mut value {
+        map.remove("__metadata");
+        map.retain(|_, v| !is_deferred(v));
+    }
+    std::fs::create_dir_all(package_dir)?;
+    std::fs::write(
+        package_dir.join(FILE_NAME),
+        serde_json::to_string_pretty(&sorted(value))? + "\n",
+    )?;
+    Ok(())
+}
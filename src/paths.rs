@@ -0,0 +1,166 @@
+use crate::error::Error;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// original ids of folders whose name had to be encoded, relative to local_dir. A dot-file, prune
+// and the mirror hash don't look at it.
+pub(crate) const NAMES_FILE: &str = ".cpi-sync-names.json";
+
+// file names Windows reserves for devices, also with an extension (`CON.txt`)
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// the name of an empty id, a `%` of an id is always encoded so a lone one is never ambiguous
+const EMPTY_ID_NAME: &str = "%";
+
+fn is_invalid_char(c: char) -> bool {
+    c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' | '%')
+}
+
+fn encode(c: char, out: &mut String) {
+    let mut buf = [0; 4];
+    for byte in c.encode_utf8(&mut buf).bytes() {
+        out.push_str(&format!("%{:02X}", byte));
+    }
+}
+
+// the folder or file name of a package id, artifact id or zip entry name. Characters Windows
+// doesn't allow, `%` itself, trailing dots and spaces and reserved names are percent-encoded on
+// every platform, so a tree synced on Linux and on Windows is the same. Valid ids stay as they are.
+pub(crate) fn dir_name(id: &str) -> String {
+    //an empty name would be local_dir or the package folder itself
+    if id.is_empty() {
+        return EMPTY_ID_NAME.to_string();
+    }
+    let stem = id.split('.').next().unwrap_or("");
+    let reserved = RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem));
+    let last = id.char_indices().last().map(|(i, _)| i);
+    let mut name = String::with_capacity(id.len());
+    for (i, c) in id.char_indices() {
+        let trailing = Some(i) == last && (c == '.' || c == ' ');
+        if is_invalid_char(c) || trailing || (reserved && i == 0) {
+            encode(c, &mut name);
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+// reverses dir_name
+pub(crate) fn id_from_dir_name(name: &str) -> String {
+    if name == EMPTY_ID_NAME {
+        return String::new();
+    }
+    let bytes = name.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = name.get(i + 1..i + 3);
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+pub(crate) fn package_dir(data_dir: &Path, package_id: &str) -> PathBuf {
+    data_dir.join(dir_name(package_id))
+}
+
+// adds the encoded folders of a run to NAMES_FILE: the path relative to local_dir and the id
+pub(crate) fn record_names(
+    data_dir: &Path,
+    names: Vec<(String, String)>,
+) -> Result<(), Error> {
+    if names.is_empty() {
+        return Ok(());
+    }
+    let path = data_dir.join(NAMES_FILE);
+    let mut recorded: BTreeMap<String, String> =
+        crate::atomic::read_with_backup(&path)?.unwrap_or_default();
+    let before = recorded.clone();
+    recorded.extend(names);
+    if recorded != before {
+        crate::atomic::write_with_backup::<BTreeMap<String, String>>(
+            &path,
+            (serde_json::to_string_pretty(&recorded)? + "\n").as_bytes(),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_names_are_encoded() {
+        assert_eq!(dir_name("CON"), "%43ON");
+        assert_eq!(dir_name("PRN"), "%50RN");
+        assert_eq!(dir_name("CON.txt"), "%43ON.txt");
+        assert_eq!(dir_name("con"), "%63on");
+        assert_eq!(dir_name("LPT9.a.b"), "%4CPT9.a.b");
+        assert_eq!(dir_name("CONSOLE"), "CONSOLE");
+        assert_eq!(dir_name("MyCON"), "MyCON");
+    }
+
+    #[test]
+    fn trailing_dot_and_space_are_encoded() {
+        assert_eq!(dir_name("Flow."), "Flow%2E");
+        assert_eq!(dir_name("Flow "), "Flow%20");
+        assert_eq!(dir_name("My Flow.v1"), "My Flow.v1");
+        assert_eq!(dir_name("."), "%2E");
+        assert_eq!(dir_name(".."), ".%2E");
+    }
+
+    #[test]
+    fn invalid_characters_and_percent_are_encoded() {
+        assert_eq!(dir_name("100%"), "100%25");
+        assert_eq!(dir_name("a%41"), "a%2541");
+        assert_eq!(dir_name("a/b\\c"), "a%2Fb%5Cc");
+        assert_eq!(dir_name("a:b*c?"), "a%3Ab%2Ac%3F");
+        assert_eq!(dir_name("tab\t"), "tab%09");
+        assert_eq!(dir_name("Größe"), "Größe");
+    }
+
+    #[test]
+    fn empty_id_is_not_the_parent_folder() {
+        assert_eq!(dir_name(""), "%");
+        assert_eq!(package_dir(Path::new("data"), ""), Path::new("data").join("%"));
+        assert_eq!(id_from_dir_name("%"), "");
+    }
+
+    #[test]
+    fn names_decode_to_their_id() {
+        let ids = [
+            "",
+            "Z_Package",
+            "CON",
+            "con.txt",
+            "Flow.",
+            "Flow ",
+            "100%",
+            "%41",
+            "%",
+            "%%",
+            "a/b\\c:d*e?f\"g<h>i|j",
+            "nul\u{0}byte",
+            "Größe ✓",
+            ".",
+            "..",
+        ];
+        for id in ids.iter() {
+            assert_eq!(&id_from_dir_name(&dir_name(id)), id, "{:?}", id);
+        }
+    }
+}
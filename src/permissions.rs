@@ -0,0 +1,166 @@
+use crate::error::Error;
+use crate::Config;
+use std::fs;
+use std::path::Path;
+
+// packages.file_mode, dir_mode and clear_quarantine for what the sync writes. Without them
+// files keep the process umask; Windows has no modes or quarantine and ignores the settings.
+fn is_set(config: &Config) -> bool {
+    config.packages.file_mode.is_some()
+        || config.packages.dir_mode.is_some()
+        || config.packages.clear_quarantine
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>) -> Result<(), std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    match mode {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Option<u32>) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+// files written by a quarantined process inherit com.apple.quarantine
+#[cfg(target_os = "macos")]
+fn clear_quarantine(path: &Path) -> Result<(), std::io::Error> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new("com.apple.quarantine")?;
+    let result = unsafe { libc::removexattr(path.as_ptr(), name.as_ptr(), libc::XATTR_NOFOLLOW) };
+    if result != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ENOATTR) {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn clear_quarantine(_path: &Path) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+// a directory gets dir_mode before its entries, symlinks are never followed
+fn apply_tree(config: &Config, path: &Path) -> Result<(), std::io::Error> {
+    let file_type = fs::symlink_metadata(path)?.file_type();
+    if file_type.is_symlink() {
+        return Ok(());
+    }
+    if config.packages.clear_quarantine {
+        clear_quarantine(path)?;
+    }
+    if file_type.is_dir() {
+        set_mode(path, config.packages.dir_mode)?;
+        for entry in fs::read_dir(path)? {
+            apply_tree(config, &entry?.path())?;
+        }
+    } else {
+        set_mode(path, config.packages.file_mode)?;
+    }
+    Ok(())
+}
+
+// the package directory itself, before its artifacts are written
+pub(crate) fn apply_package_dir(config: &Config, package_dir: &Path) -> Result<(), Error> {
+    if !is_set(config) {
+        return Ok(());
+    }
+    if config.packages.clear_quarantine {
+        clear_quarantine(package_dir)?;
+    }
+    set_mode(package_dir, config.packages.dir_mode)?;
+    Ok(())
+}
+
+// a file or folder the sync wrote, with everything in it
+pub(crate) fn apply(config: &Config, path: &Path) -> Result<(), Error> {
+    if !is_set(config) {
+        return Ok(());
+    }
+    apply_tree(config, path)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+// files and folders below path whose mode isn't the recorded one, for `verify`. Windows has no
+// modes and never reports a difference.
+pub(crate) fn mode_differences(
+    path: &Path,
+    file_mode: Option<u32>,
+    dir_mode: Option<u32>,
+) -> Result<Vec<std::path::PathBuf>, std::io::Error> {
+    let mut differences = Vec::new();
+    if cfg!(unix) {
+        collect_mode_differences(path, file_mode, dir_mode, &mut differences)?;
+    }
+    Ok(differences)
+}
+
+#[cfg(unix)]
+fn collect_mode_differences(
+    path: &Path,
+    file_mode: Option<u32>,
+    dir_mode: Option<u32>,
+    differences: &mut Vec<std::path::PathBuf>,
+) -> Result<(), std::io::Error> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    let expected = match metadata.is_dir() {
+        true => dir_mode,
+        false => file_mode,
+    };
+    if expected.is_some_and(|mode| mode != mode_of(&metadata)) {
+        differences.push(path.to_path_buf());
+    }
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_mode_differences(&entry?.path(), file_mode, dir_mode, differences)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn collect_mode_differences(
+    _path: &Path,
+    _file_mode: Option<u32>,
+    _dir_mode: Option<u32>,
+    _differences: &mut Vec<std::path::PathBuf>,
+) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+// the artifact folder or zip and the files written next to it
+pub(crate) fn apply_artifact(
+    config: &Config,
+    package_dir: &Path,
+    artifact_dir: &str,
+) -> Result<(), Error> {
+    if !is_set(config) {
+        return Ok(());
+    }
+    let paths = [
+        package_dir.join(artifact_dir),
+        package_dir.join(format!("{}.zip", artifact_dir)),
+        package_dir.join(format!("{}.configurations.json", artifact_dir)),
+        package_dir.join(format!("{}{}", artifact_dir, crate::runtime::FILE_SUFFIX)),
+    ];
+    for path in paths.iter().filter(|p| p.exists()) {
+        apply(config, path)?;
+    }
+    Ok(())
+}
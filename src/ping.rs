@@ -0,0 +1,255 @@
+use crate::error::Error;
+use crate::{authorization_header, check_api_url, get_authorization, http, Config};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::net::{lookup_host, TcpStream};
+
+// timings of a single ping attempt, in milliseconds
+#[derive(Serialize, Debug, Default)]
+struct PingAttempt {
+    attempt: usize,
+    dns_ms: Option<f64>,
+    connect_ms: Option<f64>,
+    tls_ms: Option<f64>,
+    first_byte_ms: Option<f64>,
+    status: Option<u16>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct PingStat {
+    min: f64,
+    avg: f64,
+    max: f64,
+}
+
+#[derive(Serialize, Debug)]
+struct PingSummary {
+    dns_ms: Option<PingStat>,
+    connect_ms: Option<PingStat>,
+    tls_ms: Option<PingStat>,
+    first_byte_ms: Option<PingStat>,
+}
+
+#[derive(Serialize, Debug)]
+struct PingReport {
+    host: String,
+    tls_root_store: String,
+    authenticated: bool,
+    attempts: Vec<PingAttempt>,
+    summary: PingSummary,
+}
+
+fn millis(d: Duration) -> f64 {
+    (d.as_secs_f64() * 10_000.0).round() / 10.0
+}
+
+fn stat(values: Vec<f64>) -> Option<PingStat> {
+    if values.is_empty() {
+        return None;
+    }
+    let min = values.iter().cloned().fold(f64::MAX, f64::min);
+    let max = values.iter().cloned().fold(f64::MIN, f64::max);
+    let avg = values.iter().sum::<f64>() / values.len() as f64;
+    Some(PingStat {
+        min,
+        avg: (avg * 10.0).round() / 10.0,
+        max,
+    })
+}
+
+// DNS, TCP connect and TLS handshake are measured on a raw connection,
+// first byte is measured with the same client configuration a sync uses.
+async fn ping_once(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: Option<&str>,
+    attempt: usize,
+) -> PingAttempt {
+    //api_base_url can name another host or port, or plain http for a mock server
+    let base = reqwest::Url::parse(&check_api_url(config)).ok();
+    let host = match base.as_ref().and_then(|url| url.host_str()) {
+        Some(host) => host,
+        None => config.tenant.management_host.as_str(),
+    };
+    let port = base
+        .as_ref()
+        .and_then(|url| url.port_or_known_default())
+        .unwrap_or(443);
+    let tls = base.as_ref().map(|url| url.scheme()) != Some("http");
+    let mut result = PingAttempt {
+        attempt,
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    let addr = match lookup_host((host, port)).await {
+        Ok(mut addrs) => addrs.next(),
+        Err(e) => {
+            result.error = Some(format!("DNS lookup failed: {}", e));
+            return result;
+        }
+    };
+    result.dns_ms = Some(millis(start.elapsed()));
+    let addr = match addr {
+        Some(addr) => addr,
+        None => {
+            result.error = Some("DNS lookup returned no address".to_string());
+            return result;
+        }
+    };
+
+    let start = Instant::now();
+    let stream = match TcpStream::connect(addr).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            result.error = Some(format!("Connect failed: {}", e));
+            return result;
+        }
+    };
+    result.connect_ms = Some(millis(start.elapsed()));
+
+    //certificates are verified by the real request below, the handshake is only timed here
+    if tls {
+        let connector = match native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+        {
+            Ok(connector) => tokio_native_tls::TlsConnector::from(connector),
+            Err(e) => {
+                result.error = Some(format!("TLS setup failed: {}", e));
+                return result;
+            }
+        };
+        let start = Instant::now();
+        if let Err(e) = connector.connect(host, stream).await {
+            result.error = Some(format!("TLS handshake failed: {}", e));
+            return result;
+        }
+        result.tls_ms = Some(millis(start.elapsed()));
+    }
+
+    let mut request = client
+        .get(check_api_url(config))
+        .timeout(config.http.timeout);
+    if let Some(authorization) = authorization {
+        request = request.header(authorization_header(config), authorization);
+    }
+    let start = Instant::now();
+    match request.send().await {
+        Ok(resp) => {
+            result.first_byte_ms = Some(millis(start.elapsed()));
+            result.status = Some(resp.status().as_u16());
+        }
+        Err(e) => {
+            result.error = Some(format!("Request failed: {}", e));
+        }
+    }
+
+    result
+}
+
+fn print_text(report: &PingReport) {
+    println!(
+        "Ping {} ({} attempts, authenticated: {}, TLS root store: {})",
+        report.host,
+        report.attempts.len(),
+        report.authenticated,
+        report.tls_root_store
+    );
+    let fmt = |v: Option<f64>| match v {
+        Some(v) => format!("{:>8.1}", v),
+        None => format!("{:>8}", "-"),
+    };
+    println!(
+        "{:>3}  {:>8}  {:>8}  {:>8}  {:>10}  {:>6}",
+        "#", "dns", "connect", "tls", "first byte", "status"
+    );
+    for a in &report.attempts {
+        println!(
+            "{:>3}  {}  {}  {}  {:>10}  {:>6}",
+            a.attempt,
+            fmt(a.dns_ms),
+            fmt(a.connect_ms),
+            fmt(a.tls_ms),
+            fmt(a.first_byte_ms).trim(),
+            a.status
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| "-".to_string())
+        );
+        if let Some(error) = &a.error {
+            println!("     {}", error);
+        }
+    }
+    println!("Summary in ms (min / avg / max):");
+    let rows = [
+        ("dns", &report.summary.dns_ms),
+        ("connect", &report.summary.connect_ms),
+        ("tls", &report.summary.tls_ms),
+        ("first byte", &report.summary.first_byte_ms),
+    ];
+    for (name, s) in rows.iter() {
+        match s {
+            Some(s) => println!("{:>10}: {:.1} / {:.1} / {:.1}", name, s.min, s.avg, s.max),
+            None => println!("{:>10}: -", name),
+        }
+    }
+}
+
+pub async fn run_ping(
+    config: &Config,
+    no_input: bool,
+    confirm_secret: bool,
+    count: usize,
+    interval: Duration,
+    json_output: bool,
+) -> Result<(), Error> {
+    //no idle connections are kept, so every attempt opens a new connection like a fresh sync
+    let (builder, tls_root_store) = http::client_builder(config)?;
+    let client = builder.pool_max_idle_per_host(0).build()?;
+
+    //authentication failures should not stop the transport measurement
+    let (config, authorization) =
+        match get_authorization(config, &client, no_input, confirm_secret).await {
+            Ok((config, authorization)) => (config, Some(authorization.value().await?)),
+            Err(e) => {
+                log::warn!("Authentication failed, pinging without credentials: {}", e);
+                (config.clone(), None)
+            }
+        };
+    let config = &config;
+
+    let mut attempts = Vec::new();
+    for attempt in 1..=count {
+        if attempt > 1 {
+            tokio::time::sleep(interval).await;
+        }
+        attempts.push(ping_once(config, &client, authorization.as_deref(), attempt).await);
+    }
+
+    let collect = |f: fn(&PingAttempt) -> Option<f64>| attempts.iter().filter_map(f).collect();
+    let summary = PingSummary {
+        dns_ms: stat(collect(|a| a.dns_ms)),
+        connect_ms: stat(collect(|a| a.connect_ms)),
+        tls_ms: stat(collect(|a| a.tls_ms)),
+        first_byte_ms: stat(collect(|a| a.first_byte_ms)),
+    };
+    let report = PingReport {
+        host: config.tenant.management_host.clone(),
+        tls_root_store,
+        authenticated: authorization.is_some(),
+        attempts,
+        summary,
+    };
+
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_text(&report);
+    }
+
+    if report.attempts.iter().all(|a| a.status.is_none()) {
+        return Err(Error::Failed("Ping Failed!".to_string()));
+    }
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use crate::error::Error;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+// bumped when a field is renamed or removed, an older tool refuses a newer plan
+pub(crate) const PLAN_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PlanAction {
+    Create,
+    Update,
+    Delete,
+}
+
+impl PlanAction {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            PlanAction::Create => "create",
+            PlanAction::Update => "update",
+            PlanAction::Delete => "delete",
+        }
+    }
+}
+
+// one write to the tenant or removal in local_dir, with the state it was planned against
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct PlanStep {
+    pub step: usize,
+    pub action: PlanAction,
+    pub package_id: String,
+    // None when prune removes the whole package folder
+    pub artifact_id: Option<String>,
+    // relative to packages.local_dir, with '/'
+    pub local_path: String,
+    // a zip's bytes, a directory like `hash` hashes a package
+    pub local_sha256: String,
+    // version on the tenant when planned, None for a create
+    pub remote_version: Option<String>,
+    // size of the file or folder a delete removes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+}
+
+// written by `push --plan` and `prune --plan`, executed as it is by `--apply`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Plan {
+    pub plan_version: u32,
+    pub tool_version: String,
+    pub operation: String,
+    pub host: String,
+    pub created_at: DateTime<Utc>,
+    pub steps: Vec<PlanStep>,
+}
+
+// steps of a plan already applied, kept next to the plan so an interrupted apply can be re-run
+#[derive(Serialize, Deserialize, Debug)]
+struct PlanProgressState {
+    plan_sha256: String,
+    applied: Vec<usize>,
+}
+
+pub(crate) struct PlanProgress {
+    path: PathBuf,
+    state: PlanProgressState,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn write_atomic(path: &Path, content: String) -> Result<(), Error> {
+    crate::atomic::write(path, content.as_bytes())?;
+    Ok(())
+}
+
+impl Plan {
+    pub(crate) fn new(operation: &str, host: &str, steps: Vec<PlanStep>) -> Plan {
+        Plan {
+            plan_version: PLAN_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            operation: operation.to_string(),
+            host: host.to_string(),
+            created_at: Utc::now(),
+            steps,
+        }
+    }
+
+    // the plan and the SHA-256 of the file, the progress file is bound to it
+    pub(crate) fn read(path: &Path) -> Result<(Plan, String), Error> {
+        let content = std::fs::read(path)?;
+        let plan: Plan = serde_json::from_slice(&content)?;
+        if plan.plan_version > PLAN_VERSION {
+            return Err(Error::Plan(format!(
+                "Plan {} has version {}, this cpi-sync reads up to version {}",
+                path.display(),
+                plan.plan_version,
+                PLAN_VERSION
+            )));
+        }
+        Ok((plan, hex(&Sha256::digest(&content))))
+    }
+
+    pub(crate) fn write(&self, path: &Path) -> Result<(), Error> {
+        write_atomic(path, serde_json::to_string_pretty(self)? + "\n")
+    }
+}
+
+impl PlanProgress {
+    // plan.json keeps its progress in plan.progress.json
+    pub(crate) fn path(plan_path: &Path) -> PathBuf {
+        plan_path.with_extension("progress.json")
+    }
+
+    pub(crate) fn open(plan_path: &Path, plan_sha256: &str) -> Result<PlanProgress, Error> {
+        let path = PlanProgress::path(plan_path);
+        let state = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice::<PlanProgressState>(&content)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => PlanProgressState {
+                plan_sha256: plan_sha256.to_string(),
+                applied: Vec::new(),
+            },
+            Err(err) => return Err(err.into()),
+        };
+        if state.plan_sha256 != plan_sha256 {
+            return Err(Error::Plan(format!(
+                "{} belongs to another plan, remove it to apply {} from the start",
+                path.display(),
+                plan_path.display()
+            )));
+        }
+        Ok(PlanProgress { path, state })
+    }
+
+    pub(crate) fn is_applied(&self, step: usize) -> bool {
+        self.state.applied.contains(&step)
+    }
+
+    // written after every step, an interrupted apply continues after the last one
+    pub(crate) fn mark_applied(&mut self, step: usize) -> Result<(), Error> {
+        self.state.applied.push(step);
+        write_atomic(
+            &self.path,
+            serde_json::to_string_pretty(&self.state)? + "\n",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::Value;
+
+    const SCHEMA: &str = include_str!("../resources/plan.schema.json");
+
+    fn sample_plan() -> Plan {
+        let step = |step, action, artifact_id: &str, remote_version: Option<&str>| PlanStep {
+            step,
+            action,
+            package_id: "Pkg".to_string(),
+            artifact_id: Some(artifact_id.to_string()),
+            local_path: format!("Pkg/{}", artifact_id),
+            local_sha256: "ab".repeat(32),
+            remote_version: remote_version.map(str::to_string),
+            bytes: None,
+        };
+        Plan {
+            plan_version: PLAN_VERSION,
+            tool_version: "1.2.3".to_string(),
+            operation: "push".to_string(),
+            host: "tenant.example.com".to_string(),
+            created_at: Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(),
+            steps: vec![
+                step(1, PlanAction::Update, "FlowA", Some("1.0.3")),
+                step(2, PlanAction::Create, "FlowB", None),
+                step(3, PlanAction::Update, "FlowC", Some("2.0.0")),
+            ],
+        }
+    }
+
+    // what prune plans for a package folder that is no longer selected
+    fn delete_step() -> PlanStep {
+        PlanStep {
+            step: 1,
+            action: PlanAction::Delete,
+            package_id: "Old".to_string(),
+            artifact_id: None,
+            local_path: "Old".to_string(),
+            local_sha256: "cd".repeat(32),
+            remote_version: None,
+            bytes: Some(2048),
+        }
+    }
+
+    fn keys(value: &Value) -> Vec<&str> {
+        value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn strings(value: &Value) -> Vec<&str> {
+        value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn plan_format_is_stable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.json");
+        let plan = Plan {
+            steps: sample_plan().steps[..2].to_vec(),
+            ..sample_plan()
+        };
+        plan.write(&path).unwrap();
+        let expected = r#"{
+  "plan_version": 1,
+  "tool_version": "1.2.3",
+  "operation": "push",
+  "host": "tenant.example.com",
+  "created_at": "2024-03-01T12:00:00Z",
+  "steps": [
+    {
+      "step": 1,
+      "action": "update",
+      "package_id": "Pkg",
+      "artifact_id": "FlowA",
+      "local_path": "Pkg/FlowA",
+      "local_sha256": "abababababababababababababababababababababababababababababababab",
+      "remote_version": "1.0.3"
+    },
+    {
+      "step": 2,
+      "action": "create",
+      "package_id": "Pkg",
+      "artifact_id": "FlowB",
+      "local_path": "Pkg/FlowB",
+      "local_sha256": "abababababababababababababababababababababababababababababababab",
+      "remote_version": null
+    }
+  ]
+}
+"#;
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn plan_matches_its_schema() {
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        let plan = serde_json::to_value(sample_plan()).unwrap();
+
+        assert_eq!(strings(&schema["required"]), keys(&plan));
+        assert_eq!(keys(&schema["properties"]), keys(&plan));
+        assert!(strings(&schema["properties"]["operation"]["enum"])
+            .contains(&plan["operation"].as_str().unwrap()));
+
+        let step_schema = &schema["properties"]["steps"]["items"];
+        let required = strings(&step_schema["required"]);
+        let properties = keys(&step_schema["properties"]);
+        let actions = strings(&step_schema["properties"]["action"]["enum"]);
+        assert_eq!(actions, vec!["create", "update", "delete"]);
+        let mut steps = plan["steps"].as_array().unwrap().clone();
+        steps.push(serde_json::to_value(delete_step()).unwrap());
+        for step in &steps {
+            // bytes is only written for a delete
+            assert!(required.iter().all(|key| keys(step).contains(key)));
+            assert!(keys(step).iter().all(|key| properties.contains(key)));
+            assert!(actions.contains(&step["action"].as_str().unwrap()));
+        }
+        assert_eq!(steps[0]["bytes"], Value::Null);
+        assert_eq!(steps[3]["artifact_id"], Value::Null);
+        assert_eq!(steps[3]["bytes"], 2048);
+    }
+
+    #[test]
+    fn newer_plan_version_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.json");
+        let plan = Plan {
+            plan_version: PLAN_VERSION + 1,
+            ..sample_plan()
+        };
+        plan.write(&path).unwrap();
+        let err = Plan::read(&path).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("this cpi-sync reads up to version"));
+    }
+
+    // what apply does: the steps that aren't applied yet, each one marked when done
+    fn apply_pending(plan_path: &Path, fail_at: Option<usize>) -> Vec<usize> {
+        let (plan, plan_sha256) = Plan::read(plan_path).unwrap();
+        let mut progress = PlanProgress::open(plan_path, &plan_sha256).unwrap();
+        let mut applied = Vec::new();
+        let pending: Vec<&PlanStep> = plan
+            .steps
+            .iter()
+            .filter(|s| !progress.is_applied(s.step))
+            .collect();
+        for step in pending {
+            if Some(step.step) == fail_at {
+                break;
+            }
+            progress.mark_applied(step.step).unwrap();
+            applied.push(step.step);
+        }
+        applied
+    }
+
+    #[test]
+    fn rerun_after_partial_apply_continues_where_it_stopped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.json");
+        sample_plan().write(&path).unwrap();
+
+        assert_eq!(apply_pending(&path, Some(2)), vec![1]);
+        assert!(PlanProgress::path(&path).is_file());
+        assert_eq!(apply_pending(&path, None), vec![2, 3]);
+        // a third run has nothing left
+        assert!(apply_pending(&path, None).is_empty());
+    }
+
+    #[test]
+    fn progress_of_another_plan_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.json");
+        sample_plan().write(&path).unwrap();
+        assert_eq!(apply_pending(&path, Some(2)), vec![1]);
+
+        let replanned = Plan {
+            host: "other.example.com".to_string(),
+            ..sample_plan()
+        };
+        replanned.write(&path).unwrap();
+        let (_, plan_sha256) = Plan::read(&path).unwrap();
+        let err = PlanProgress::open(&path, &plan_sha256).err().unwrap();
+        assert!(err.to_string().contains("belongs to another plan"));
+    }
+}
@@ -0,0 +1,74 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressStyle};
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// set by the command line tool on an interactive terminal, an embedder gets no bars
+static BARS: OnceLock<MultiProgress> = OnceLock::new();
+
+tokio::task_local! {
+    static DOWNLOAD_LABEL: String;
+}
+
+// shows progress bars on stderr from now on, log lines are printed above them
+pub fn enable_progress() {
+    BARS.get_or_init(MultiProgress::new);
+}
+
+// log output while bars are drawn has to clear and redraw them
+pub(crate) fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    match BARS.get() {
+        Some(bars) => bars.suspend(f),
+        None => f(),
+    }
+}
+
+// artifacts completed across all packages of a sync pass, hidden without enable_progress
+pub(crate) fn artifacts_bar(total: usize) -> ProgressBar {
+    let bars = match BARS.get() {
+        Some(bars) => bars,
+        None => return ProgressBar::hidden(),
+    };
+    let bar = bars.add(ProgressBar::new(total as u64).with_finish(ProgressFinish::AndClear));
+    bar.set_style(
+        ProgressStyle::with_template("{prefix}{bar:30} {pos}/{len} artifacts, {elapsed}")
+            .expect("valid template"),
+    );
+    bar.set_prefix(crate::logging::log_prefix());
+    bar
+}
+
+// the name shown next to the byte progress of payload downloads inside the future
+pub(crate) async fn with_download_label<F: Future>(label: String, future: F) -> F::Output {
+    DOWNLOAD_LABEL.scope(label, future).await
+}
+
+// bytes of one payload, a bar with Content-Length and a spinner without. None without
+// enable_progress, the body is then read at once.
+pub(crate) fn download_bar(content_length: Option<u64>) -> Option<ProgressBar> {
+    let bars = BARS.get()?;
+    let bar = match content_length {
+        Some(length) => {
+            let bar = ProgressBar::new(length);
+            bar.set_style(
+                ProgressStyle::with_template("{prefix}  {bar:20} {bytes}/{total_bytes} {wide_msg}")
+                    .expect("valid template"),
+            );
+            bar
+        }
+        None => {
+            let bar = ProgressBar::new_spinner();
+            bar.set_style(
+                ProgressStyle::with_template("{prefix}  {spinner} {bytes} {wide_msg}")
+                    .expect("valid template"),
+            );
+            bar.enable_steady_tick(Duration::from_millis(120));
+            bar
+        }
+    };
+    //a bar dropped by a failed or cancelled download doesn't stay on screen
+    let bar = bars.add(bar.with_finish(ProgressFinish::AndClear));
+    bar.set_prefix(crate::logging::log_prefix());
+    bar.set_message(DOWNLOAD_LABEL.try_with(|l| l.clone()).unwrap_or_default());
+    Some(bar)
+}
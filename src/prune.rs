@@ -0,0 +1,556 @@
+use crate::config::Config;
+use crate::error::Error;
+use crate::managed::{self, ManagedPaths};
+use crate::plan::{Plan, PlanAction, PlanProgress, PlanStep};
+use crate::stale::list_artifacts;
+use crate::{get_authorization, http, local_data_dir, manifest, paths, runtime};
+use path_slash::PathBufExt;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+// what a run listed in a package: the artifact folders it wrote and the artifact ids it didn't
+// download, e.g. skipped by artifact_filter_rules. A recorded artifact matching neither is gone.
+#[derive(Debug, Default)]
+pub(crate) struct ListedArtifacts {
+    pub names: Vec<String>,
+    pub ids: Vec<String>,
+}
+
+impl ListedArtifacts {
+    // folders of pinned versions are kept by their name, the tenant returns ids in any casing
+    fn keeps(&self, name: &str, artifact_id: &str) -> bool {
+        self.names.iter().any(|n| n.eq_ignore_ascii_case(name))
+            || self
+                .ids
+                .iter()
+                .any(|id| id.eq_ignore_ascii_case(artifact_id))
+    }
+}
+
+// the folder and the files the sync can write for an artifact, see budget::clear_artifact
+pub(crate) fn artifact_entries(name: &str) -> Vec<String> {
+    vec![
+        name.to_string(),
+        format!("{}.zip", name),
+        format!("{}.configurations.json", name),
+        format!("{}{}", name, runtime::FILE_SUFFIX),
+    ]
+}
+
+// a symlink is never followed, a plan only has real folders and files
+fn is_plain_entry(path: &Path) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => !metadata.file_type().is_symlink(),
+        Err(_) => false,
+    }
+}
+
+fn disk_size(path: &Path) -> Result<u64, Error> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += disk_size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+fn delete_step(
+    data_dir: &Path,
+    package_dir: &str,
+    artifact_id: Option<&str>,
+    local_path: String,
+) -> Result<PlanStep, Error> {
+    let path = data_dir.join(PathBuf::from_slash(&local_path));
+    Ok(PlanStep {
+        step: 0,
+        action: PlanAction::Delete,
+        package_id: paths::id_from_dir_name(package_dir),
+        artifact_id: artifact_id.map(str::to_string),
+        local_sha256: manifest::local_sha256(&path)?,
+        bytes: Some(disk_size(&path)?),
+        local_path,
+        remote_version: None,
+    })
+}
+
+// package directories the sync created for packages that aren't selected any more, and in the
+// packages listed by this run the artifact entries it wrote for artifacts the tenant no longer
+// returns. Only what managed::FILE_NAME records is looked at, a `docs/` folder in a package
+// directory or a package synced by an older cpi-sync is the user's.
+pub(crate) fn plan(
+    data_dir: &Path,
+    host: &str,
+    selected_packages: &[String],
+    listed_artifacts: &HashMap<String, ListedArtifacts>,
+) -> Result<Plan, Error> {
+    let managed = ManagedPaths::read(data_dir)?;
+    let mut package_dirs: Vec<String> = Vec::new();
+    for entry in fs::read_dir(data_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        //files next to the packages, like a checkpoint or report, and dot-folders aren't packages
+        if entry.file_type()?.is_dir() && !name.starts_with('.') {
+            package_dirs.push(name);
+        }
+    }
+    package_dirs.sort();
+
+    let mut steps = Vec::new();
+    for package_dir in package_dirs {
+        if !selected_packages.contains(&package_dir) {
+            if !managed.is_managed(&package_dir) {
+                log::debug!(
+                    "Prune keeps {}: not listed in {}",
+                    package_dir,
+                    managed::FILE_NAME
+                );
+                continue;
+            }
+            steps.push(delete_step(
+                data_dir,
+                &package_dir,
+                None,
+                package_dir.clone(),
+            )?);
+            continue;
+        }
+        //packages done in an earlier run of a checkpoint weren't listed, their artifacts stay
+        let listed = match listed_artifacts.get(&package_dir) {
+            Some(listed) => listed,
+            None => continue,
+        };
+        for (name, artifact_id) in managed.artifacts(&package_dir) {
+            if listed.keeps(&name, &artifact_id) {
+                continue;
+            }
+            for entry in artifact_entries(&name) {
+                let local_path = format!("{}/{}", package_dir, entry);
+                if !is_plain_entry(&data_dir.join(&package_dir).join(&entry)) {
+                    continue;
+                }
+                steps.push(delete_step(
+                    data_dir,
+                    &package_dir,
+                    Some(&artifact_id),
+                    local_path,
+                )?);
+            }
+        }
+    }
+    for (index, step) in steps.iter_mut().enumerate() {
+        step.step = index + 1;
+    }
+    Ok(Plan::new("prune", host, steps))
+}
+
+pub(crate) fn print_plan(plan: &Plan) {
+    for step in plan.steps.iter() {
+        println!(
+            "delete\t{}\t{} bytes",
+            step.local_path,
+            step.bytes.unwrap_or(0)
+        );
+    }
+    println!(
+        "Prune: {} path(s), {} bytes in total",
+        plan.steps.len(),
+        plan.steps.iter().filter_map(|s| s.bytes).sum::<u64>()
+    );
+}
+
+// a plan is only executed after a typed "yes" or with --yes, --no-input never deletes on its own
+pub(crate) fn confirm(plan: &Plan, yes: bool, no_input: bool) -> Result<bool, Error> {
+    if plan.steps.is_empty() || yes {
+        return Ok(true);
+    }
+    if no_input {
+        return Err(Error::ConfigValidation(format!(
+            "Prune would delete {} path(s), pass --yes to delete them with --no-input",
+            plan.steps.len()
+        )));
+    }
+    print!("Type yes to delete these {} path(s): ", plan.steps.len());
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim() == "yes")
+}
+
+// the package directory a step belongs to and the entry name in it, None for a whole package
+fn split_local_path(local_path: &str) -> (&str, Option<&str>) {
+    match local_path.split_once('/') {
+        Some((package_dir, entry)) => (package_dir, Some(entry)),
+        None => (local_path, None),
+    }
+}
+
+// removes exactly the paths of the plan, steps of an earlier apply are skipped. Returns the
+// removed paths relative to data_dir.
+pub(crate) fn execute(
+    data_dir: &Path,
+    plan: &Plan,
+    mut progress: Option<&mut PlanProgress>,
+) -> Result<Vec<String>, Error> {
+    let mut managed = ManagedPaths::read(data_dir)?;
+    let mut removed = Vec::new();
+    for step in plan.steps.iter() {
+        if progress.as_ref().is_some_and(|p| p.is_applied(step.step)) {
+            continue;
+        }
+        let path = data_dir.join(PathBuf::from_slash(&step.local_path));
+        if fs::symlink_metadata(&path)?.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+        match split_local_path(&step.local_path) {
+            (package_dir, None) => {
+                managed.remove(package_dir)?;
+                log::info!("Pruned package: {}", step.local_path);
+            }
+            (package_dir, Some(_)) => {
+                //the record goes with the last entry of its artifact
+                let package_path = data_dir.join(package_dir);
+                for (name, _) in managed.artifacts(package_dir) {
+                    let entries = artifact_entries(&name);
+                    if entries
+                        .iter()
+                        .all(|e| !is_plain_entry(&package_path.join(e)))
+                    {
+                        managed.remove_artifact(package_dir, &name)?;
+                    }
+                }
+                log::info!("Pruned artifact: {}", step.local_path);
+            }
+        }
+        if let Some(progress) = progress.as_mut() {
+            progress.mark_applied(step.step)?;
+        }
+        removed.push(step.local_path.clone());
+    }
+    Ok(removed)
+}
+
+// `prune --apply`: nothing is removed when a path of the plan is gone or its content changed
+fn apply(
+    config: &Config,
+    data_dir: &Path,
+    yes: bool,
+    no_input: bool,
+    plan_path: &Path,
+) -> Result<(), Error> {
+    let (plan, plan_sha256) = Plan::read(plan_path)?;
+    if plan.operation != "prune" {
+        return Err(Error::Plan(format!(
+            "Plan {} is for {}, not prune",
+            plan_path.display(),
+            plan.operation
+        )));
+    }
+    if plan.host != config.tenant.management_host {
+        return Err(Error::Plan(format!(
+            "Plan {} was made for {}, the config is for {}",
+            plan_path.display(),
+            plan.host,
+            config.tenant.management_host
+        )));
+    }
+    let mut progress = PlanProgress::open(plan_path, &plan_sha256)?;
+    let pending: Vec<PlanStep> = plan
+        .steps
+        .iter()
+        .filter(|s| !progress.is_applied(s.step))
+        .cloned()
+        .collect();
+    if pending.is_empty() {
+        println!(
+            "Apply: all {} step(s) of {} were already applied",
+            plan.steps.len(),
+            plan_path.display()
+        );
+        return Ok(());
+    }
+
+    let mut drift = Vec::new();
+    for step in pending.iter() {
+        let path = data_dir.join(PathBuf::from_slash(&step.local_path));
+        if step.action != PlanAction::Delete {
+            drift.push(format!("step {}: prune only deletes", step.step));
+        } else if !is_plain_entry(&path) {
+            drift.push(format!(
+                "step {}: {} is missing",
+                step.step, step.local_path
+            ));
+        } else if manifest::local_sha256(&path)? != step.local_sha256 {
+            drift.push(format!("step {}: {} changed", step.step, step.local_path));
+        }
+    }
+    if !drift.is_empty() {
+        for message in &drift {
+            log::error!("Plan drift: {}", message);
+        }
+        return Err(Error::Plan(format!(
+            "{} step(s) of {} no longer match the local files, create a new plan",
+            drift.len(),
+            plan_path.display()
+        )));
+    }
+
+    let remaining = Plan {
+        steps: pending,
+        ..plan.clone()
+    };
+    print_plan(&remaining);
+    if !confirm(&remaining, yes, no_input)? {
+        println!("Prune cancelled, nothing was removed");
+        return Ok(());
+    }
+    let removed = execute(data_dir, &remaining, Some(&mut progress))?;
+    println!(
+        "Apply: {} step(s) applied, {} already applied before, progress in {}",
+        removed.len(),
+        plan.steps.len() - remaining.steps.len(),
+        PlanProgress::path(plan_path).display()
+    );
+    Ok(())
+}
+
+// `prune` lists the tenant like `diff` and removes what a sync with packages.prune would, without
+// downloading. --plan writes the deletions to a file, --apply removes them without the tenant.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_prune(
+    config: &Config,
+    config_path: &str,
+    no_input: bool,
+    yes: bool,
+    confirm_secret: bool,
+    plan_path: Option<&Path>,
+    apply_path: Option<&Path>,
+) -> Result<(), Error> {
+    let data_dir = local_data_dir(config, config_path);
+    if let Some(apply_path) = apply_path {
+        return apply(config, &data_dir, yes, no_input, apply_path);
+    }
+
+    let client = http::build_client(config)?;
+    let (config, authorization) =
+        get_authorization(config, &client, no_input, confirm_secret).await?;
+    let config = &config;
+    let (selected_packages, artifacts) =
+        list_artifacts(config, &client, &authorization, true).await?;
+    if selected_packages.is_empty() {
+        return Err(Error::Selection(
+            "No package selected, nothing is removed".to_string(),
+        ));
+    }
+    //nothing was downloaded, every artifact the tenant still returns is kept
+    let mut listed_artifacts: HashMap<String, ListedArtifacts> = selected_packages
+        .iter()
+        .map(|id| (paths::dir_name(id), ListedArtifacts::default()))
+        .collect();
+    for (package_id, _, artifact_id, _) in artifacts {
+        listed_artifacts
+            .entry(paths::dir_name(&package_id))
+            .or_default()
+            .ids
+            .push(artifact_id);
+    }
+    let selected: Vec<String> = listed_artifacts.keys().cloned().collect();
+    let plan = plan(
+        &data_dir,
+        &config.tenant.management_host,
+        &selected,
+        &listed_artifacts,
+    )?;
+
+    if let Some(plan_path) = plan_path {
+        plan.write(plan_path)?;
+        println!(
+            "Plan: {} deletion(s) written to {}, run `prune --apply {}` to remove them",
+            plan.steps.len(),
+            plan_path.display(),
+            plan_path.display()
+        );
+        return Ok(());
+    }
+    print_plan(&plan);
+    if !confirm(&plan, yes, no_input)? {
+        println!("Prune cancelled, nothing was removed");
+        return Ok(());
+    }
+    let removed = execute(&data_dir, &plan, None)?;
+    println!("Pruned: {} path(s)", removed.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    const HOST: &str = "tenant.example.com";
+
+    // every file and folder below dir, relative with '/'
+    fn all_paths(dir: &Path) -> BTreeSet<String> {
+        fn walk(root: &Path, dir: &Path, paths: &mut BTreeSet<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let relative = path.strip_prefix(root).unwrap();
+                paths.insert(relative.to_str().unwrap().replace('\\', "/"));
+                if path.is_dir() {
+                    walk(root, &path, paths);
+                }
+            }
+        }
+        let mut paths = BTreeSet::new();
+        walk(dir, dir, &mut paths);
+        paths
+    }
+
+    fn write(data_dir: &Path, path: &str, content: &str) {
+        let path = data_dir.join(PathBuf::from_slash(path));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    // PkgA is still selected and lost its artifact Old, PkgGone is no longer selected, Mine and
+    // PkgA/docs were never written by the sync
+    fn synced_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path();
+        write(data_dir, "PkgA/Flow/src/flow.iflw", "flow");
+        write(data_dir, "PkgA/Old/src/old.iflw", "old");
+        write(data_dir, "PkgA/Old.configurations.json", "{}");
+        write(data_dir, "PkgA/docs/notes.md", "mine");
+        write(data_dir, "PkgGone/Map/map.mmap", "map");
+        write(data_dir, "Mine/todo.md", "mine");
+        let mut managed = ManagedPaths::read(data_dir).unwrap();
+        managed
+            .add(["PkgA".to_string(), "PkgGone".to_string()].iter())
+            .unwrap();
+        let artifacts = [
+            ("Flow".to_string(), "Flow".to_string()),
+            ("Old".to_string(), "Old".to_string()),
+        ];
+        managed
+            .add_artifacts("PkgA", artifacts.iter().map(|(n, id)| (n, id)))
+            .unwrap();
+        dir
+    }
+
+    fn listed_flow() -> HashMap<String, ListedArtifacts> {
+        let mut listed = HashMap::new();
+        listed.insert(
+            "PkgA".to_string(),
+            ListedArtifacts {
+                names: vec!["Flow".to_string()],
+                ids: Vec::new(),
+            },
+        );
+        listed
+    }
+
+    #[test]
+    fn execution_removes_exactly_the_plan() {
+        let dir = synced_dir();
+        let data_dir = dir.path();
+        let plan = plan(data_dir, HOST, &["PkgA".to_string()], &listed_flow()).unwrap();
+        let planned: Vec<&str> = plan.steps.iter().map(|s| s.local_path.as_str()).collect();
+        assert_eq!(
+            planned,
+            vec!["PkgA/Old", "PkgA/Old.configurations.json", "PkgGone"]
+        );
+        assert!(plan.steps.iter().all(|s| s.action == PlanAction::Delete));
+        assert_eq!(plan.steps[2].artifact_id, None);
+        assert_eq!(plan.steps[0].bytes, Some(3));
+
+        let before = all_paths(data_dir);
+        let removed = execute(data_dir, &plan, None).unwrap();
+        assert_eq!(removed, planned);
+        let after = all_paths(data_dir);
+        let gone: Vec<&String> = before.difference(&after).collect();
+        let under_plan = |path: &String| {
+            planned
+                .iter()
+                .any(|p| path == p || path.starts_with(&format!("{}/", p)))
+        };
+        assert!(gone.iter().all(|path| under_plan(path)));
+        assert!(before
+            .iter()
+            .filter(|p| under_plan(p))
+            .all(|p| !after.contains(p)));
+        assert!(after.contains("PkgA/docs/notes.md"));
+        assert!(after.contains("Mine/todo.md"));
+
+        let managed = ManagedPaths::read(data_dir).unwrap();
+        assert!(!managed.is_managed("PkgGone"));
+        let recorded: Vec<String> = managed.artifacts("PkgA").into_keys().collect();
+        assert_eq!(recorded, vec!["Flow"]);
+    }
+
+    #[test]
+    fn skipped_and_listed_ids_are_kept() {
+        let dir = synced_dir();
+        let mut listed = listed_flow();
+        listed.get_mut("PkgA").unwrap().ids.push("old".to_string());
+        let plan = plan(dir.path(), HOST, &["PkgA".to_string()], &listed).unwrap();
+        let planned: Vec<&str> = plan.steps.iter().map(|s| s.local_path.as_str()).collect();
+        assert_eq!(planned, vec!["PkgGone"]);
+    }
+
+    #[test]
+    fn no_input_without_yes_deletes_nothing() {
+        let dir = synced_dir();
+        let plan = plan(dir.path(), HOST, &["PkgA".to_string()], &listed_flow()).unwrap();
+        let err = confirm(&plan, false, true).unwrap_err();
+        assert!(err.to_string().contains("pass --yes"));
+        assert!(confirm(&plan, true, true).unwrap());
+        // an empty plan has nothing to confirm
+        let empty = Plan::new("prune", HOST, Vec::new());
+        assert!(confirm(&empty, false, true).unwrap());
+    }
+
+    #[test]
+    fn apply_runs_the_written_plan() {
+        let dir = synced_dir();
+        let data_dir = dir.path();
+        let config = crate::config::test_config(serde_json::json!({ "filter_rules": [] }));
+        let plan_dir = tempfile::tempdir().unwrap();
+        let plan_path = plan_dir.path().join("prune.json");
+        plan(data_dir, HOST, &["PkgA".to_string()], &listed_flow())
+            .unwrap()
+            .write(&plan_path)
+            .unwrap();
+
+        apply(&config, data_dir, true, true, &plan_path).unwrap();
+        assert!(!data_dir.join("PkgA/Old").exists());
+        assert!(!data_dir.join("PkgGone").exists());
+        assert!(data_dir.join("PkgA/docs/notes.md").is_file());
+        // a second apply has nothing left
+        apply(&config, data_dir, true, true, &plan_path).unwrap();
+    }
+
+    #[test]
+    fn apply_refuses_a_changed_path() {
+        let dir = synced_dir();
+        let data_dir = dir.path();
+        let config = crate::config::test_config(serde_json::json!({ "filter_rules": [] }));
+        let plan_dir = tempfile::tempdir().unwrap();
+        let plan_path = plan_dir.path().join("prune.json");
+        plan(data_dir, HOST, &["PkgA".to_string()], &listed_flow())
+            .unwrap()
+            .write(&plan_path)
+            .unwrap();
+        write(data_dir, "PkgGone/Map/map.mmap", "edited after the plan");
+
+        let err = apply(&config, data_dir, true, true, &plan_path).unwrap_err();
+        assert!(err.to_string().contains("no longer match the local files"));
+        assert!(data_dir.join("PkgA/Old").is_dir());
+        assert!(data_dir.join("PkgGone").is_dir());
+    }
+}
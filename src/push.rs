@@ -0,0 +1,609 @@
+use crate::auth::Authorization;
+use crate::error::Error;
+use crate::plan::{Plan, PlanAction, PlanProgress, PlanStep};
+use crate::{
+    check_api_url, filter, get_all_packages, get_authorization, hash, http, local_data_dir,
+    ArtifactKind, Config,
+};
+use path_slash::{PathBufExt, PathExt};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+// local form of an artifact: the extracted directory or the zip kept with zip_extraction disabled
+enum LocalArtifact {
+    Directory(std::path::PathBuf),
+    Zip(std::path::PathBuf),
+}
+
+struct PushItem {
+    package_id: String,
+    artifact_id: String,
+    local: LocalArtifact,
+}
+
+// an integration flow that exists on the tenant
+struct RemoteArtifact {
+    version: Option<String>,
+}
+
+// the CPI write APIs want a CSRF token fetched in the same session
+struct CsrfSession {
+    token: Option<String>,
+    cookie: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct PushSummary {
+    created: usize,
+    updated: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+fn has_iflw_file(dir: &Path) -> Result<bool, Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if has_iflw_file(&path)? {
+                return Ok(true);
+            }
+        } else if path.extension().is_some_and(|e| e == "iflw") {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn zip_has_iflw_file(path: &Path) -> Result<bool, Error> {
+    let archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+    let has_iflw = archive.file_names().any(|name| name.ends_with(".iflw"));
+    Ok(has_iflw)
+}
+
+// only integration flows can be written back, other local artifacts are counted as skipped
+fn find_local_iflows(
+    data_dir: &Path,
+    package_id: &str,
+    summary: &mut PushSummary,
+) -> Result<Vec<PushItem>, Error> {
+    let package_dir = crate::paths::package_dir(data_dir, package_id);
+    let mut items = Vec::new();
+    if !package_dir.is_dir() {
+        return Ok(items);
+    }
+
+    let mut entries: Vec<_> = fs::read_dir(&package_dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // externalized parameters of a zip artifact and deployment metadata, not artifacts
+        if name.ends_with(".configurations.json")
+            || name.ends_with(crate::runtime::FILE_SUFFIX)
+            || name == crate::package_metadata::FILE_NAME
+        {
+            continue;
+        }
+        let item = if path.is_dir() && has_iflw_file(&path)? {
+            Some((name, LocalArtifact::Directory(path)))
+        } else if name.ends_with(".zip") && zip_has_iflw_file(&path)? {
+            Some((
+                name.trim_end_matches(".zip").to_string(),
+                LocalArtifact::Zip(path),
+            ))
+        } else {
+            None
+        };
+        match item {
+            Some((dir, local)) => items.push(PushItem {
+                package_id: package_id.to_string(),
+                artifact_id: crate::paths::id_from_dir_name(&dir),
+                local,
+            }),
+            None => summary.skipped += 1,
+        }
+    }
+    //zip_extraction "both" leaves a zip next to the folder, the folder is pushed
+    let dirs: Vec<String> = items
+        .iter()
+        .filter(|item| matches!(item.local, LocalArtifact::Directory(_)))
+        .map(|item| item.artifact_id.clone())
+        .collect();
+    items.retain(|item| {
+        !(matches!(item.local, LocalArtifact::Zip(_)) && dirs.contains(&item.artifact_id))
+    });
+    Ok(items)
+}
+
+fn add_to_zip(
+    zip: &mut zip::ZipWriter<Cursor<Vec<u8>>>,
+    dir: &Path,
+    prefix: &str,
+) -> Result<(), Error> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        // names the extraction encoded go back to the tenant as they were in the zip
+        let file_name = crate::paths::id_from_dir_name(&entry.file_name().to_string_lossy());
+        let name = format!("{}{}", prefix, file_name);
+        // written by the sync next to the artifact content, the tenant keeps them apart
+        if name == crate::configurations::FILE_NAME {
+            continue;
+        }
+        let options = zip::write::FileOptions::default();
+        if entry.file_type()?.is_dir() {
+            zip.add_directory(format!("{}/", name), options)?;
+            add_to_zip(zip, &entry.path(), &format!("{}/", name))?;
+        } else {
+            zip.start_file(name, options)?;
+            zip.write_all(&fs::read(entry.path())?)?;
+        }
+    }
+    Ok(())
+}
+
+// the same local content gives the same hash, unlike the zip built for the upload
+fn local_sha256(local: &LocalArtifact) -> Result<String, Error> {
+    match local {
+        LocalArtifact::Zip(path) => Ok(Sha256::digest(&fs::read(path)?)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()),
+        LocalArtifact::Directory(dir) => hash::hash_dir(dir),
+    }
+}
+
+fn local_path(data_dir: &Path, local: &LocalArtifact) -> String {
+    let path = match local {
+        LocalArtifact::Zip(path) | LocalArtifact::Directory(path) => path,
+    };
+    path.strip_prefix(data_dir).unwrap_or(path).to_slash_lossy()
+}
+
+fn artifact_content(local: &LocalArtifact) -> Result<Vec<u8>, Error> {
+    match local {
+        LocalArtifact::Zip(path) => Ok(fs::read(path)?),
+        LocalArtifact::Directory(dir) => {
+            let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+            add_to_zip(&mut zip, dir, "")?;
+            Ok(zip.finish()?.into_inner())
+        }
+    }
+}
+
+// Bundle-Name of the manifest, the id if there is none
+fn artifact_name(content: &[u8], artifact_id: &str) -> String {
+    let mut manifest = String::new();
+    if let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(content)) {
+        if let Ok(mut file) = archive.by_name("META-INF/MANIFEST.MF") {
+            let _ = file.read_to_string(&mut manifest);
+        }
+    }
+    manifest
+        .lines()
+        .find_map(|l| l.strip_prefix("Bundle-Name:"))
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| artifact_id.to_string())
+}
+
+fn artifact_url(config: &Config, artifact_id: &str) -> String {
+    format!(
+        "{api}{entity_set}(Id='{id}',Version='active')",
+        api = check_api_url(config),
+        entity_set = ArtifactKind::Iflow.entity_set(),
+        id = artifact_id
+    )
+}
+
+async fn remote_artifact(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    artifact_id: &str,
+) -> Result<Option<RemoteArtifact>, Error> {
+    let url = artifact_url(config, artifact_id);
+    let request = client.get(&url).header("Accept", "application/json");
+    let resp = authorization
+        .fetch(request, &url, http::Endpoint::ArtifactLookup, None)
+        .await?;
+    let resp_code = resp.status;
+    if resp_code == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp_code.is_success() {
+        log::error!("Artifact Lookup Failed!");
+        log::error!("API URL: {}", &url);
+        log::error!("API Response Code: {:#?}", &resp_code);
+        crate::logging::response_body(&resp.text());
+        return Err(Error::api_request(
+            "Artifact lookup",
+            &url,
+            resp_code,
+            &resp.text(),
+        ));
+    }
+    let body: serde_json::Value = serde_json::from_slice(&resp.body).unwrap_or_default();
+    Ok(Some(RemoteArtifact {
+        version: body["d"]["Version"].as_str().map(|v| v.to_string()),
+    }))
+}
+
+async fn fetch_csrf(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+) -> Result<CsrfSession, Error> {
+    let url = check_api_url(config);
+    let request = client.get(&url).header("X-CSRF-Token", "Fetch");
+    let resp = authorization
+        .fetch(request, &url, http::Endpoint::ApiCheck, None)
+        .await?;
+    if !resp.status.is_success() {
+        log::error!("CSRF Token Fetch Failed!");
+        log::error!("API URL: {}", &url);
+        log::error!("API Response Code: {:#?}", resp.status);
+        return Err(Error::api_request(
+            "CSRF token fetch",
+            &url,
+            resp.status,
+            &resp.text(),
+        ));
+    }
+
+    let token = resp
+        .headers
+        .get("x-csrf-token")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    // the token is bound to the session cookies of this response
+    let cookies: Vec<&str> = resp
+        .headers
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|v| v.split(';').next())
+        .collect();
+    let cookie = if cookies.is_empty() {
+        None
+    } else {
+        Some(cookies.join("; "))
+    };
+    Ok(CsrfSession { token, cookie })
+}
+
+// sent once: a create that timed out may exist already, a second POST would fail or a second PUT
+// replace content changed in between. Only a 401 is sent again, with a renewed OAuth token.
+async fn upload_artifact(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    csrf: &CsrfSession,
+    item: &PushItem,
+    content: &[u8],
+    exists: bool,
+) -> Result<(), Error> {
+    let name = artifact_name(content, &item.artifact_id);
+    let encoded = base64::encode(content);
+    let (mut request, url) = if exists {
+        let url = artifact_url(config, &item.artifact_id);
+        let body = json!({ "Name": name, "ArtifactContent": encoded });
+        (client.put(&url).json(&body), url)
+    } else {
+        let url = format!(
+            "{api}{entity_set}",
+            api = check_api_url(config),
+            entity_set = ArtifactKind::Iflow.entity_set()
+        );
+        let body = json!({
+            "Name": name,
+            "Id": item.artifact_id,
+            "PackageId": item.package_id,
+            "ArtifactContent": encoded
+        });
+        (client.post(&url).json(&body), url)
+    };
+    request = request.header("Accept", "application/json");
+    if let Some(token) = &csrf.token {
+        request = request.header("X-CSRF-Token", token);
+    }
+    if let Some(cookie) = &csrf.cookie {
+        request = request.header(reqwest::header::COOKIE, cookie);
+    }
+
+    let resp = authorization
+        .fetch(request, &url, http::Endpoint::ArtifactUpload, None)
+        .await?;
+    let resp_code = resp.status;
+    if !resp_code.is_success() {
+        log::error!("Artifact Upload Failed!");
+        log::error!("API URL: {}", &url);
+        log::error!("API Response Code: {:#?}", &resp_code);
+        crate::logging::response_body(&resp.text());
+        return Err(Error::api_request(
+            "Artifact upload",
+            &url,
+            resp_code,
+            &resp.text(),
+        ));
+    }
+    Ok(())
+}
+
+pub async fn run_push(
+    config: &Config,
+    config_path: &str,
+    no_input: bool,
+    confirm_secret: bool,
+    dry_run: bool,
+    plan_path: Option<&Path>,
+) -> Result<(), Error> {
+    let client = http::build_client(config)?;
+    let (config, authorization) =
+        get_authorization(config, &client, no_input, confirm_secret).await?;
+    let config = &config;
+    let data_dir = local_data_dir(config, config_path);
+
+    // packages are selected with the same filter rules as a sync, they must exist on the tenant
+    let api_package_list = get_all_packages(config, &client, &authorization, None).await?;
+    let package_decisions = filter::evaluate_filter_rules(config, &api_package_list.d.results)?;
+
+    let mut summary = PushSummary::default();
+    let mut items = Vec::new();
+    for decision in package_decisions.iter().filter(|d| d.selected) {
+        items.append(&mut find_local_iflows(
+            &data_dir,
+            &decision.package_id,
+            &mut summary,
+        )?);
+    }
+
+    log::warn!(
+        "Uploaded content replaces the design-time version 'active' of an integration flow."
+    );
+    log::warn!("The tenant doesn't keep the previous content unless a version was saved in the WebUI, and nothing is deployed.");
+
+    let csrf = if dry_run || plan_path.is_some() {
+        None
+    } else {
+        Some(fetch_csrf(config, &client, &authorization).await?)
+    };
+
+    let mut steps = Vec::new();
+    for item in items.iter() {
+        let remote = remote_artifact(config, &client, &authorization, &item.artifact_id).await?;
+        let exists = remote.is_some();
+        let action = if exists {
+            PlanAction::Update
+        } else {
+            PlanAction::Create
+        };
+        let content = artifact_content(&item.local)?;
+        log::info!(
+            "- Artifact: {:#?} ({}, {} bytes), to Package: {:#?}",
+            item.artifact_id,
+            action.as_str(),
+            content.len(),
+            item.package_id
+        );
+        if plan_path.is_some() {
+            steps.push(PlanStep {
+                step: steps.len() + 1,
+                action,
+                package_id: item.package_id.clone(),
+                artifact_id: Some(item.artifact_id.clone()),
+                local_path: local_path(&data_dir, &item.local),
+                local_sha256: local_sha256(&item.local)?,
+                remote_version: remote.and_then(|r| r.version),
+                bytes: None,
+            });
+        }
+        let csrf = match &csrf {
+            Some(csrf) => csrf,
+            None => continue,
+        };
+        match upload_artifact(
+            config,
+            &client,
+            &authorization,
+            csrf,
+            item,
+            &content,
+            exists,
+        )
+        .await
+        {
+            Ok(()) if exists => summary.updated += 1,
+            Ok(()) => summary.created += 1,
+            Err(err) => {
+                log::error!("{}", err);
+                summary.failed += 1;
+            }
+        }
+    }
+
+    if let Some(plan_path) = plan_path {
+        Plan::new("push", &config.tenant.management_host, steps).write(plan_path)?;
+        println!(
+            "Plan with {} step(s) written to {}, {} local artifact(s) skipped (not an integration flow)",
+            items.len(),
+            plan_path.display(),
+            summary.skipped
+        );
+        return Ok(());
+    }
+    if dry_run {
+        println!(
+            "Dry run: {} integration flow(s) would be uploaded, {} local artifact(s) skipped (not an integration flow)",
+            items.len(),
+            summary.skipped
+        );
+        return Ok(());
+    }
+    println!(
+        "Push: {} updated, {} created, {} failed, {} skipped (not an integration flow)",
+        summary.updated, summary.created, summary.failed, summary.skipped
+    );
+    if summary.failed > 0 {
+        return Err(Error::Failed("Push Failed!".to_string()));
+    }
+    Ok(())
+}
+
+// executes a plan of `push --plan` as it is. Nothing is uploaded when a local artifact or the
+// tenant changed since the plan was made, steps of an earlier apply are skipped.
+pub async fn run_push_apply(
+    config: &Config,
+    config_path: &str,
+    no_input: bool,
+    confirm_secret: bool,
+    plan_path: &Path,
+) -> Result<(), Error> {
+    let (plan, plan_sha256) = Plan::read(plan_path)?;
+    if plan.operation != "push" {
+        return Err(Error::Plan(format!(
+            "Plan {} is for {}, not push",
+            plan_path.display(),
+            plan.operation
+        )));
+    }
+    if plan.host != config.tenant.management_host {
+        return Err(Error::Plan(format!(
+            "Plan {} was made for {}, the config is for {}",
+            plan_path.display(),
+            plan.host,
+            config.tenant.management_host
+        )));
+    }
+    let mut progress = PlanProgress::open(plan_path, &plan_sha256)?;
+    let pending: Vec<&PlanStep> = plan
+        .steps
+        .iter()
+        .filter(|s| !progress.is_applied(s.step))
+        .collect();
+    if pending.is_empty() {
+        println!(
+            "Apply: all {} step(s) of {} were already applied",
+            plan.steps.len(),
+            plan_path.display()
+        );
+        return Ok(());
+    }
+
+    let client = http::build_client(config)?;
+    let (config, authorization) =
+        get_authorization(config, &client, no_input, confirm_secret).await?;
+    let config = &config;
+    let data_dir = local_data_dir(config, config_path);
+
+    let mut drift = Vec::new();
+    let mut work = Vec::new();
+    for step in pending {
+        let artifact_id = match (&step.artifact_id, step.action) {
+            (Some(artifact_id), PlanAction::Create | PlanAction::Update) => artifact_id,
+            _ => {
+                drift.push(format!(
+                    "step {}: push only creates and updates artifacts",
+                    step.step
+                ));
+                continue;
+            }
+        };
+        let path = data_dir.join(PathBuf::from_slash(&step.local_path));
+        let local = if path.is_file() {
+            LocalArtifact::Zip(path)
+        } else if path.is_dir() {
+            LocalArtifact::Directory(path)
+        } else {
+            drift.push(format!(
+                "step {}: {} is missing",
+                step.step, step.local_path
+            ));
+            continue;
+        };
+        if local_sha256(&local)? != step.local_sha256 {
+            drift.push(format!("step {}: {} changed", step.step, step.local_path));
+        }
+        let remote = remote_artifact(config, &client, &authorization, artifact_id).await?;
+        let remote_drift = match (step.action, remote) {
+            (PlanAction::Create, Some(_)) => Some("exists on the tenant now".to_string()),
+            (PlanAction::Update, None) => Some("no longer exists on the tenant".to_string()),
+            (PlanAction::Update, Some(remote)) if remote.version != step.remote_version => {
+                Some(format!(
+                    "has version {:?} on the tenant, the plan expects {:?}",
+                    remote.version, step.remote_version
+                ))
+            }
+            _ => None,
+        };
+        if let Some(message) = remote_drift {
+            drift.push(format!("step {}: {} {}", step.step, artifact_id, message));
+        }
+        work.push((
+            step,
+            PushItem {
+                package_id: step.package_id.clone(),
+                artifact_id: artifact_id.clone(),
+                local,
+            },
+        ));
+    }
+    if !drift.is_empty() {
+        for message in &drift {
+            log::error!("Plan drift: {}", message);
+        }
+        return Err(Error::Plan(format!(
+            "{} step(s) of {} no longer match the tenant or the local files, create a new plan",
+            drift.len(),
+            plan_path.display()
+        )));
+    }
+
+    log::warn!(
+        "Uploaded content replaces the design-time version 'active' of an integration flow."
+    );
+    log::warn!("The tenant doesn't keep the previous content unless a version was saved in the WebUI, and nothing is deployed.");
+    let csrf = fetch_csrf(config, &client, &authorization).await?;
+
+    let already_applied = plan.steps.len() - work.len();
+    for (step, item) in work.iter() {
+        let content = artifact_content(&item.local)?;
+        log::info!(
+            "- Step {}: {:#?} ({}, {} bytes), to Package: {:#?}",
+            step.step,
+            item.artifact_id,
+            step.action.as_str(),
+            content.len(),
+            item.package_id
+        );
+        let exists = step.action == PlanAction::Update;
+        if let Err(err) = upload_artifact(
+            config,
+            &client,
+            &authorization,
+            &csrf,
+            item,
+            &content,
+            exists,
+        )
+        .await
+        {
+            log::error!(
+                "Apply stopped at step {}, run --apply again to continue with it",
+                step.step
+            );
+            return Err(err);
+        }
+        progress.mark_applied(step.step)?;
+    }
+    println!(
+        "Apply: {} step(s) applied, {} already applied before, progress in {}",
+        work.len(),
+        already_applied,
+        PlanProgress::path(plan_path).display()
+    );
+    Ok(())
+}
@@ -0,0 +1,320 @@
+use crate::error::Error;
+use crate::filter::{ArtifactSkip, ArtifactSkipReason, PackageDecision, PackageSkipReason};
+use crate::{ArtifactKind, Config, Identity, RunStats, StaleActiveCheck};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+// bumped when a field is renamed or removed, new fields keep the version
+pub const REPORT_VERSION: u32 = 1;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Success,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageAction {
+    // all artifacts downloaded or skipped by artifact rules
+    Synced,
+    // not selected by filter_rules
+    Skipped,
+    // at least one artifact failed, see --ignore-error-download
+    Failed,
+    // the run ended before the package was done
+    Incomplete,
+    // done in an earlier run of the same --checkpoint
+    Resumed,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactAction {
+    Downloaded,
+    Skipped,
+    Failed,
+    // listed, but the run ended before the download finished
+    NotDownloaded,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ArtifactReport {
+    pub artifact_id: String,
+    pub artifact_type: String,
+    pub action: ArtifactAction,
+    pub version: Option<String>,
+    pub bytes: Option<u64>,
+    pub duration_ms: Option<u128>,
+    pub error: Option<String>,
+    pub skip_reason: Option<ArtifactSkipReason>,
+    // downloaded from a draft with packages.draft_handling "download", it may hold unsaved changes
+    pub draft: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PackageReport {
+    pub package_id: String,
+    pub action: PackageAction,
+    pub skip_reason: Option<PackageSkipReason>,
+    pub artifacts: Vec<ArtifactReport>,
+}
+
+// written by --report-json at the end of a sync, also when it fails
+#[derive(Serialize, Debug, Clone)]
+pub struct RunReport {
+    pub report_version: u32,
+    pub tool_version: String,
+    pub host: String,
+    // None when the run failed before the API first check
+    pub identity: Option<Identity>,
+    pub status: RunStatus,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: u128,
+    pub stats: RunStats,
+    pub packages: Vec<PackageReport>,
+    // packages.check_stale_active, None when it is off
+    pub stale_active: Option<StaleActiveCheck>,
+}
+
+// collects the outcome of every package and artifact while the sync runs
+#[derive(Debug)]
+pub(crate) struct ReportBuilder {
+    host: String,
+    identity: Option<Identity>,
+    started_at: DateTime<Utc>,
+    packages: BTreeMap<String, PackageReport>,
+    listed: Vec<String>,
+    stale_active: Option<StaleActiveCheck>,
+}
+
+impl ReportBuilder {
+    pub(crate) fn new(config: &Config) -> ReportBuilder {
+        ReportBuilder {
+            host: config.tenant.management_host.clone(),
+            identity: None,
+            started_at: Utc::now(),
+            packages: BTreeMap::new(),
+            listed: Vec::new(),
+            stale_active: None,
+        }
+    }
+
+    pub(crate) fn identity(&mut self, identity: &Identity) {
+        self.identity = Some(identity.clone());
+    }
+
+    pub(crate) fn decisions(&mut self, decisions: &[PackageDecision]) {
+        for decision in decisions {
+            self.packages.insert(
+                decision.package_id.clone(),
+                PackageReport {
+                    package_id: decision.package_id.clone(),
+                    action: if decision.selected {
+                        PackageAction::Incomplete
+                    } else {
+                        PackageAction::Skipped
+                    },
+                    skip_reason: decision.skipped.clone(),
+                    artifacts: Vec::new(),
+                },
+            );
+        }
+    }
+
+    // an artifact pinned to several versions is listed once per version
+    fn artifact(
+        &mut self,
+        package_id: &str,
+        artifact_id: &str,
+        version: Option<&str>,
+    ) -> Option<&mut ArtifactReport> {
+        self.packages
+            .get_mut(package_id)?
+            .artifacts
+            .iter_mut()
+            .find(|a| a.artifact_id == artifact_id && a.version.as_deref() == version)
+    }
+
+    pub(crate) fn resumed(&mut self, package_id: &str) {
+        if let Some(package) = self.packages.get_mut(package_id) {
+            package.action = PackageAction::Resumed;
+        }
+    }
+
+    // the artifacts of a package are known once it is listed
+    pub(crate) fn listed(
+        &mut self,
+        package_id: &str,
+        artifacts: &[(ArtifactKind, String, Option<String>)],
+        skipped: &[ArtifactSkip],
+    ) {
+        let package = match self.packages.get_mut(package_id) {
+            Some(package) => package,
+            None => return,
+        };
+        for (kind, artifact_id, version) in artifacts {
+            package.artifacts.push(ArtifactReport {
+                artifact_id: artifact_id.clone(),
+                artifact_type: kind.to_string(),
+                action: ArtifactAction::NotDownloaded,
+                version: version.clone(),
+                bytes: None,
+                duration_ms: None,
+                error: None,
+                skip_reason: None,
+                draft: false,
+            });
+        }
+        for skip in skipped {
+            package.artifacts.push(ArtifactReport {
+                artifact_id: skip.artifact_id.clone(),
+                artifact_type: skip.artifact_type.clone(),
+                action: ArtifactAction::Skipped,
+                version: None,
+                bytes: None,
+                duration_ms: None,
+                error: None,
+                skip_reason: Some(skip.reason.clone()),
+                draft: false,
+            });
+        }
+        self.listed.push(package_id.to_string());
+    }
+
+    pub(crate) fn downloaded(
+        &mut self,
+        package_id: &str,
+        artifact_id: &str,
+        version: Option<&str>,
+        bytes: u64,
+        duration: Duration,
+    ) {
+        if let Some(artifact) = self.artifact(package_id, artifact_id, version) {
+            artifact.action = ArtifactAction::Downloaded;
+            artifact.bytes = Some(bytes);
+            artifact.duration_ms = Some(duration.as_millis());
+        }
+    }
+
+    pub(crate) fn draft(&mut self, package_id: &str, artifact_id: &str, version: Option<&str>) {
+        if let Some(artifact) = self.artifact(package_id, artifact_id, version) {
+            artifact.draft = true;
+        }
+    }
+
+    // a listed artifact that turned out to have nothing to download
+    pub(crate) fn skipped(
+        &mut self,
+        package_id: &str,
+        artifact_id: &str,
+        version: Option<&str>,
+        reason: ArtifactSkipReason,
+    ) {
+        if let Some(artifact) = self.artifact(package_id, artifact_id, version) {
+            artifact.action = ArtifactAction::Skipped;
+            artifact.skip_reason = Some(reason);
+        }
+    }
+
+    pub(crate) fn failed(
+        &mut self,
+        package_id: &str,
+        artifact_id: &str,
+        version: Option<&str>,
+        error: &str,
+    ) {
+        if let Some(artifact) = self.artifact(package_id, artifact_id, version) {
+            artifact.action = ArtifactAction::Failed;
+            artifact.error = Some(error.to_string());
+        }
+    }
+
+    // a late pass adds its artifacts to the check of the first one
+    pub(crate) fn stale_active(&mut self, check: StaleActiveCheck) {
+        match self.stale_active.as_mut() {
+            Some(existing) => {
+                existing.available |= check.available;
+                existing.checked += check.checked;
+                existing.artifacts.extend(check.artifacts);
+            }
+            None => self.stale_active = Some(check),
+        }
+    }
+
+    pub(crate) fn failed_artifacts(&self) -> usize {
+        self.packages
+            .values()
+            .flat_map(|p| p.artifacts.iter())
+            .filter(|a| a.action == ArtifactAction::Failed)
+            .count()
+    }
+
+    pub(crate) fn finish(mut self, stats: &RunStats, error: Option<&Error>) -> RunReport {
+        for package in self.packages.values_mut() {
+            if let PackageAction::Skipped | PackageAction::Resumed = package.action {
+                continue;
+            }
+            let artifacts = &package.artifacts;
+            let deferred = artifacts.iter().any(|a| {
+                matches!(
+                    a.skip_reason,
+                    Some(ArtifactSkipReason::BudgetExceeded { .. })
+                )
+            });
+            package.action = if artifacts.iter().any(|a| a.action == ArtifactAction::Failed) {
+                PackageAction::Failed
+            } else if !deferred
+                && self.listed.contains(&package.package_id)
+                && artifacts
+                    .iter()
+                    .all(|a| a.action != ArtifactAction::NotDownloaded)
+            {
+                PackageAction::Synced
+            } else {
+                PackageAction::Incomplete
+            };
+        }
+        let status = match error {
+            None => RunStatus::Success,
+            Some(Error::Cancelled(_)) => RunStatus::Cancelled,
+            Some(_) => RunStatus::Failed,
+        };
+        let finished_at = Utc::now();
+        RunReport {
+            report_version: REPORT_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            host: self.host,
+            identity: self.identity,
+            status,
+            error: error.map(|e| e.to_string()),
+            started_at: self.started_at,
+            finished_at,
+            duration_ms: (finished_at - self.started_at)
+                .to_std()
+                .unwrap_or_default()
+                .as_millis(),
+            stats: stats.clone(),
+            packages: self.packages.into_values().collect(),
+            stale_active: self.stale_active,
+        }
+    }
+}
+
+impl RunReport {
+    // written like the checkpoint, see atomic::write
+    pub(crate) fn write(&self, path: &Path) -> Result<(), Error> {
+        crate::atomic::write(
+            path,
+            (serde_json::to_string_pretty(self)? + "\n").as_bytes(),
+        )?;
+        Ok(())
+    }
+}
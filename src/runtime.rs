@@ -0,0 +1,288 @@
+use crate::auth::Authorization;
+use crate::error::Error;
+use crate::stale::{compare_versions, ActiveArtifact};
+use crate::{filter, http, logging, ArtifactKind, Config};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::Path;
+
+// deployment metadata of an artifact, written as <artifact>.deployment.json next to the
+// artifact folder or zip with packages.source "deployed"
+pub(crate) const FILE_SUFFIX: &str = ".deployment.json";
+
+// an entry of IntegrationRuntimeArtifacts. The runtime has no package, a deployed artifact is
+// grouped by the package listing the design-time artifact with the same id and type.
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct RuntimeArtifact {
+    #[serde(rename = "Id")]
+    pub id: String,
+    #[serde(rename = "Version")]
+    pub version: Option<String>,
+    #[serde(rename = "Type")]
+    pub artifact_type: Option<String>,
+    #[serde(rename = "DeployedBy")]
+    pub deployed_by: Option<String>,
+    #[serde(rename = "DeployedOn")]
+    pub deployed_on: Option<String>,
+    #[serde(rename = "Status")]
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RuntimeResponseD {
+    results: Vec<RuntimeArtifact>,
+}
+#[derive(Deserialize, Debug)]
+struct RuntimeResponseRoot {
+    d: RuntimeResponseD,
+}
+
+#[derive(Serialize, Debug)]
+struct Deployment<'a> {
+    artifact_id: &'a str,
+    artifact_type: Option<&'a str>,
+    version: Option<&'a str>,
+    deployed_by: Option<&'a str>,
+    // RFC 3339 when the OData date can be read, as returned otherwise
+    deployed_on: Option<String>,
+    status: Option<&'a str>,
+}
+
+impl RuntimeArtifact {
+    // artifacts of an unknown runtime type are matched by id only
+    pub(crate) fn is_kind(&self, kind: ArtifactKind) -> bool {
+        match self.artifact_type.as_deref() {
+            Some(artifact_type) => ArtifactKind::ALL
+                .iter()
+                .find(|k| k.runtime_type() == artifact_type)
+                .is_none_or(|k| *k == kind),
+            None => true,
+        }
+    }
+}
+
+// the deployed artifact of a listed design-time artifact
+pub(crate) fn find<'a>(
+    runtime_artifacts: &'a [RuntimeArtifact],
+    kind: ArtifactKind,
+    artifact_id: &str,
+) -> Option<&'a RuntimeArtifact> {
+    runtime_artifacts
+        .iter()
+        .find(|r| r.id == artifact_id && r.is_kind(kind))
+}
+
+// designed vs deployed of a listed artifact, `--with-deploy-status` of list and diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DeployStatus {
+    NotDeployed,
+    DeployedSameVersion,
+    DeployedOlderVersion,
+    // the design-time version was lowered after the deployment, e.g. by a restored version
+    DeployedNewerVersion,
+    // one side has no version to compare
+    DeployedUnknownVersion,
+}
+
+impl fmt::Display for DeployStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DeployStatus::NotDeployed => "NotDeployed",
+            DeployStatus::DeployedSameVersion => "DeployedSameVersion",
+            DeployStatus::DeployedOlderVersion => "DeployedOlderVersion",
+            DeployStatus::DeployedNewerVersion => "DeployedNewerVersion",
+            DeployStatus::DeployedUnknownVersion => "DeployedUnknownVersion",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub(crate) fn deploy_status(
+    runtime_artifacts: &[RuntimeArtifact],
+    kind: ArtifactKind,
+    artifact_id: &str,
+    version: Option<&str>,
+) -> DeployStatus {
+    let deployed = match find(runtime_artifacts, kind, artifact_id) {
+        Some(deployed) => deployed,
+        None => return DeployStatus::NotDeployed,
+    };
+    match (deployed.version.as_deref(), version) {
+        (Some(deployed), Some(designed)) => match compare_versions(deployed, designed) {
+            Ordering::Equal => DeployStatus::DeployedSameVersion,
+            Ordering::Less => DeployStatus::DeployedOlderVersion,
+            Ordering::Greater => DeployStatus::DeployedNewerVersion,
+        },
+        _ => DeployStatus::DeployedUnknownVersion,
+    }
+}
+
+// deployed artifacts without a listed design-time artifact: deployed directly, or from a package
+// that isn't selected
+pub(crate) fn runtime_only<'a>(
+    runtime_artifacts: &'a [RuntimeArtifact],
+    listed: &[ActiveArtifact],
+) -> Vec<&'a RuntimeArtifact> {
+    runtime_artifacts
+        .iter()
+        .filter(|r| {
+            !listed
+                .iter()
+                .any(|(_, kind, artifact_id, _)| &r.id == artifact_id && r.is_kind(*kind))
+        })
+        .collect()
+}
+
+pub(crate) fn payload_url(config: &Config, artifact_id: &str) -> String {
+    format!(
+        "{api}IntegrationRuntimeArtifacts('{artifact_id}')/$value",
+        api = crate::check_api_url(config),
+        artifact_id = artifact_id
+    )
+}
+
+pub(crate) async fn list_runtime_artifacts(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    timings: &http::Timings,
+) -> Result<Vec<RuntimeArtifact>, Error> {
+    let url = format!(
+        "{api}IntegrationRuntimeArtifacts",
+        api = crate::check_api_url(config)
+    );
+    let request = client.get(&url).header("Accept", "application/json");
+    let resp = authorization
+        .fetch(
+            request,
+            &url,
+            http::Endpoint::RuntimeArtifactList,
+            Some(timings),
+        )
+        .await?;
+    let body_text = resp.text();
+    if !resp.status.is_success() {
+        log::error!("API Runtime Artifact List Failed!");
+        log::error!("API URL: {}", &url);
+        log::error!("API Response Code: {:#?}", &resp.status);
+        logging::response_body(&body_text);
+        return Err(Error::api_request(
+            "Runtime artifact list",
+            &url,
+            resp.status,
+            &body_text,
+        ));
+    }
+    let resp_obj: RuntimeResponseRoot = match serde_json::from_slice(body_text.as_bytes()) {
+        Ok(api_resp) => api_resp,
+        Err(err) => {
+            log::error!("API Runtime Artifact List Parse Failed!");
+            log::error!("API URL: {}", &url);
+            logging::response_body(&body_text);
+            return Err(Error::api_response("Runtime artifact list", &url, err));
+        }
+    };
+    Ok(resp_obj.d.results)
+}
+
+pub(crate) fn write_deployment(
+    container: &Path,
+    artifact_dir: &str,
+    runtime: &RuntimeArtifact,
+) -> Result<(), Error> {
+    let deployment = Deployment {
+        artifact_id: &runtime.id,
+        artifact_type: runtime.artifact_type.as_deref(),
+        version: runtime.version.as_deref(),
+        deployed_by: runtime.deployed_by.as_deref(),
+        deployed_on: runtime.deployed_on.as_deref().map(|value| {
+            filter::parse_modified_at(value)
+                .map(|date| date.to_rfc3339())
+                .unwrap_or_else(|| value.to_string())
+        }),
+        status: runtime.status.as_deref(),
+    };
+    std::fs::create_dir_all(container)?;
+    std::fs::write(
+        container.join(format!("{}{}", artifact_dir, FILE_SUFFIX)),
+        serde_json::to_string_pretty(&deployment)? + "\n",
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deployed(id: &str, artifact_type: &str, version: Option<&str>) -> RuntimeArtifact {
+        RuntimeArtifact {
+            id: id.to_string(),
+            version: version.map(str::to_string),
+            artifact_type: Some(artifact_type.to_string()),
+            deployed_by: None,
+            deployed_on: None,
+            status: Some("STARTED".to_string()),
+        }
+    }
+
+    #[test]
+    fn design_time_versions_are_compared_with_the_deployed_ones() {
+        let runtime = vec![
+            deployed("Same", "INTEGRATION_FLOW", Some("1.0.2")),
+            deployed("Older", "INTEGRATION_FLOW", Some("1.0.9")),
+            deployed("Newer", "INTEGRATION_FLOW", Some("2.0.0")),
+            deployed("NoVersion", "INTEGRATION_FLOW", None),
+            deployed("Mapping", "VALUE_MAPPING", Some("1.0.0")),
+        ];
+        let status = |id, version| deploy_status(&runtime, ArtifactKind::Iflow, id, version);
+
+        assert_eq!(
+            status("Same", Some("1.0.2")),
+            DeployStatus::DeployedSameVersion
+        );
+        // numeric parts, 1.0.9 is below 1.0.10
+        assert_eq!(
+            status("Older", Some("1.0.10")),
+            DeployStatus::DeployedOlderVersion
+        );
+        assert_eq!(
+            status("Newer", Some("1.5.0")),
+            DeployStatus::DeployedNewerVersion
+        );
+        assert_eq!(
+            status("NoVersion", Some("1.0.0")),
+            DeployStatus::DeployedUnknownVersion
+        );
+        assert_eq!(status("Missing", Some("1.0.0")), DeployStatus::NotDeployed);
+        // a value mapping with the id of an integration flow isn't its deployment
+        assert_eq!(status("Mapping", Some("1.0.0")), DeployStatus::NotDeployed);
+    }
+
+    #[test]
+    fn deployed_artifacts_without_a_listing_are_runtime_only() {
+        let runtime = vec![
+            deployed("Flow", "INTEGRATION_FLOW", Some("1.0.0")),
+            deployed("Direct", "INTEGRATION_FLOW", Some("1.0.0")),
+            deployed("Flow", "VALUE_MAPPING", Some("1.0.0")),
+        ];
+        let listed = vec![(
+            "PkgA".to_string(),
+            ArtifactKind::Iflow,
+            "Flow".to_string(),
+            Some("1.0.0".to_string()),
+        )];
+
+        let only: Vec<(&str, Option<&str>)> = runtime_only(&runtime, &listed)
+            .iter()
+            .map(|r| (r.id.as_str(), r.artifact_type.as_deref()))
+            .collect();
+        assert_eq!(
+            only,
+            vec![
+                ("Direct", Some("INTEGRATION_FLOW")),
+                ("Flow", Some("VALUE_MAPPING"))
+            ]
+        );
+    }
+}
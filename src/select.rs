@@ -0,0 +1,166 @@
+use crate::error::Error;
+use crate::APIResponseResult;
+use crossterm::event::{read, Event, KeyCode, KeyModifiers};
+use crossterm::{cursor, execute, queue, style::Print, terminal};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+// set by --interactive-select of the command line tool, an embedder never gets a menu
+static INTERACTIVE: AtomicBool = AtomicBool::new(false);
+// the packages picked in the menu, printed as filter rules once the run is over
+static PICKED: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+// the next sync shows a menu to pick the packages instead of evaluating filter_rules
+pub fn enable_interactive_select() {
+    INTERACTIVE.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn is_enabled() -> bool {
+    INTERACTIVE.load(Ordering::Relaxed)
+}
+
+// a `single` rule per picked package, to paste into packages.filter_rules
+pub fn print_selected_filter_rules() {
+    let picked = match PICKED.lock().ok().and_then(|mut picked| picked.take()) {
+        Some(picked) => picked,
+        None => return,
+    };
+    let rules: Vec<serde_json::Value> = picked
+        .iter()
+        .map(|id| serde_json::json!({ "type": "single", "id": id }))
+        .collect();
+    println!("Filter rules of the selection, for packages.filter_rules in cpi-sync.json:");
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&serde_json::json!({ "filter_rules": rules }))
+            .unwrap_or_default()
+    );
+}
+
+struct Menu<'a> {
+    packages: &'a [APIResponseResult],
+    checked: Vec<bool>,
+    cursor: usize,
+    top: usize,
+    hint: Option<&'static str>,
+}
+
+impl Menu<'_> {
+    fn draw(&mut self, out: &mut impl Write) -> crossterm::Result<()> {
+        let (_, rows) = terminal::size()?;
+        let visible = (rows as usize).saturating_sub(3).max(1);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + visible {
+            self.top = self.cursor + 1 - visible;
+        }
+        queue!(
+            out,
+            terminal::Clear(terminal::ClearType::All),
+            cursor::MoveTo(0, 0),
+            Print(format!(
+                "Select packages ({} of {}): Space toggles, a toggles all, Enter syncs, Esc cancels",
+                self.checked.iter().filter(|c| **c).count(),
+                self.packages.len()
+            )),
+        )?;
+        let end = (self.top + visible).min(self.packages.len());
+        for (line, index) in (self.top..end).enumerate() {
+            let package = &self.packages[index];
+            queue!(
+                out,
+                cursor::MoveTo(0, line as u16 + 2),
+                Print(format!(
+                    "{} [{}] {} ({})",
+                    if index == self.cursor { ">" } else { " " },
+                    if self.checked[index] { "x" } else { " " },
+                    package.name,
+                    package.id
+                )),
+            )?;
+        }
+        if let Some(hint) = self.hint.take() {
+            queue!(out, cursor::MoveTo(0, rows.saturating_sub(1)), Print(hint))?;
+        }
+        Ok(out.flush()?)
+    }
+
+    // the picked package ids, None when the menu was cancelled
+    fn run(&mut self, out: &mut impl Write) -> Result<Option<Vec<String>>, Error> {
+        loop {
+            self.draw(out)?;
+            let key = match read()? {
+                Event::Key(key) => key,
+                _ => continue,
+            };
+            let last = self.packages.len().saturating_sub(1);
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => self.cursor = (self.cursor + 1).min(last),
+                KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(10),
+                KeyCode::PageDown => self.cursor = (self.cursor + 10).min(last),
+                KeyCode::Home => self.cursor = 0,
+                KeyCode::End => self.cursor = last,
+                KeyCode::Char(' ') => self.checked[self.cursor] = !self.checked[self.cursor],
+                KeyCode::Char('a') => {
+                    let all = self.checked.iter().all(|c| *c);
+                    self.checked.iter_mut().for_each(|c| *c = !all);
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(None)
+                }
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(None),
+                KeyCode::Enter if self.checked.iter().any(|c| *c) => {
+                    return Ok(Some(
+                        self.packages
+                            .iter()
+                            .zip(self.checked.iter())
+                            .filter(|(_, checked)| **checked)
+                            .map(|(package, _)| package.id.clone())
+                            .collect(),
+                    ))
+                }
+                KeyCode::Enter => self.hint = Some("Select at least one package with Space"),
+                _ => {}
+            }
+        }
+    }
+}
+
+// a checkbox list of all packages of the tenant on the alternate screen of stderr
+pub(crate) fn pick_packages(packages: &[APIResponseResult]) -> Result<Vec<String>, Error> {
+    if packages.is_empty() {
+        return Err(Error::Selection(
+            "The tenant has no packages to select".to_string(),
+        ));
+    }
+    let mut packages = packages.to_vec();
+    packages.sort_by_key(|p| p.name.to_lowercase());
+    let mut menu = Menu {
+        checked: vec![false; packages.len()],
+        packages: &packages,
+        cursor: 0,
+        top: 0,
+        hint: None,
+    };
+
+    let mut out = std::io::stderr();
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    terminal::enable_raw_mode()?;
+    let picked = menu.run(&mut out);
+    //the terminal is restored also when reading a key failed
+    let _ = terminal::disable_raw_mode();
+    let _ = execute!(out, cursor::Show, terminal::LeaveAlternateScreen);
+
+    match picked? {
+        Some(picked) => {
+            log::info!("{} package(s) selected interactively", picked.len());
+            if let Ok(mut stored) = PICKED.lock() {
+                *stored = Some(picked.clone());
+            }
+            Ok(picked)
+        }
+        None => Err(Error::SelectionCancelled),
+    }
+}
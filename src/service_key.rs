@@ -0,0 +1,105 @@
+use crate::config::{Config, CredentialInside, CredentialServiceKey};
+use crate::error::Error;
+use serde::Deserialize;
+
+// the `oauth` part of a Process Integration Runtime service key (plan api). A key created with
+// `"credential-type": "x509"` has certificate and key instead of clientsecret.
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct ServiceKeyOauth {
+    #[serde(rename = "clientid")]
+    pub client_id: String,
+    #[serde(rename = "clientsecret")]
+    pub client_secret: Option<String>,
+    pub certificate: Option<String>,
+    pub key: Option<String>,
+    #[serde(rename = "tokenurl")]
+    pub token_url: String,
+    // API URL of the tenant
+    pub url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ServiceKey {
+    oauth: ServiceKeyOauth,
+}
+
+pub(crate) fn read(credential: &CredentialServiceKey) -> Result<ServiceKeyOauth, Error> {
+    let (source, content) = match (&credential.path, &credential.environment_variable) {
+        (Some(path), _) => (
+            path.to_string(),
+            std::fs::read_to_string(path).map_err(|e| {
+                Error::ConfigValidation(format!("Can not read service_key.path {}: {}", path, e))
+            })?,
+        ),
+        (None, Some(varkey)) => (
+            format!("environment variable {}", varkey),
+            std::env::var(varkey).map_err(|e| {
+                Error::ConfigValidation(format!(
+                    "Can not find service key environment variable: {}: {}",
+                    varkey, e
+                ))
+            })?,
+        ),
+        (None, None) => {
+            return Err(Error::ConfigValidation(
+                "service_key needs path or environment_variable".to_string(),
+            ))
+        }
+    };
+    let key: ServiceKey = serde_json::from_str(&content).map_err(|e| {
+        Error::ConfigValidation(format!(
+            "Service key from {} is not a service key with an oauth section: {}",
+            source, e
+        ))
+    })?;
+    if key.oauth.client_secret.is_none()
+        && (key.oauth.certificate.is_none() || key.oauth.key.is_none())
+    {
+        return Err(Error::ConfigValidation(format!(
+            "Service key from {} has neither clientsecret nor certificate and key",
+            source
+        )));
+    }
+    Ok(key.oauth)
+}
+
+fn url_host(url: &str) -> Option<String> {
+    let host = reqwest::Url::parse(url).ok()?.host_str()?.to_string();
+    Some(host)
+}
+
+// fills tenant.management_host from the `url` of the first service key when it isn't set, a
+// configured host that differs from the key is kept with a warning
+pub fn resolve_service_keys(config: &mut Config) -> Result<(), Error> {
+    let mut key_host = None;
+    for candidate in config.tenant.credential.candidates() {
+        if let CredentialInside::ServiceKey(credential) = candidate {
+            let host = read(credential)?.url.as_deref().and_then(url_host);
+            if key_host.is_none() {
+                key_host = host;
+            }
+        }
+    }
+    match (config.tenant.management_host.is_empty(), key_host) {
+        (true, Some(host)) => {
+            log::info!("management_host {} from the service key", host);
+            config.tenant.management_host = host;
+        }
+        (true, None) => {
+            return Err(Error::ConfigValidation(
+                "tenant.management_host is required unless a service_key credential has a url"
+                    .to_string(),
+            ))
+        }
+        (false, Some(host)) if !host.eq_ignore_ascii_case(&config.tenant.management_host) => {
+            log::warn!(
+                "management_host {} differs from the service key url host {}, using {}",
+                config.tenant.management_host,
+                host,
+                config.tenant.management_host
+            );
+        }
+        (false, _) => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,304 @@
+use crate::auth::Authorization;
+use crate::error::Error;
+use crate::{
+    filter, get_all_packages, get_authorization, http, list_package_artifacts, runtime,
+    APIResponseResult, ArtifactKind, Config,
+};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::cmp::Ordering;
+
+// an artifact with a saved version above the one that is Active: its changes never deploy
+#[derive(Serialize, Debug, Clone)]
+pub struct StaleActive {
+    pub package_id: String,
+    pub artifact_id: String,
+    pub artifact_type: String,
+    pub active_version: String,
+    pub highest_version: String,
+}
+
+// result of packages.check_stale_active, in the run report
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct StaleActiveCheck {
+    // false when the tenant has no Versions feed, nothing could be compared
+    pub available: bool,
+    pub checked: usize,
+    pub artifacts: Vec<StaleActive>,
+}
+
+// a listed artifact: package id, type, id and its Active version
+pub(crate) type ActiveArtifact = (String, ArtifactKind, String, Option<String>);
+
+// "1.0.10" is above "1.0.9", parts that aren't numbers compare as text
+pub(crate) fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => {
+                let order = match (a.parse::<u64>(), b.parse::<u64>()) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    _ => a.cmp(b),
+                };
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+        }
+    }
+}
+
+// None when the tenant doesn't expose the version history of the artifact
+async fn saved_versions(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    kind: ArtifactKind,
+    artifact_id: &str,
+    timings: Option<&http::Timings>,
+) -> Result<Option<Vec<String>>, Error> {
+    let url = kind.versions_url(config, artifact_id);
+    let request = client.get(&url).header("Accept", "application/json");
+    let resp = authorization
+        .fetch(request, &url, http::Endpoint::ArtifactList(kind), timings)
+        .await?;
+    if !resp.status.is_success() {
+        log::debug!(
+            "Versions of {:?} not available (API Response Code: {})",
+            artifact_id,
+            resp.status
+        );
+        return Ok(None);
+    }
+    let body: serde_json::Value = match serde_json::from_slice(&resp.body) {
+        Ok(body) => body,
+        Err(_) => return Ok(None),
+    };
+    let versions = body["d"]["results"].as_array().map(|results| {
+        results
+            .iter()
+            .filter_map(|r| r["Version"].as_str())
+            .filter(|v| !v.eq_ignore_ascii_case("active"))
+            .map(|v| v.to_string())
+            .collect()
+    });
+    Ok(versions)
+}
+
+// compares the Active version of every artifact with its saved versions, only metadata is read
+pub(crate) async fn check_stale_active(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    timings: Option<&http::Timings>,
+    artifacts: Vec<ActiveArtifact>,
+) -> Result<StaleActiveCheck, Error> {
+    let results: Vec<_> = stream::iter(artifacts.into_iter().map(
+        |(package_id, kind, artifact_id, active_version)| async move {
+            let versions =
+                saved_versions(config, client, authorization, kind, &artifact_id, timings).await;
+            (package_id, kind, artifact_id, active_version, versions)
+        },
+    ))
+    .buffer_unordered(config.packages.worker_count.max(1))
+    .collect()
+    .await;
+
+    let mut check = StaleActiveCheck::default();
+    for (package_id, kind, artifact_id, active_version, versions) in results {
+        let versions = match versions? {
+            Some(versions) => versions,
+            None => continue,
+        };
+        check.available = true;
+        check.checked += 1;
+        let active_version = match active_version {
+            Some(active_version) => active_version,
+            None => continue,
+        };
+        let highest = versions.into_iter().max_by(|a, b| compare_versions(a, b));
+        if let Some(highest_version) = highest {
+            if compare_versions(&highest_version, &active_version) == Ordering::Greater {
+                check.artifacts.push(StaleActive {
+                    package_id,
+                    artifact_id,
+                    artifact_type: kind.to_string(),
+                    active_version,
+                    highest_version,
+                });
+            }
+        }
+    }
+    check
+        .artifacts
+        .sort_by(|a, b| (&a.package_id, &a.artifact_id).cmp(&(&b.package_id, &b.artifact_id)));
+    Ok(check)
+}
+
+pub(crate) fn print_summary(check: &StaleActiveCheck) {
+    if !check.available {
+        log::info!(
+            "Stale active check: the tenant doesn't expose artifact versions, nothing was compared"
+        );
+        return;
+    }
+    if check.artifacts.is_empty() {
+        log::info!(
+            "Stale active check: {} artifact(s) checked, all are Active at their highest version",
+            check.checked
+        );
+        return;
+    }
+    let mut text = format!(
+        "Stale active check: {} of {} artifact(s) have a saved version above the Active one:",
+        check.artifacts.len(),
+        check.checked
+    );
+    for stale in check.artifacts.iter() {
+        text.push_str(&format!(
+            "\n  {}/{} ({}): active {}, highest {}",
+            stale.package_id,
+            stale.artifact_id,
+            stale.artifact_type,
+            stale.active_version,
+            stale.highest_version
+        ));
+    }
+    log::warn!("{}", text);
+}
+
+// the selected packages and their selected artifacts with the Active version, listed like a sync
+// without downloads. with_skipped also returns the artifacts of artifact_filter_rules.
+pub(crate) async fn list_artifacts(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &Authorization,
+    with_skipped: bool,
+) -> Result<(Vec<String>, Vec<ActiveArtifact>), Error> {
+    let timings = http::Timings::default();
+    let api_package_list = get_all_packages(config, client, authorization, None).await?;
+    let package_decisions = filter::evaluate_filter_rules(config, &api_package_list.d.results)?;
+    let mut selected: Vec<_> = package_decisions.iter().filter(|d| d.selected).collect();
+    selected.sort_by(|a, b| a.package_id.cmp(&b.package_id));
+
+    let package_ids = selected.iter().map(|d| d.package_id.clone()).collect();
+    let mut artifacts = Vec::new();
+    for decision in selected {
+        let selection = filter::artifact_selection(config, decision)?;
+        for kind in ArtifactKind::ALL.iter().filter(|k| k.enabled(config)) {
+            let listed: Vec<APIResponseResult> = list_package_artifacts(
+                &decision.package_id,
+                *kind,
+                config,
+                client,
+                authorization,
+                &timings,
+            )
+            .await?;
+            for artifact in listed {
+                if !with_skipped
+                    && selection
+                        .skip_reason(config, &artifact.id, artifact.modified_at.as_deref())
+                        .is_some()
+                {
+                    continue;
+                }
+                artifacts.push((
+                    decision.package_id.clone(),
+                    *kind,
+                    artifact.id,
+                    artifact.version,
+                ));
+            }
+        }
+    }
+    Ok((package_ids, artifacts))
+}
+
+// `list` prints the selected artifacts with their Active version, `--stale-active` only those
+// with a saved version above it. `--with-deploy-status` adds the deploy status of every artifact
+// and the deployed artifacts no selected package lists.
+pub async fn run_list(
+    config: &Config,
+    no_input: bool,
+    confirm_secret: bool,
+    stale_active: bool,
+    with_deploy_status: bool,
+) -> Result<(), Error> {
+    let client = http::build_client(config)?;
+    let (config, authorization) =
+        get_authorization(config, &client, no_input, confirm_secret).await?;
+    let config = &config;
+    let (_, artifacts) = list_artifacts(config, &client, &authorization, false).await?;
+
+    if !stale_active {
+        let runtime_artifacts = match with_deploy_status {
+            true => Some(
+                runtime::list_runtime_artifacts(
+                    config,
+                    &client,
+                    &authorization,
+                    &http::Timings::default(),
+                )
+                .await?,
+            ),
+            false => None,
+        };
+        for (package_id, kind, artifact_id, version) in artifacts.iter() {
+            let status = match &runtime_artifacts {
+                Some(runtime_artifacts) => format!(
+                    "\t{}",
+                    runtime::deploy_status(
+                        runtime_artifacts,
+                        *kind,
+                        artifact_id,
+                        version.as_deref()
+                    )
+                ),
+                None => String::new(),
+            };
+            println!(
+                "{}\t{}\t{}\t{}{}",
+                package_id,
+                kind,
+                artifact_id,
+                version.as_deref().unwrap_or("-"),
+                status
+            );
+        }
+        if let Some(runtime_artifacts) = &runtime_artifacts {
+            for deployed in runtime::runtime_only(runtime_artifacts, &artifacts) {
+                println!(
+                    "-\t{}\t{}\t{}\tRuntimeOnly",
+                    deployed.artifact_type.as_deref().unwrap_or("-"),
+                    deployed.id,
+                    deployed.version.as_deref().unwrap_or("-")
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    let check = check_stale_active(config, &client, &authorization, None, artifacts).await?;
+    if !check.available {
+        println!(
+            "The tenant doesn't expose artifact versions, stale active versions can't be detected."
+        );
+        return Ok(());
+    }
+    for stale in check.artifacts.iter() {
+        println!(
+            "{}\t{}\t{}\tactive {}\thighest {}",
+            stale.package_id,
+            stale.artifact_type,
+            stale.artifact_id,
+            stale.active_version,
+            stale.highest_version
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use crate::artifact::ArtifactKind;
+use crate::http::EndpointTimings;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+// counters of a sync run, also returned when the run was cancelled
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RunStats {
+    pub packages_selected: usize,
+    pub packages_listed: usize,
+    pub artifacts_listed: usize,
+    pub artifacts_downloaded: usize,
+    // keyed by OData entity set, every enabled artifact type is present even with 0 artifacts
+    pub artifacts_listed_by_type: BTreeMap<String, usize>,
+    pub artifacts_downloaded_by_type: BTreeMap<String, usize>,
+    // per endpoint family, filled when the run finishes
+    pub timings: Vec<EndpointTimings>,
+    // packages found by packages.refresh_listing_at_end after the main pass
+    pub late_additions: Vec<String>,
+    // removed by packages.prune, relative to local_dir
+    pub pruned: Vec<String>,
+    // created by git.commit, None when it is off or nothing changed
+    pub git_commit: Option<String>,
+}
+
+impl RunStats {
+    pub(crate) fn count_download(&mut self, kind: ArtifactKind) {
+        self.artifacts_downloaded += 1;
+        *self
+            .artifacts_downloaded_by_type
+            .entry(kind.to_string())
+            .or_insert(0) += 1;
+    }
+
+    pub(crate) fn print_type_summary(&self) {
+        log::info!("Artifacts per type (downloaded/listed):");
+        for (artifact_type, listed) in self.artifacts_listed_by_type.iter() {
+            let downloaded = self
+                .artifacts_downloaded_by_type
+                .get(artifact_type)
+                .copied()
+                .unwrap_or(0);
+            log::info!("  {}: {}/{}", artifact_type, downloaded, listed);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cancelled {
+    pub stats: RunStats,
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Sync cancelled after {}/{} packages listed and {}/{} artifacts downloaded",
+            self.stats.packages_listed,
+            self.stats.packages_selected,
+            self.stats.artifacts_downloaded,
+            self.stats.artifacts_listed
+        )
+    }
+}
+
+impl std::error::Error for Cancelled {}
@@ -0,0 +1,213 @@
+use crate::error::Error;
+use serde_json::Value;
+use std::env;
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+const REDACTED: &str = "<redacted>";
+
+// config keys whose string values are secrets, the tool itself only reads secrets from the environment
+// or from files
+const SECRET_KEYS: [&str; 6] = [
+    "password",
+    "client_secret",
+    "access_token",
+    "token",
+    "authorization",
+    "apikey",
+];
+
+// parts of a bug report the tool doesn't write yet, listed so the bundle says what's missing
+const NOT_AVAILABLE: [&str; 5] = [
+    "run report",
+    "status file",
+    "capability probe results",
+    "trace/log files",
+    "manifest header",
+];
+
+struct BundleFile {
+    name: &'static str,
+    description: &'static str,
+    content: String,
+}
+
+// values of the environment variables and contents of the files the config reads secrets from
+fn configured_secrets(value: &Value, secrets: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, child) in object {
+                let is_secret = ["password", "secret", "api_key", "passphrase"]
+                    .iter()
+                    .any(|s| key.contains(s));
+                //the secret and private key inside a service key
+                if key == "service_key" {
+                    if let Ok(credential) = serde_json::from_value(child.clone()) {
+                        if let Ok(service_key) = crate::service_key::read(&credential) {
+                            secrets.extend(service_key.client_secret);
+                            secrets.extend(service_key.key);
+                        }
+                    }
+                    continue;
+                }
+                match child.as_str() {
+                    Some(varkey) if is_secret && key.ends_with("_environment_variable") => {
+                        if let Ok(secret) = env::var(varkey) {
+                            secrets.push(secret);
+                        }
+                    }
+                    Some(path) if is_secret && key.ends_with("_file") => {
+                        if let Ok(content) = std::fs::read_to_string(path) {
+                            secrets.push(content.trim_end_matches(['\r', '\n']).to_string());
+                        }
+                    }
+                    _ => configured_secrets(child, secrets),
+                }
+            }
+        }
+        Value::Array(items) => items
+            .iter()
+            .for_each(|item| configured_secrets(item, secrets)),
+        _ => {}
+    }
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, child) in object.iter_mut() {
+                if child.is_string() && SECRET_KEYS.contains(&key.to_ascii_lowercase().as_str()) {
+                    *child = Value::String(REDACTED.to_string());
+                } else {
+                    redact_value(child);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+// runs over every text file of the bundle: known secret values, then what logging::redact finds
+fn redact_text(text: &str, secrets: &[String]) -> String {
+    let mut text = text.to_string();
+    for secret in secrets.iter().filter(|s| s.len() >= 4) {
+        text = text.replace(secret.as_str(), REDACTED);
+    }
+    crate::logging::redact(&text)
+}
+
+fn environment_text(config_path: &str) -> String {
+    let mut lines = vec![
+        format!("cpi-sync version: {}", env!("CARGO_PKG_VERSION")),
+        format!("os: {}", env::consts::OS),
+        format!("arch: {}", env::consts::ARCH),
+        format!("config path: {}", config_path),
+    ];
+    for varkey in [
+        "HTTP_PROXY",
+        "HTTPS_PROXY",
+        "NO_PROXY",
+        "http_proxy",
+        "https_proxy",
+        "no_proxy",
+    ]
+    .iter()
+    {
+        if let Ok(value) = env::var(varkey) {
+            lines.push(format!("{}: {}", varkey, value));
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+// the same checks a sync does before it connects, without the schema
+fn config_check_text(config_json: &Value) -> String {
+    let mut lines = Vec::new();
+    match crate::split_tenants(config_json) {
+        Ok(tenants) => {
+            for tenant in tenants {
+                let prefix = tenant
+                    .name
+                    .map(|name| format!("tenant {}: ", name))
+                    .unwrap_or_default();
+                match serde_json::from_value::<crate::Config>(tenant.value) {
+                    Ok(config) => {
+                        let lints = crate::lint_config(&config);
+                        lines.push(format!(
+                            "{}config parsed, {} lint warning(s)",
+                            prefix,
+                            lints.len()
+                        ));
+                        lines.extend(lints.iter().map(|lint| format!("{}{}", prefix, lint)));
+                    }
+                    Err(err) => lines.push(format!("{}config error: {}", prefix, err)),
+                }
+            }
+        }
+        Err(err) => lines.push(format!("config error: {}", err)),
+    }
+    lines.join("\n") + "\n"
+}
+
+pub fn run_support_bundle(config_path: &str, out: &Path) -> Result<(), Error> {
+    let config_text = std::fs::read_to_string(config_path)?;
+    let mut files = vec![BundleFile {
+        name: "environment.txt",
+        description: "tool version, platform, config path and proxy variables",
+        content: environment_text(config_path),
+    }];
+
+    let mut secrets = Vec::new();
+    match crate::parse_config_text(config_path, &config_text) {
+        Ok(mut config_json) => {
+            configured_secrets(&config_json, &mut secrets);
+            let deprecations = crate::apply_deprecations(&mut config_json);
+            let mut check = config_check_text(&config_json);
+            for deprecation in &deprecations {
+                check.push_str(&format!("deprecation: {}\n", deprecation));
+            }
+            redact_value(&mut config_json);
+            files.push(BundleFile {
+                name: "config.json",
+                description: "effective config after deprecations, secrets replaced",
+                content: serde_json::to_string_pretty(&config_json)? + "\n",
+            });
+            files.push(BundleFile {
+                name: "config-check.txt",
+                description: "config parse result, lint warnings and deprecations",
+                content: check,
+            });
+        }
+        Err(err) => files.push(BundleFile {
+            name: "config-check.txt",
+            description: "why the config can't be read (the config itself is not included)",
+            content: format!("config error: {}\n", err),
+        }),
+    }
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for file in files.iter_mut() {
+        file.content = redact_text(&file.content, &secrets);
+        zip.start_file(file.name, zip::write::FileOptions::default())?;
+        zip.write_all(file.content.as_bytes())?;
+    }
+    std::fs::write(out, zip.finish()?.into_inner())?;
+
+    println!("Support bundle written to {}", out.display());
+    println!("Included:");
+    for file in files.iter() {
+        println!(
+            "  {} ({} bytes): {}",
+            file.name,
+            file.content.len(),
+            file.description
+        );
+    }
+    println!(
+        "Not included, not written by this version: {}",
+        NOT_AVAILABLE.join(", ")
+    );
+    println!("Secrets were redacted, review the files before sharing the bundle.");
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use crate::error::Error;
+use serde_json::{Map, Value};
+
+// fields of a `tenants` entry that are not part of `tenant`
+const ENTRY_FIELDS: [&str; 3] = ["name", "packages", "local_dir"];
+
+// one tenant of the config in the single-tenant form, `name` is None for a plain `tenant` config
+#[derive(Debug, Clone)]
+pub struct TenantValue {
+    pub name: Option<String>,
+    pub value: Value,
+}
+
+// builds the single-tenant config of a `tenants` entry: the entry's packages replace the
+// top-level packages, local_dir overrides packages.local_dir
+fn entry_config(
+    root: &Map<String, Value>,
+    entry: &Map<String, Value>,
+    name: &str,
+) -> Result<Value, Error> {
+    let mut config = root.clone();
+    config.remove("tenants");
+
+    let mut packages = match entry.get("packages").or_else(|| root.get("packages")) {
+        Some(packages) => packages.clone(),
+        None => {
+            return Err(Error::ConfigValidation(format!(
+                "Tenant '{}' has no packages and there are no top-level packages",
+                name
+            )))
+        }
+    };
+    if let (Some(local_dir), Value::Object(packages)) = (entry.get("local_dir"), &mut packages) {
+        packages.insert("local_dir".to_string(), local_dir.clone());
+    }
+    config.insert("packages".to_string(), packages);
+
+    let tenant: Map<String, Value> = entry
+        .iter()
+        .filter(|(key, _)| !ENTRY_FIELDS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    config.insert("tenant".to_string(), Value::Object(tenant));
+    Ok(Value::Object(config))
+}
+
+// splits a validated raw config into one config per tenant, in file order
+pub fn split_tenants(config_json: &Value) -> Result<Vec<TenantValue>, Error> {
+    let root = match config_json {
+        Value::Object(root) => root,
+        _ => {
+            return Err(Error::ConfigValidation(
+                "The config must be an object".to_string(),
+            ))
+        }
+    };
+    let entries = match root.get("tenants") {
+        None => {
+            return Ok(vec![TenantValue {
+                name: None,
+                value: config_json.clone(),
+            }])
+        }
+        Some(Value::Array(entries)) => entries,
+        Some(_) => {
+            return Err(Error::ConfigValidation(
+                "tenants must be an array".to_string(),
+            ))
+        }
+    };
+    if root.contains_key("tenant") {
+        return Err(Error::ConfigValidation(
+            "Use either tenant or tenants, not both".to_string(),
+        ));
+    }
+
+    let mut tenants: Vec<TenantValue> = Vec::new();
+    for entry in entries {
+        let entry = entry.as_object().ok_or_else(|| {
+            Error::ConfigValidation("Every tenants entry must be an object".to_string())
+        })?;
+        let name = entry
+            .get("name")
+            .and_then(|n| n.as_str())
+            .filter(|n| !n.is_empty())
+            .ok_or_else(|| {
+                Error::ConfigValidation("Every tenants entry needs a name".to_string())
+            })?;
+        if tenants.iter().any(|t| t.name.as_deref() == Some(name)) {
+            return Err(Error::ConfigValidation(format!(
+                "Tenant name '{}' is used twice",
+                name
+            )));
+        }
+        tenants.push(TenantValue {
+            name: Some(name.to_string()),
+            value: entry_config(root, entry, name)?,
+        });
+    }
+
+    // tenants writing into the same directory would empty each other's packages
+    for (index, tenant) in tenants.iter().enumerate() {
+        let local_dir = &tenant.value["packages"]["local_dir"];
+        if let Some(other) = tenants[..index]
+            .iter()
+            .find(|other| &other.value["packages"]["local_dir"] == local_dir)
+        {
+            return Err(Error::ConfigValidation(format!(
+                "Tenants '{}' and '{}' use the same local_dir, set local_dir per tenant",
+                other.name.as_deref().unwrap_or_default(),
+                tenant.name.as_deref().unwrap_or_default()
+            )));
+        }
+    }
+    Ok(tenants)
+}
+
+// --tenant restricts the run to one entry of `tenants`
+pub fn select_tenant(
+    tenants: Vec<TenantValue>,
+    name: Option<&str>,
+) -> Result<Vec<TenantValue>, Error> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(tenants),
+    };
+    if tenants.iter().all(|t| t.name.is_none()) {
+        return Err(Error::ConfigValidation(
+            "--tenant needs a config with a tenants array".to_string(),
+        ));
+    }
+    let names: Vec<&str> = tenants.iter().filter_map(|t| t.name.as_deref()).collect();
+    if !names.contains(&name) {
+        return Err(Error::ConfigValidation(format!(
+            "Unknown tenant '{}', the config has: {}",
+            name,
+            names.join(", ")
+        )));
+    }
+    Ok(tenants
+        .into_iter()
+        .filter(|t| t.name.as_deref() == Some(name))
+        .collect())
+}
@@ -0,0 +1,138 @@
+use crate::error::Error;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io::{Cursor, Write};
+
+// layout of a transport archive, kept apart from the export so the format can change on its own.
+// The archive is MTA-style like the ones CTMS imports for Cloud Integration content:
+//   META-INF/MANIFEST.MF   one section per package zip, naming its MTA module
+//   META-INF/mtad.yaml     deployment descriptor, one com.sap.hci.content module per package
+//   <package id>.zip       the package export of the tenant, unchanged
+// Entries are sorted by package id and carry a fixed timestamp, the same packages give the same bytes.
+
+pub(crate) const MTA_SCHEMA_VERSION: &str = "3.1";
+pub(crate) const MODULE_TYPE: &str = "com.sap.hci.content";
+
+// a package export that goes into the archive
+pub(crate) struct TransportPackage {
+    pub id: String,
+    pub name: String,
+    pub version: Option<String>,
+    pub content: Vec<u8>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TransportPackageInfo {
+    pub id: String,
+    pub name: String,
+    pub version: Option<String>,
+    pub file: String,
+    pub bytes: usize,
+    pub sha256: String,
+}
+
+// the JSON description written next to the archive
+#[derive(Debug, Serialize)]
+pub(crate) struct TransportDescription {
+    pub mta_id: String,
+    pub mta_version: String,
+    pub archive_sha256: String,
+    pub packages: Vec<TransportPackageInfo>,
+    pub failed: Vec<String>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn package_file(package: &TransportPackage) -> String {
+    format!("{}.zip", package.id)
+}
+
+fn manifest(packages: &[TransportPackage]) -> String {
+    let mut text = format!(
+        "Manifest-Version: 1.0\r\nCreated-By: cpi-sync {}\r\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    for package in packages {
+        text.push_str(&format!(
+            "\r\nName: {}\r\nMTA-Module: {}\r\nContent-Type: application/zip\r\n",
+            package_file(package),
+            package.id
+        ));
+    }
+    text
+}
+
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn descriptor(mta_id: &str, mta_version: &str, packages: &[TransportPackage]) -> String {
+    let mut text = format!(
+        "_schema-version: {}\nID: {}\nversion: {}\nmodules:\n",
+        yaml_string(MTA_SCHEMA_VERSION),
+        yaml_string(mta_id),
+        yaml_string(mta_version)
+    );
+    for package in packages {
+        text.push_str(&format!(
+            "  - name: {}\n    type: {}\n    path: {}\n",
+            yaml_string(&package.id),
+            MODULE_TYPE,
+            yaml_string(&package_file(package))
+        ));
+    }
+    text
+}
+
+// writes the archive and describes it, packages are sorted by id first
+pub(crate) fn build_transport(
+    mta_id: &str,
+    mta_version: &str,
+    mut packages: Vec<TransportPackage>,
+    failed: Vec<String>,
+) -> Result<(Vec<u8>, TransportDescription), Error> {
+    packages.sort_by(|a, b| a.id.cmp(&b.id));
+    let options = zip::write::FileOptions::default()
+        .last_modified_time(zip::DateTime::default())
+        .unix_permissions(0o644);
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("META-INF/MANIFEST.MF", options)?;
+    zip.write_all(manifest(&packages).as_bytes())?;
+    zip.start_file("META-INF/mtad.yaml", options)?;
+    zip.write_all(descriptor(mta_id, mta_version, &packages).as_bytes())?;
+    for package in packages.iter() {
+        // package exports are already compressed
+        zip.start_file(
+            package_file(package),
+            options.compression_method(zip::CompressionMethod::Stored),
+        )?;
+        zip.write_all(&package.content)?;
+    }
+    let archive = zip.finish()?.into_inner();
+
+    let description = TransportDescription {
+        mta_id: mta_id.to_string(),
+        mta_version: mta_version.to_string(),
+        archive_sha256: sha256(&archive),
+        packages: packages
+            .iter()
+            .map(|package| TransportPackageInfo {
+                id: package.id.clone(),
+                name: package.name.clone(),
+                version: package.version.clone(),
+                file: package_file(package),
+                bytes: package.content.len(),
+                sha256: sha256(&package.content),
+            })
+            .collect(),
+        failed,
+    };
+    Ok((archive, description))
+}
@@ -0,0 +1,331 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use std::fmt;
+use std::time::Duration;
+
+// accepted forms, shown in every parse error so flags and config fields read the same
+const DURATION_FORMS: &str =
+    "a number of seconds or a value with unit, e.g. \"90\", \"90s\", \"15m\", \"1h30m\", \"2.5h\", \"500ms\"";
+const DATE_FORMS: &str =
+    "an ISO-8601 date or date-time, e.g. \"2024-01-31\" (UTC midnight), \"2024-01-31T08:00:00Z\", \"2024-01-31T10:00:00+02:00\"";
+const SIZE_FORMS: &str =
+    "a number of bytes or a value with unit, e.g. \"1048576\", \"500k\", \"2.5M\", \"1G\", \"64KiB\", \"10MiB\"";
+const MODE_FORMS: &str = "an octal file mode, e.g. \"644\", \"0640\", \"0o750\"";
+
+// split "1h30m" into [(1, "h"), (30, "m")]
+fn split_number_unit(input: &str) -> Option<Vec<(f64, String)>> {
+    let mut parts = Vec::new();
+    let mut rest = input.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        if number_len == 0 {
+            return None;
+        }
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = rest[number_len..].trim_start();
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        parts.push((number, rest[..unit_len].to_string()));
+        rest = rest[unit_len..].trim_start();
+    }
+    Some(parts)
+}
+
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let error = || format!("invalid duration '{}': expected {}", input, DURATION_FORMS);
+    let parts = split_number_unit(input).ok_or_else(error)?;
+    if parts.len() > 1 && parts.iter().any(|(_, unit)| unit.is_empty()) {
+        return Err(error());
+    }
+
+    let mut seconds = 0.0;
+    for (number, unit) in parts {
+        let factor = match unit.as_str() {
+            "" | "s" | "sec" | "secs" => 1.0,
+            "ms" => 0.001,
+            "m" | "min" | "mins" => 60.0,
+            "h" | "hr" | "hrs" => 3600.0,
+            "d" => 86400.0,
+            _ => return Err(error()),
+        };
+        seconds += number * factor;
+    }
+    if !seconds.is_finite() {
+        return Err(error());
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+// decimal suffixes (k, M, G, kB, MB, GB) are powers of 1000, binary ones (KiB, MiB, GiB) powers of 1024
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let error = || format!("invalid size '{}': expected {}", input, SIZE_FORMS);
+    let parts = split_number_unit(input).ok_or_else(error)?;
+    if parts.len() != 1 {
+        return Err(error());
+    }
+    let (number, unit) = &parts[0];
+
+    let factor: f64 = match unit.as_str() {
+        "" | "b" | "B" => 1.0,
+        "k" | "K" | "kB" | "KB" => 1e3,
+        "M" | "MB" => 1e6,
+        "G" | "GB" => 1e9,
+        "Ki" | "KiB" => 1024.0,
+        "Mi" | "MiB" => 1024.0 * 1024.0,
+        "Gi" | "GiB" => 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(error()),
+    };
+    let bytes = (number * factor).round();
+    if !bytes.is_finite() || bytes > u64::MAX as f64 {
+        return Err(error());
+    }
+    Ok(bytes as u64)
+}
+
+// a string, a JSON number like 644 would read as decimal
+pub fn parse_mode(input: &str) -> Result<u32, String> {
+    let error = || format!("invalid file mode '{}': expected {}", input, MODE_FORMS);
+    let input = input.trim();
+    let digits = input.strip_prefix("0o").unwrap_or(input);
+    if digits.is_empty() {
+        return Err(error());
+    }
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(error()),
+    }
+}
+
+// dates without a time are UTC midnight, date-times without an offset are UTC
+pub fn parse_date_time(input: &str) -> Result<DateTime<Utc>, String> {
+    let error = || format!("invalid date '{}': expected {}", input, DATE_FORMS);
+    let input = input.trim();
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(date_time.with_timezone(&Utc));
+    }
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(input, "%Y-%m-%dT%H:%M:%S%.f") {
+        return Ok(Utc.from_utc_datetime(&date_time));
+    }
+    let date = NaiveDate::parse_from_str(input, "%Y-%m-%d").map_err(|_| error())?;
+    Ok(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).ok_or_else(error)?))
+}
+
+// config fields accept the same strings as the flags, plain integers stay seconds/bytes
+struct DurationVisitor;
+
+impl<'de> Visitor<'de> for DurationVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", DURATION_FORMS)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Duration, E> {
+        Ok(Duration::from_secs(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Duration, E> {
+        if v < 0 {
+            return Err(E::custom(format!(
+                "invalid duration '{}': expected {}",
+                v, DURATION_FORMS
+            )));
+        }
+        Ok(Duration::from_secs(v as u64))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Duration, E> {
+        parse_duration(v).map_err(E::custom)
+    }
+}
+
+struct SizeVisitor;
+
+impl<'de> Visitor<'de> for SizeVisitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", SIZE_FORMS)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<u64, E> {
+        Ok(v)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<u64, E> {
+        if v < 0 {
+            return Err(E::custom(format!(
+                "invalid size '{}': expected {}",
+                v, SIZE_FORMS
+            )));
+        }
+        Ok(v as u64)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<u64, E> {
+        parse_size(v).map_err(E::custom)
+    }
+}
+
+pub fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(DurationVisitor)
+}
+
+pub fn deserialize_size<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(SizeVisitor)
+}
+
+// for optional config fields: `#[serde(default, deserialize_with = "deserialize_opt_duration")]`
+pub fn deserialize_opt_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_duration(deserializer).map(Some)
+}
+
+pub fn deserialize_opt_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_size(deserializer).map(Some)
+}
+
+pub fn deserialize_opt_mode<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_mode(&value).map(Some).map_err(de::Error::custom)
+}
+
+pub fn deserialize_opt_date_time<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_date_time(&value).map(Some).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1h 30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2.5h"), Ok(Duration::from_secs(9000)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert_eq!(parse_duration(" 10 min "), Ok(Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn invalid_durations_list_the_forms() {
+        for input in ["", "abc", "10x", "1h30", "-5s", "s"].iter() {
+            let err = parse_duration(input).unwrap_err();
+            assert_eq!(
+                err,
+                format!("invalid duration '{}': expected {}", input, DURATION_FORMS)
+            );
+        }
+        assert!(parse_duration("1x")
+            .unwrap_err()
+            .contains("\"90s\", \"15m\", \"1h30m\""));
+    }
+
+    #[test]
+    fn decimal_sizes() {
+        assert_eq!(parse_size("1048576"), Ok(1_048_576));
+        assert_eq!(parse_size("100B"), Ok(100));
+        assert_eq!(parse_size("500k"), Ok(500_000));
+        assert_eq!(parse_size("500kB"), Ok(500_000));
+        assert_eq!(parse_size("2.5M"), Ok(2_500_000));
+        assert_eq!(parse_size("1GB"), Ok(1_000_000_000));
+    }
+
+    #[test]
+    fn binary_sizes() {
+        assert_eq!(parse_size("64KiB"), Ok(65_536));
+        assert_eq!(parse_size("64Ki"), Ok(65_536));
+        assert_eq!(parse_size("10MiB"), Ok(10_485_760));
+        assert_eq!(parse_size("1.5GiB"), Ok(1_610_612_736));
+    }
+
+    #[test]
+    fn invalid_sizes_list_the_forms() {
+        for input in ["", "1m", "10 MB 5", "1TB", "k"].iter() {
+            let err = parse_size(input).unwrap_err();
+            assert_eq!(
+                err,
+                format!("invalid size '{}': expected {}", input, SIZE_FORMS)
+            );
+        }
+        assert!(parse_size("1m")
+            .unwrap_err()
+            .contains("\"64KiB\", \"10MiB\""));
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(parse_mode("644"), Ok(0o644));
+        assert_eq!(parse_mode("0640"), Ok(0o640));
+        assert_eq!(parse_mode("0o750"), Ok(0o750));
+        assert_eq!(parse_mode("4755"), Ok(0o4755));
+        for input in ["", "0o", "888", "rwx", "17777"].iter() {
+            assert_eq!(
+                parse_mode(input).unwrap_err(),
+                format!("invalid file mode '{}': expected {}", input, MODE_FORMS)
+            );
+        }
+    }
+
+    #[test]
+    fn date_times() {
+        let utc = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        assert_eq!(
+            parse_date_time("2024-01-31"),
+            Ok(utc("2024-01-31T00:00:00Z"))
+        );
+        assert_eq!(
+            parse_date_time("2024-01-31T08:00:00Z"),
+            Ok(utc("2024-01-31T08:00:00Z"))
+        );
+        assert_eq!(
+            parse_date_time("2024-01-31T10:00:00+02:00"),
+            Ok(utc("2024-01-31T08:00:00Z"))
+        );
+        assert_eq!(
+            parse_date_time("2024-01-31T08:00:00.250"),
+            Ok(utc("2024-01-31T08:00:00.250Z"))
+        );
+    }
+
+    #[test]
+    fn invalid_date_times_list_the_forms() {
+        for input in ["", "31.01.2024", "2024-02-30", "yesterday"].iter() {
+            assert_eq!(
+                parse_date_time(input).unwrap_err(),
+                format!("invalid date '{}': expected {}", input, DATE_FORMS)
+            );
+        }
+        assert!(parse_date_time("x")
+            .unwrap_err()
+            .contains("\"2024-01-31\" (UTC midnight)"));
+    }
+}
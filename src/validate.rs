@@ -0,0 +1,145 @@
+use crate::config::ContentChecks;
+use crate::error::Error;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Serialize;
+use std::io::{Cursor, Read};
+
+pub const CHECK_IFLW_PRESENT: &str = "iflw_present";
+pub const CHECK_XML_WELL_FORMED: &str = "xml_well_formed";
+pub const CHECK_SCRIPT_NUL_BYTES: &str = "script_nul_bytes";
+
+const XML_EXTENSIONS: [&str; 7] = [".iflw", ".xml", ".xsd", ".wsdl", ".mmap", ".edmx", ".xsl"];
+const SCRIPT_EXTENSIONS: [&str; 3] = [".groovy", ".gsh", ".js"];
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ContentViolation {
+    pub package_id: String,
+    pub artifact_id: String,
+    pub check: &'static str,
+    pub file: Option<String>,
+    pub message: String,
+}
+
+// a file of an extracted artifact, path relative to the artifact directory
+pub struct ArtifactFile {
+    pub path: String,
+    pub content: Vec<u8>,
+}
+
+fn xml_error(content: &[u8]) -> Option<String> {
+    let mut reader = Reader::from_reader(content);
+    let mut buf = Vec::new();
+    let mut depth: usize = 0;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(_)) => depth += 1,
+            Ok(Event::End(_)) => depth = depth.saturating_sub(1),
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => {
+                return Some(format!(
+                    "not well-formed at byte {}: {}",
+                    reader.buffer_position(),
+                    e
+                ))
+            }
+        }
+        buf.clear();
+    }
+    if depth > 0 {
+        return Some(format!("not well-formed: {} unclosed element(s)", depth));
+    }
+    None
+}
+
+fn has_extension(path: &str, extensions: &[&str]) -> bool {
+    let path = path.to_lowercase();
+    extensions.iter().any(|e| path.ends_with(e))
+}
+
+// checks are pure over the extracted files, so they can run on anything that yields file contents
+pub fn check_artifact(
+    package_id: &str,
+    artifact_id: &str,
+    artifact_type: &str,
+    files: &[ArtifactFile],
+    checks: &ContentChecks,
+) -> Vec<ContentViolation> {
+    let violation = |check, file: Option<&str>, message: String| ContentViolation {
+        package_id: package_id.to_string(),
+        artifact_id: artifact_id.to_string(),
+        check,
+        file: file.map(|f| f.to_string()),
+        message,
+    };
+    let mut violations = Vec::new();
+
+    if checks.iflw_present
+        && artifact_type == "IntegrationDesigntimeArtifacts"
+        && !files.iter().any(|f| has_extension(&f.path, &[".iflw"]))
+    {
+        violations.push(violation(
+            CHECK_IFLW_PRESENT,
+            None,
+            "no .iflw file in the artifact".to_string(),
+        ));
+    }
+
+    for file in files {
+        if checks.xml_well_formed && has_extension(&file.path, &XML_EXTENSIONS) {
+            if let Some(message) = xml_error(&file.content) {
+                violations.push(violation(CHECK_XML_WELL_FORMED, Some(&file.path), message));
+            }
+        }
+        if checks.script_nul_bytes && has_extension(&file.path, &SCRIPT_EXTENSIONS) {
+            if let Some(offset) = file.content.iter().position(|b| *b == 0) {
+                violations.push(violation(
+                    CHECK_SCRIPT_NUL_BYTES,
+                    Some(&file.path),
+                    format!("NUL byte at offset {}", offset),
+                ));
+            }
+        }
+    }
+
+    violations
+}
+
+// the same entries that zip extraction writes, read from the downloaded archive
+pub(crate) fn archive_files(bytes: &[u8]) -> Result<Vec<ArtifactFile>, Error> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let path = file.name().to_string();
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        files.push(ArtifactFile { path, content });
+    }
+    Ok(files)
+}
+
+pub(crate) fn print_summary(violations: &[ContentViolation]) {
+    if violations.is_empty() {
+        log::info!("Content Validation: no violations");
+        return;
+    }
+    let mut text = format!("Content Validation: {} violation(s)", violations.len());
+    for v in violations {
+        match &v.file {
+            Some(file) => text.push_str(&format!(
+                "\n  {} {}/{} {}: {}",
+                v.check, v.package_id, v.artifact_id, file, v.message
+            )),
+            None => text.push_str(&format!(
+                "\n  {} {}/{}: {}",
+                v.check, v.package_id, v.artifact_id, v.message
+            )),
+        }
+    }
+    log::warn!("{}", text);
+}
@@ -0,0 +1,133 @@
+use crate::error::Error;
+use crate::{
+    authorization_header, check_api_url, get_all_packages, get_authorization, http, ArtifactKind,
+    Config,
+};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct ArtifactEntry {
+    #[serde(rename = "PackageId")]
+    package_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ArtifactEntryRoot {
+    d: ArtifactEntry,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    description: String,
+    url: String,
+}
+
+// the management API and the WebUI are served from the same host on both NEO (*-tmn) and CF tenants
+fn ui_base_url(config: &Config) -> String {
+    let host = config
+        .tenant
+        .ui_host
+        .as_deref()
+        .unwrap_or(&config.tenant.management_host);
+    format!("https://{}/itspaces/shell/design/contentpackage", host)
+}
+
+async fn find_artifact(
+    config: &Config,
+    client: &reqwest::Client,
+    authorization: &str,
+    kind: ArtifactKind,
+    id: &str,
+) -> Result<Option<String>, Error> {
+    let api_artifact_url = format!(
+        "{api}{entity_set}(Id='{id}',Version='active')",
+        api = check_api_url(config),
+        entity_set = kind.entity_set(),
+        id = id
+    );
+    let request = client
+        .get(&api_artifact_url)
+        .header(authorization_header(config), authorization)
+        .header("Accept", "application/json");
+    let resp = http::send(config, request).await?;
+
+    let resp_code = resp.status();
+    if resp_code == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let body_text = resp.text().await?;
+    if !resp_code.is_success() {
+        log::error!("Artifact Lookup Failed!");
+        log::error!("API URL: {}", &api_artifact_url);
+        log::error!("API Response Code: {:#?}", &resp_code);
+        crate::logging::response_body(&body_text);
+        return Err(Error::api_request(
+            "Artifact lookup",
+            &api_artifact_url,
+            resp_code,
+            &body_text,
+        ));
+    }
+
+    let entry: ArtifactEntryRoot = serde_json::from_str(&body_text)?;
+    Ok(entry.d.package_id)
+}
+
+pub async fn run_open(
+    config: &Config,
+    id: &str,
+    no_input: bool,
+    confirm_secret: bool,
+    print_only: bool,
+) -> Result<(), Error> {
+    let client = http::build_client(config)?;
+    let (config, authorization) =
+        get_authorization(config, &client, no_input, confirm_secret).await?;
+    let config = &config;
+    let base_url = ui_base_url(config);
+
+    let mut candidates = Vec::new();
+
+    let packages = get_all_packages(config, &client, &authorization, None).await?;
+    for p in packages.d.results.iter() {
+        if p.id == id || p.name == id {
+            candidates.push(Candidate {
+                description: format!("Package {} ({})", p.id, p.name),
+                url: format!("{}/{}", base_url, p.id),
+            });
+        }
+    }
+
+    let authorization = authorization.value().await?;
+    for kind in ArtifactKind::ALL.iter() {
+        if let Some(package_id) = find_artifact(config, &client, &authorization, *kind, id).await? {
+            candidates.push(Candidate {
+                description: format!("{} {} in Package {}", kind, id, package_id),
+                url: format!("{}/{}/{}/{}", base_url, package_id, kind.ui_page(), id),
+            });
+        }
+    }
+
+    match candidates.len() {
+        0 => Err(Error::NotFound(format!(
+            "No package or artifact found: {}",
+            id
+        ))),
+        1 => {
+            let candidate = &candidates[0];
+            println!("{}", candidate.description);
+            println!("{}", candidate.url);
+            if !print_only && !no_input {
+                open::that(&candidate.url)?;
+            }
+            Ok(())
+        }
+        _ => {
+            println!("Ambiguous id '{}', candidates:", id);
+            for candidate in &candidates {
+                println!("  {}: {}", candidate.description, candidate.url);
+            }
+            Err(Error::Selection(format!("Ambiguous id '{}'", id)))
+        }
+    }
+}
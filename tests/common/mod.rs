@@ -0,0 +1,250 @@
+// a tenant API on a wiremock server, reached through tenant.api_base_url. Every test file uses
+// a part of it.
+#![allow(dead_code)]
+
+use cpi_sync::{CancellationToken, Config, RunStats};
+use serde_json::{json, Value};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+pub const IFLOWS: &str = "IntegrationDesigntimeArtifacts";
+pub const SCRIPT_COLLECTIONS: &str = "ScriptCollectionDesigntimeArtifacts";
+
+pub struct MockTenant {
+    pub server: MockServer,
+}
+
+// OData list responses of the API
+pub fn results(entries: Vec<Value>) -> Value {
+    json!({ "d": { "results": entries } })
+}
+
+pub fn packages(ids: &[&str]) -> Value {
+    results(
+        ids.iter()
+            .map(|id| json!({ "Id": id, "Name": format!("{} Name", id), "Version": "1.0.0" }))
+            .collect(),
+    )
+}
+
+pub fn artifacts(ids: &[&str]) -> Value {
+    results(
+        ids.iter()
+            .map(|id| json!({ "Id": id, "Name": id, "Version": "1.0.0" }))
+            .collect(),
+    )
+}
+
+// the zip of an integration flow as $value returns it
+pub fn artifact_zip(artifact_id: &str) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default();
+    zip.start_file("META-INF/MANIFEST.MF", options).unwrap();
+    write!(
+        zip,
+        "Manifest-Version: 1.0\nBundle-Name: {}\nBundle-SymbolicName: {}\nBundle-Version: 1.0.0\n",
+        artifact_id, artifact_id
+    )
+    .unwrap();
+    zip.start_file(
+        format!(
+            "src/main/resources/scenarioflows/integrationflow/{}.iflw",
+            artifact_id
+        ),
+        options,
+    )
+    .unwrap();
+    write!(zip, "<definitions id=\"{}\"/>", artifact_id).unwrap();
+    zip.finish().unwrap().into_inner()
+}
+
+pub fn list_path(package_id: &str, entity_set: &str) -> String {
+    format!(
+        "/api/v1/IntegrationPackages('{}')/{}",
+        package_id, entity_set
+    )
+}
+
+pub fn payload_path(entity_set: &str, artifact_id: &str) -> String {
+    format!(
+        "/api/v1/{}(Id='{}',Version='Active')/$value",
+        entity_set, artifact_id
+    )
+}
+
+impl MockTenant {
+    // answers the API first check, everything else is mounted by the test
+    pub async fn start() -> MockTenant {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        MockTenant { server }
+    }
+
+    pub fn api_base_url(&self) -> String {
+        format!("{}/api/v1", self.server.uri())
+    }
+
+    pub async fn json(&self, url_path: &str, body: Value) {
+        Mock::given(method("GET"))
+            .and(path(url_path))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn packages(&self, ids: &[&str]) {
+        self.json("/api/v1/IntegrationPackages", packages(ids))
+            .await;
+    }
+
+    // the integration flows of a package
+    pub async fn iflows(&self, package_id: &str, ids: &[&str]) {
+        self.json(&list_path(package_id, IFLOWS), artifacts(ids))
+            .await;
+    }
+
+    pub async fn payload(&self, entity_set: &str, artifact_id: &str) {
+        Mock::given(method("GET"))
+            .and(path(payload_path(entity_set, artifact_id)))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Type", "application/zip")
+                    .set_body_bytes(artifact_zip(artifact_id)),
+            )
+            .mount(&self.server)
+            .await;
+    }
+
+    // one package with integration flows, listed and downloadable
+    pub async fn package_with_iflows(&self, package_id: &str, ids: &[&str]) {
+        self.iflows(package_id, ids).await;
+        for id in ids {
+            self.payload(IFLOWS, id).await;
+        }
+    }
+
+    pub async fn requests_to(&self, url_path: &str) -> Vec<wiremock::Request> {
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| r.url.path() == url_path)
+            .collect()
+    }
+}
+
+// a config file in its own folder, the packages are written next to it into "mirror"
+pub struct Workspace {
+    pub dir: tempfile::TempDir,
+}
+
+impl Workspace {
+    pub fn new() -> Workspace {
+        Workspace {
+            dir: tempfile::tempdir().unwrap(),
+        }
+    }
+
+    pub fn config_path(&self) -> String {
+        self.dir
+            .path()
+            .join("cpi-sync.json")
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn mirror(&self) -> PathBuf {
+        self.dir.path().join("mirror")
+    }
+
+    // a secret file for a credential, like a mounted secret
+    pub fn secret_file(&self, name: &str, secret: &str) -> String {
+        let path = self.dir.path().join(name);
+        std::fs::write(&path, secret).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    // an S-user config for the tenant, `packages` is merged over the defaults of the tests. Only
+    // integration flows are synced unless a test turns the other types on.
+    pub fn config(&self, tenant: &MockTenant, packages: Value) -> Config {
+        let credential = json!({
+            "s_user": {
+                "username": "S0001",
+                "password_file": self.secret_file("password", "secret")
+            }
+        });
+        self.config_with_credential(tenant, credential, packages)
+    }
+
+    pub fn config_with_credential(
+        &self,
+        tenant: &MockTenant,
+        credential: Value,
+        packages: Value,
+    ) -> Config {
+        let mut config_packages = json!({
+            "local_dir": "mirror",
+            "filter_rules": [{ "type": "regex", "pattern": ".*", "operation": "include" }],
+            "include_value_mappings": false,
+            "include_script_collections": false,
+            "include_message_mappings": false,
+            "include_configurations": false
+        });
+        for (key, value) in packages.as_object().unwrap() {
+            config_packages[key] = value.clone();
+        }
+        serde_json::from_value(json!({
+            "cpisync": cpi_sync::CONFIG_VERSION,
+            "tenant": {
+                "management_host": "tenant.example.com",
+                "api_base_url": tenant.api_base_url(),
+                "credential": credential
+            },
+            "packages": config_packages
+        }))
+        .unwrap()
+    }
+
+    pub fn exists(&self, relative: &str) -> bool {
+        self.mirror().join(relative).exists()
+    }
+
+    pub fn read_json(&self, relative: &str) -> Value {
+        let content = std::fs::read(self.mirror().join(relative)).unwrap();
+        serde_json::from_slice(&content).unwrap()
+    }
+}
+
+// a sync without prompts like `cpisync --no-input`
+pub async fn sync(config: &Config, workspace: &Workspace) -> Result<RunStats, cpi_sync::Error> {
+    sync_with(config, workspace, None, &CancellationToken::new()).await
+}
+
+pub async fn sync_with(
+    config: &Config,
+    workspace: &Workspace,
+    report: Option<&Path>,
+    cancel: &CancellationToken,
+) -> Result<RunStats, cpi_sync::Error> {
+    cpi_sync::run_with_config(
+        config,
+        &workspace.config_path(),
+        true,
+        false,
+        false,
+        false,
+        None,
+        false,
+        None,
+        report,
+        cancel,
+    )
+    .await
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use common::{MockTenant, Workspace};
+use serde_json::json;
+
+// synth-481: deployed artifacts no selected package lists are shown, but aren't differences
+#[tokio::test]
+async fn diff_with_deploy_status_lists_the_runtime() {
+    let tenant = MockTenant::start().await;
+    tenant.packages(&["PkgA"]).await;
+    tenant.package_with_iflows("PkgA", &["Flow"]).await;
+    tenant
+        .json(
+            "/api/v1/IntegrationRuntimeArtifacts",
+            common::results(vec![
+                json!({ "Id": "Flow", "Version": "1.0.0", "Type": "INTEGRATION_FLOW" }),
+                json!({ "Id": "Direct", "Version": "1.0.0", "Type": "INTEGRATION_FLOW" }),
+            ]),
+        )
+        .await;
+    let workspace = Workspace::new();
+    let config = workspace.config(&tenant, json!({}));
+    common::sync(&config, &workspace).await.unwrap();
+
+    cpi_sync::run_diff(&config, &workspace.config_path(), true, false, true)
+        .await
+        .unwrap();
+
+    let runtime = tenant
+        .requests_to("/api/v1/IntegrationRuntimeArtifacts")
+        .await;
+    assert_eq!(runtime.len(), 1);
+}
+
+// without the flag the runtime isn't listed
+#[tokio::test]
+async fn diff_without_deploy_status_skips_the_runtime() {
+    let tenant = MockTenant::start().await;
+    tenant.packages(&["PkgA"]).await;
+    tenant.package_with_iflows("PkgA", &["Flow"]).await;
+    let workspace = Workspace::new();
+    let config = workspace.config(&tenant, json!({}));
+    common::sync(&config, &workspace).await.unwrap();
+
+    cpi_sync::run_diff(&config, &workspace.config_path(), true, false, false)
+        .await
+        .unwrap();
+
+    assert!(tenant
+        .requests_to("/api/v1/IntegrationRuntimeArtifacts")
+        .await
+        .is_empty());
+}
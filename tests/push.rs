@@ -0,0 +1,89 @@
+mod common;
+
+use common::{MockTenant, Workspace};
+use serde_json::json;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const UPLOAD_PATH: &str = "/api/v1/IntegrationDesigntimeArtifacts";
+
+fn lookup_path(artifact_id: &str) -> String {
+    format!("{}(Id='{}',Version='active')", UPLOAD_PATH, artifact_id)
+}
+
+// an integration flow folder as a sync extracts it
+fn local_iflow(workspace: &Workspace, package_id: &str, artifact_id: &str) {
+    let dir = workspace
+        .mirror()
+        .join(package_id)
+        .join(artifact_id)
+        .join("src/main/resources/scenarioflows/integrationflow");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join(format!("{}.iflw", artifact_id)),
+        format!("<definitions id=\"{}\"/>", artifact_id),
+    )
+    .unwrap();
+}
+
+async fn push(workspace: &Workspace, config: &cpi_sync::Config) -> Result<(), cpi_sync::Error> {
+    cpi_sync::run_push(config, &workspace.config_path(), true, false, false, None).await
+}
+
+// a create that failed with a 5xx may still have been stored, it is not sent a second time
+#[tokio::test]
+async fn failed_upload_is_sent_once() {
+    let tenant = MockTenant::start().await;
+    tenant.packages(&["PkgA"]).await;
+    Mock::given(method("POST"))
+        .and(path(UPLOAD_PATH))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&tenant.server)
+        .await;
+    let workspace = Workspace::new();
+    local_iflow(&workspace, "PkgA", "Flow");
+    let config = workspace.config(&tenant, json!({}));
+
+    let err = push(&workspace, &config).await.unwrap_err();
+
+    assert_eq!(err.to_string(), "Push Failed!");
+    // the lookup answered 404, so it was a create
+    assert_eq!(tenant.requests_to(&lookup_path("Flow")).await.len(), 1);
+    tenant.server.verify().await;
+}
+
+// an existing flow is replaced with a PUT that carries the CSRF token of the session
+#[tokio::test]
+async fn existing_flow_is_updated_with_the_csrf_token() {
+    let tenant = MockTenant::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/"))
+        .and(header("X-CSRF-Token", "Fetch"))
+        .respond_with(ResponseTemplate::new(200).insert_header("X-CSRF-Token", "token-1"))
+        .with_priority(1)
+        .mount(&tenant.server)
+        .await;
+    tenant.packages(&["PkgA"]).await;
+    tenant
+        .json(
+            &lookup_path("Flow"),
+            json!({ "d": { "Id": "Flow", "Version": "1.0.3" } }),
+        )
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(lookup_path("Flow")))
+        .and(header("X-CSRF-Token", "token-1"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&tenant.server)
+        .await;
+    let workspace = Workspace::new();
+    local_iflow(&workspace, "PkgA", "Flow");
+    let config = workspace.config(&tenant, json!({}));
+
+    push(&workspace, &config).await.unwrap();
+
+    assert!(tenant.requests_to(UPLOAD_PATH).await.is_empty());
+    tenant.server.verify().await;
+}
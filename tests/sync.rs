@@ -0,0 +1,388 @@
+mod common;
+
+use common::{MockTenant, Workspace, IFLOWS, SCRIPT_COLLECTIONS};
+use cpi_sync::CancellationToken;
+use serde_json::json;
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const FLOW_FILE: &str = "src/main/resources/scenarioflows/integrationflow";
+
+#[tokio::test]
+async fn package_list_artifact_list_and_download() {
+    let tenant = MockTenant::start().await;
+    tenant.packages(&["PkgA", "PkgB"]).await;
+    tenant
+        .package_with_iflows("PkgA", &["FlowA1", "FlowA2"])
+        .await;
+    tenant.package_with_iflows("PkgB", &["FlowB1"]).await;
+    let workspace = Workspace::new();
+    let config = workspace.config(&tenant, json!({ "manifest": true }));
+
+    let stats = common::sync(&config, &workspace).await.unwrap();
+
+    assert_eq!(stats.packages_selected, 2);
+    assert_eq!(stats.artifacts_listed, 3);
+    assert_eq!(stats.artifacts_downloaded, 3);
+    for (package_id, artifact_id) in &[("PkgA", "FlowA1"), ("PkgA", "FlowA2"), ("PkgB", "FlowB1")] {
+        let flow = format!(
+            "{}/{}/{}/{}.iflw",
+            package_id, artifact_id, FLOW_FILE, artifact_id
+        );
+        assert!(workspace.exists(&flow), "{} is missing", flow);
+    }
+    let manifest = workspace.read_json("manifest.json");
+    let paths: Vec<&str> = manifest["artifacts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|a| a["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(paths, vec!["PkgA/FlowA1", "PkgA/FlowA2", "PkgB/FlowB1"]);
+}
+
+// synth-477: a gateway error page with status 200 is not saved as the artifact, the error shows
+// the start of the page
+#[tokio::test]
+async fn html_page_with_200_is_refused() {
+    let tenant = MockTenant::start().await;
+    tenant.packages(&["PkgA"]).await;
+    tenant.iflows("PkgA", &["Flow"]).await;
+    let page = format!(
+        "<html><body>Gateway error, Authorization: Bearer abcdefgh12345\n{}</body></html>",
+        "x".repeat(1000)
+    );
+    Mock::given(method("GET"))
+        .and(path(common::payload_path(IFLOWS, "Flow")))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(page, "text/html"))
+        .mount(&tenant.server)
+        .await;
+    let workspace = Workspace::new();
+    let config = workspace.config(&tenant, json!({}));
+
+    let err = common::sync(&config, &workspace).await.unwrap_err();
+
+    assert_eq!(cpi_sync::ExitCode::of(&err), cpi_sync::ExitCode::Download);
+    let reason = match err {
+        cpi_sync::Error::Download(failure) => match *failure {
+            cpi_sync::Error::ApiResponse { reason, .. } => reason,
+            other => panic!("expected an unexpected response error, got {:?}", other),
+        },
+        other => panic!("expected a download error, got {:?}", other),
+    };
+    assert!(reason.contains("not a zip file"), "{}", reason);
+    assert!(reason.contains("text/html"), "{}", reason);
+    assert!(reason.contains("<html><body>Gateway error"), "{}", reason);
+    // the token the page echoes is redacted, the page is cut after 500 characters
+    assert!(
+        reason.contains("Authorization: \"<redacted>\""),
+        "{}",
+        reason
+    );
+    assert!(!reason.contains("abcdefgh12345"), "{}", reason);
+    let body = reason.split_once("): ").unwrap().1;
+    assert_eq!(body.chars().count(), 500);
+    assert!(!workspace.exists("PkgA/Flow"));
+    assert!(!workspace.exists("PkgA/Flow.zip"));
+}
+
+// synth-504: cancelling while a download hangs returns Cancelled with the partial stats
+#[tokio::test]
+async fn cancel_a_slow_sync() {
+    let tenant = MockTenant::start().await;
+    tenant.packages(&["PkgA"]).await;
+    tenant.iflows("PkgA", &["Slow"]).await;
+    Mock::given(method("GET"))
+        .and(path(common::payload_path(IFLOWS, "Slow")))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(common::artifact_zip("Slow"))
+                .set_delay(Duration::from_secs(30)),
+        )
+        .mount(&tenant.server)
+        .await;
+    let workspace = Workspace::new();
+    let config = workspace.config(&tenant, json!({}));
+    let cancel = CancellationToken::new();
+    let canceller = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        canceller.cancel();
+    });
+
+    let started = std::time::Instant::now();
+    let err = common::sync_with(&config, &workspace, None, &cancel)
+        .await
+        .unwrap_err();
+
+    assert!(started.elapsed() < Duration::from_secs(10));
+    let cancelled = match err {
+        cpi_sync::Error::Cancelled(cancelled) => cancelled,
+        other => panic!("expected Cancelled, got {}", other),
+    };
+    assert_eq!(cancelled.stats.packages_selected, 1);
+    assert_eq!(cancelled.stats.artifacts_downloaded, 0);
+    assert!(!workspace.exists("PkgA/Slow/META-INF/MANIFEST.MF"));
+}
+
+// synth-507: two mirrors synced from the same tenant state have the same hashes
+#[tokio::test]
+async fn two_mirrors_of_the_same_tenant_hash_the_same() {
+    let tenant = MockTenant::start().await;
+    tenant.packages(&["PkgA", "PkgB"]).await;
+    tenant.package_with_iflows("PkgA", &["FlowA"]).await;
+    tenant.package_with_iflows("PkgB", &["FlowB"]).await;
+    let first = Workspace::new();
+    let second = Workspace::new();
+    common::sync(&first.config(&tenant, json!({})), &first)
+        .await
+        .unwrap();
+    common::sync(&second.config(&tenant, json!({})), &second)
+        .await
+        .unwrap();
+
+    let first_hash = cpi_sync::hash_mirror(&first.mirror()).unwrap();
+    let second_hash = cpi_sync::hash_mirror(&second.mirror()).unwrap();
+    assert_eq!(first_hash.root, second_hash.root);
+    assert_eq!(first_hash.packages.len(), 2);
+    for (a, b) in first_hash.packages.iter().zip(second_hash.packages.iter()) {
+        assert_eq!(a.package_id, b.package_id);
+        assert_eq!(a.hash, b.hash);
+    }
+}
+
+// synth-510: the timings count exactly the requests the tenant received
+#[tokio::test]
+async fn timings_count_the_mocked_requests() {
+    let tenant = MockTenant::start().await;
+    tenant.packages(&["PkgA", "PkgB"]).await;
+    tenant
+        .package_with_iflows("PkgA", &["FlowA1", "FlowA2"])
+        .await;
+    tenant.package_with_iflows("PkgB", &["FlowB1"]).await;
+    let workspace = Workspace::new();
+    let config = workspace.config(&tenant, json!({}));
+
+    let stats = common::sync(&config, &workspace).await.unwrap();
+
+    let requests = |endpoint: &str| {
+        stats
+            .timings
+            .iter()
+            .find(|t| t.endpoint == endpoint)
+            .map_or(0, |t| t.requests)
+    };
+    // the credential check of the S-user isn't timed, the API first check of the sync is
+    assert_eq!(requests("api check"), 1);
+    assert_eq!(requests("package list"), 1);
+    assert_eq!(requests(&format!("artifact list {}", IFLOWS)), 2);
+    assert_eq!(requests(&format!("artifact payload {}", IFLOWS)), 3);
+    assert!(stats.timings.iter().all(|t| t.errors == 0));
+    let timed: usize = stats.timings.iter().map(|t| t.requests).sum();
+    let received = tenant.server.received_requests().await.unwrap().len();
+    assert_eq!(timed, received);
+}
+
+// synth-512: an id listed in another casing than it is stored is downloaded with the stored one
+#[tokio::test]
+async fn listed_id_casing_is_retried_with_the_stored_id() {
+    let tenant = MockTenant::start().await;
+    tenant.packages(&["PkgA"]).await;
+    // the first listing has the casing of the copy, the check after the 404 the stored one
+    Mock::given(method("GET"))
+        .and(path(common::list_path("PkgA", IFLOWS)))
+        .respond_with(ResponseTemplate::new(200).set_body_json(common::artifacts(&["MyFlow"])))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&tenant.server)
+        .await;
+    tenant.iflows("PkgA", &["MYFLOW"]).await;
+    Mock::given(method("GET"))
+        .and(path(common::payload_path(IFLOWS, "MyFlow")))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&tenant.server)
+        .await;
+    tenant.payload(IFLOWS, "MYFLOW").await;
+    let workspace = Workspace::new();
+    let config = workspace.config(&tenant, json!({ "manifest": true }));
+
+    let stats = common::sync(&config, &workspace).await.unwrap();
+
+    assert_eq!(stats.artifacts_downloaded, 1);
+    assert!(workspace.exists(&format!("PkgA/MyFlow/{}/MYFLOW.iflw", FLOW_FILE)));
+    let manifest = workspace.read_json("manifest.json");
+    let entry = &manifest["artifacts"][0];
+    assert_eq!(entry["artifact_id"], "MyFlow");
+    assert_eq!(entry["stored_id"], "MYFLOW");
+    assert_eq!(
+        tenant
+            .requests_to(&common::payload_path(IFLOWS, "MyFlow"))
+            .await
+            .len(),
+        1
+    );
+}
+
+// synth-513: a package created while the sync runs is picked up by the second listing
+#[tokio::test]
+async fn package_added_mid_run_is_synced() {
+    let tenant = MockTenant::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/IntegrationPackages"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(common::packages(&["PkgA"])))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&tenant.server)
+        .await;
+    tenant.packages(&["PkgA", "PkgNew"]).await;
+    tenant.package_with_iflows("PkgA", &["FlowA"]).await;
+    tenant.package_with_iflows("PkgNew", &["FlowNew"]).await;
+    let workspace = Workspace::new();
+    let config = workspace.config(&tenant, json!({ "refresh_listing_at_end": true }));
+
+    let stats = common::sync(&config, &workspace).await.unwrap();
+
+    // the late package counts as selected as well
+    assert_eq!(stats.packages_selected, 2);
+    assert_eq!(stats.late_additions, vec!["PkgNew"]);
+    assert_eq!(stats.artifacts_downloaded, 2);
+    assert!(workspace.exists(&format!("PkgNew/FlowNew/{}/FlowNew.iflw", FLOW_FILE)));
+    assert_eq!(
+        tenant
+            .requests_to("/api/v1/IntegrationPackages")
+            .await
+            .len(),
+        2
+    );
+}
+
+// synth-518: an integration flow and a script collection with the same id get their own folders
+#[tokio::test]
+async fn same_id_of_two_types_is_kept_apart() {
+    let tenant = MockTenant::start().await;
+    tenant.packages(&["PkgA"]).await;
+    tenant.package_with_iflows("PkgA", &["Shared"]).await;
+    tenant
+        .json(
+            &common::list_path("PkgA", SCRIPT_COLLECTIONS),
+            common::artifacts(&["Shared"]),
+        )
+        .await;
+    tenant.payload(SCRIPT_COLLECTIONS, "Shared").await;
+    let workspace = Workspace::new();
+    let config = workspace.config(
+        &tenant,
+        json!({ "include_script_collections": true, "manifest": true }),
+    );
+
+    let stats = common::sync(&config, &workspace).await.unwrap();
+
+    assert_eq!(stats.artifacts_downloaded, 2);
+    assert!(workspace.exists("PkgA/Shared/META-INF/MANIFEST.MF"));
+    assert!(workspace.exists("PkgA/Shared.script_collection/META-INF/MANIFEST.MF"));
+    let manifest = workspace.read_json("manifest.json");
+    let mut entries: Vec<(String, String)> = manifest["artifacts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|a| {
+            (
+                a["path"].as_str().unwrap().to_string(),
+                a["artifact_type"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    entries.sort();
+    assert_eq!(
+        entries,
+        vec![
+            ("PkgA/Shared".to_string(), IFLOWS.to_string()),
+            (
+                "PkgA/Shared.script_collection".to_string(),
+                SCRIPT_COLLECTIONS.to_string()
+            ),
+        ]
+    );
+}
+
+// synth-524: two tenants synced at the same time only write their own packages and reports
+#[tokio::test]
+async fn two_tenants_are_kept_apart() {
+    let dev = MockTenant::start().await;
+    dev.packages(&["DevPkg"]).await;
+    dev.package_with_iflows("DevPkg", &["DevFlow"]).await;
+    let prod = MockTenant::start().await;
+    prod.packages(&["ProdPkg"]).await;
+    prod.package_with_iflows("ProdPkg", &["ProdFlow"]).await;
+    let dev_workspace = Workspace::new();
+    let prod_workspace = Workspace::new();
+    let dev_config = dev_workspace.config(&dev, json!({}));
+    let prod_config = prod_workspace.config(&prod, json!({}));
+    let dev_report = dev_workspace.dir.path().join("report.json");
+    let prod_report = prod_workspace.dir.path().join("report.json");
+    let cancel = CancellationToken::new();
+
+    let (dev_result, prod_result) = tokio::join!(
+        common::sync_with(&dev_config, &dev_workspace, Some(&dev_report), &cancel),
+        common::sync_with(&prod_config, &prod_workspace, Some(&prod_report), &cancel),
+    );
+    dev_result.unwrap();
+    prod_result.unwrap();
+
+    assert!(dev_workspace.exists("DevPkg/DevFlow"));
+    assert!(!dev_workspace.exists("ProdPkg"));
+    assert!(prod_workspace.exists("ProdPkg/ProdFlow"));
+    assert!(!prod_workspace.exists("DevPkg"));
+    for (report, package_id) in &[(&dev_report, "DevPkg"), (&prod_report, "ProdPkg")] {
+        let report: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(report).unwrap()).unwrap();
+        let packages: Vec<&str> = report["packages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["package_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(packages, vec![*package_id]);
+    }
+    // neither server saw a request meant for the other tenant
+    for (tenant, other) in &[(&dev, "ProdPkg"), (&prod, "DevPkg")] {
+        let requests = tenant.server.received_requests().await.unwrap();
+        assert!(requests.iter().all(|r| !r.url.path().contains(other)));
+    }
+}
+
+// synth-519: the sync replaces the artifacts of a package folder, the user's own files stay
+#[tokio::test]
+async fn user_files_in_a_package_folder_are_kept() {
+    let tenant = MockTenant::start().await;
+    tenant.packages(&["PkgA"]).await;
+    // the second sync lists the package without the flow deleted on the tenant in between
+    Mock::given(method("GET"))
+        .and(path(common::list_path("PkgA", IFLOWS)))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(common::artifacts(&["Flow", "Deleted"])),
+        )
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&tenant.server)
+        .await;
+    tenant.package_with_iflows("PkgA", &["Flow"]).await;
+    tenant.payload(IFLOWS, "Deleted").await;
+    let workspace = Workspace::new();
+    let config = workspace.config(&tenant, json!({}));
+    common::sync(&config, &workspace).await.unwrap();
+    assert!(workspace.exists("PkgA/Deleted"));
+    std::fs::create_dir(workspace.mirror().join("PkgA/docs")).unwrap();
+    std::fs::write(workspace.mirror().join("PkgA/docs/notes.md"), "mine").unwrap();
+
+    let stats = common::sync(&config, &workspace).await.unwrap();
+
+    assert_eq!(stats.artifacts_downloaded, 1);
+    assert!(workspace.exists(&format!("PkgA/Flow/{}/Flow.iflw", FLOW_FILE)));
+    assert!(!workspace.exists("PkgA/Deleted"));
+    assert_eq!(
+        std::fs::read_to_string(workspace.mirror().join("PkgA/docs/notes.md")).unwrap(),
+        "mine"
+    );
+}